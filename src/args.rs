@@ -1,19 +1,358 @@
-#[derive(Debug, PartialEq, Eq)]
+use completions::Shell;
+use config;
+use output::Verbosity;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ReportFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+// Partial modes are for scripted workflows, i.e. push sources, run a manual step, pull results later.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum RunMode {
+    #[default]
+    Full,
+    UploadOnly,
+    DownloadOnly,
+    // Both syncs without remote command, i.e. seed a remote mirror or pull results of a manual step.
+    NoExecute,
+}
+
+// Names from config printed one per line, i.e. for shell completion of "--profile" and "--machine".
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Listing {
+    Profiles,
+    RemoteMachines,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Phase {
+    Upload,
+    Execute,
+    Download,
+}
+
+impl RunMode {
+    pub fn phases(self) -> &'static [Phase] {
+        match self {
+            RunMode::Full => &[Phase::Upload, Phase::Execute, Phase::Download],
+            RunMode::UploadOnly => &[Phase::Upload],
+            RunMode::DownloadOnly => &[Phase::Download],
+            RunMode::NoExecute => &[Phase::Upload, Phase::Download],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Default)]
 pub struct Args {
-    pub command: String
+    // Run one after another, the one after options is the last.
+    pub commands: Vec<String>,
+    // Local script run after the commands.
+    pub command_file: Option<String>,
+    pub remote_machine: Option<String>,
+    pub profile: Option<String>,
+    pub dry_run: bool,
+    pub timeout_seconds: Option<u32>,
+    pub report_format: ReportFormat,
+    pub verbosity: Verbosity,
+    pub excludes: Vec<String>,
+    // Layered in order, later ones win, "-" is stdin.
+    pub config_paths: Vec<String>,
+    pub keep_going: bool,
+    pub tty: bool,
+    pub no_color: bool,
+    pub stats: bool,
+    pub delete: bool,
+    // Stream NDJSON progress events instead of human output.
+    pub machine_readable_progress: bool,
+    // Override compression levels from config for a single run.
+    pub local_compression_level: Option<u8>,
+    pub remote_compression_level: Option<u8>,
+    // Run again every time local files change.
+    pub watch: bool,
+    // Run command on local machine without syncing, e.g. while offline.
+    pub local: bool,
+    pub run_mode: RunMode,
+    // Upload only files changed since the last upload, "full" forces a full one anyway.
+    pub since: bool,
+    pub full: bool,
+    // Transfer every file as on the first upload, i.e. if remote copy is suspected to be corrupted.
+    pub force_full_upload: bool,
+    // Print resolved config instead of running a command.
+    pub explain: bool,
+    // Resolve config and report errors of it instead of running a command, nothing connects to remote machine.
+    pub config_check: bool,
+    // Print names from config instead of running a command.
+    pub list: Option<Listing>,
+    // Print completion script for the shell instead of running a command.
+    pub completions: Option<Shell>,
+    // Scaffold config instead of running a command.
+    pub init: bool,
+    pub force: bool,
 }
 
 impl Args {
+    // Options are only recognized before the command, the rest is passed to remote machine as is.
     pub fn parse(raw_args: &[String]) -> Result<Args, String> {
-        match raw_args.len() {
-            0 => Err(String::from("Please pass remote command.")), // TODO more user friendly message, for now it's consistent with Bash version.
+        let mut remote_machine = None;
+        let mut profile = None;
+        let mut dry_run = false;
+        let mut timeout_seconds = None;
+        let mut report_format = ReportFormat::Human;
+        let mut verbosity = Verbosity::Normal;
+        let mut excludes = vec![];
+        let mut config_paths = vec![];
+        let mut keep_going = false;
+        let mut tty = false;
+        let mut no_color = false;
+        let mut stats = false;
+        let mut delete = false;
+        let mut machine_readable_progress = false;
+        let mut local_compression_level = None;
+        let mut remote_compression_level = None;
+        let mut watch = false;
+        let mut local = false;
+        let mut run_mode = RunMode::Full;
+        let mut since = false;
+        let mut full = false;
+        let mut force_full_upload = false;
+        let mut explain = false;
+        let mut config_check = false;
+        let mut list = None;
+        let mut completions = None;
+        let mut init = false;
+        let mut force = false;
+        let mut commands = vec![];
+        let mut command_file = None;
+        let mut index = 0;
+
+        while index < raw_args.len() {
+            match raw_args[index].as_ref() {
+                "--" => {
+                    index += 1;
+                    break;
+                }
+                "--machine" => {
+                    remote_machine = Some(option_value(raw_args, index)?);
+                    index += 2;
+                }
+                "--profile" => {
+                    profile = Some(option_value(raw_args, index)?);
+                    index += 2;
+                }
+                "--dry-run" => {
+                    dry_run = true;
+                    index += 1;
+                }
+                "--timeout" => {
+                    let value = option_value(raw_args, index)?;
+
+                    timeout_seconds = match value.parse() {
+                        Ok(seconds) if seconds > 0 => Some(seconds),
+                        _ => return Err(format!("Please pass positive number of seconds for '--timeout', found '{}'.", value))
+                    };
+                    index += 2;
+                }
+                "--report-format" => {
+                    report_format = match option_value(raw_args, index)?.as_ref() {
+                        "human" => ReportFormat::Human,
+                        "json" => ReportFormat::Json,
+                        value => return Err(format!("Please pass either 'human' or 'json' for '--report-format', found '{}'.", value))
+                    };
+                    index += 2;
+                }
+                "--exclude" => {
+                    excludes.push(option_value(raw_args, index)?);
+                    index += 2;
+                }
+                "--config" => {
+                    let config_path = option_value(raw_args, index)?;
+
+                    // Stdin is empty once it's read.
+                    if config_path == "-" && config_paths.contains(&config_path) {
+                        return Err(String::from("Please pass '-' to '--config' only once."));
+                    }
+
+                    config_paths.push(config_path);
+                    index += 2;
+                }
+                "--command" => {
+                    commands.push(option_value(raw_args, index)?);
+                    index += 2;
+                }
+                "--command-file" => {
+                    command_file = Some(option_value(raw_args, index)?);
+                    index += 2;
+                }
+                "--keep-going" => {
+                    keep_going = true;
+                    index += 1;
+                }
+                "--tty" => {
+                    tty = true;
+                    index += 1;
+                }
+                "--no-color" => {
+                    no_color = true;
+                    index += 1;
+                }
+                "--stats" => {
+                    stats = true;
+                    index += 1;
+                }
+                "--delete" => {
+                    delete = true;
+                    index += 1;
+                }
+                "--machine-readable-progress" => {
+                    machine_readable_progress = true;
+                    index += 1;
+                }
+                "--compression-local" => {
+                    local_compression_level = Some(config::validate_compression_level("--compression-local", &option_value(raw_args, index)?).map_err(|error| error.to_string())?);
+                    index += 2;
+                }
+                "--compression-remote" => {
+                    remote_compression_level = Some(config::validate_compression_level("--compression-remote", &option_value(raw_args, index)?).map_err(|error| error.to_string())?);
+                    index += 2;
+                }
+                "--watch" => {
+                    watch = true;
+                    index += 1;
+                }
+                "--local" => {
+                    local = true;
+                    index += 1;
+                }
+                "--upload-only" | "--download-only" | "--no-execute" => {
+                    let mode = match raw_args[index].as_str() {
+                        "--upload-only" => RunMode::UploadOnly,
+                        "--download-only" => RunMode::DownloadOnly,
+                        _ => RunMode::NoExecute,
+                    };
+
+                    if run_mode != RunMode::Full && run_mode != mode {
+                        return Err(String::from("Please pass only one of '--upload-only', '--download-only' and '--no-execute'."));
+                    }
+
+                    run_mode = mode;
+                    index += 1;
+                }
+                "--since" => {
+                    since = true;
+                    index += 1;
+                }
+                "--full" => {
+                    full = true;
+                    index += 1;
+                }
+                "--force-full-upload" => {
+                    force_full_upload = true;
+                    index += 1;
+                }
+                "--explain" => {
+                    explain = true;
+                    index += 1;
+                }
+                "--config-check" => {
+                    config_check = true;
+                    index += 1;
+                }
+                "--list-profiles" | "--list-machines" => {
+                    let listing = if raw_args[index] == "--list-profiles" { Listing::Profiles } else { Listing::RemoteMachines };
+
+                    if list.is_some() && list != Some(listing) {
+                        return Err(String::from("Please pass either '--list-profiles' or '--list-machines', not both."));
+                    }
+
+                    list = Some(listing);
+                    index += 1;
+                }
+                "--completions" => {
+                    let shell = option_value(raw_args, index)?;
+                    completions = match Shell::parse(&shell) {
+                        None => return Err(format!("Unknown shell '{}', please pass one of 'bash', 'zsh' and 'fish'.", shell)),
+                        shell => shell
+                    };
+                    index += 2;
+                }
+                "--init" => {
+                    init = true;
+                    index += 1;
+                }
+                "--force" => {
+                    force = true;
+                    index += 1;
+                }
+                "--quiet" | "-q" | "--verbose" | "-v" => {
+                    let value = if raw_args[index] == "--quiet" || raw_args[index] == "-q" { Verbosity::Quiet } else { Verbosity::Verbose };
+
+                    if verbosity != Verbosity::Normal && verbosity != value {
+                        return Err(String::from("Please pass either '--quiet' or '--verbose', not both."));
+                    }
+
+                    verbosity = value;
+                    index += 1;
+                }
+                _ => break
+            }
+        }
+
+        if index < raw_args.len() {
+            commands.push(raw_args[index..].join(" ").trim().into());
+        }
+
+        if local && run_mode != RunMode::Full {
+            return Err(String::from("Please pass either '--local' or partial run mode, '--local' doesn't sync anything."));
+        }
+
+        match commands.len() {
+            0 if !init && !explain && !config_check && list.is_none() && completions.is_none() && command_file.is_none() && run_mode == RunMode::Full => Err(String::from("Please pass remote command.")), // TODO more user friendly message, for now it's consistent with Bash version.
             _ => Ok(Args {
-                command: raw_args.join(" ").trim().into()
+                commands,
+                command_file,
+                remote_machine,
+                profile,
+                dry_run,
+                timeout_seconds,
+                report_format,
+                verbosity,
+                excludes,
+                config_paths,
+                keep_going,
+                tty,
+                no_color,
+                stats,
+                delete,
+                machine_readable_progress,
+                local_compression_level,
+                remote_compression_level,
+                watch,
+                local,
+                run_mode,
+                since,
+                full,
+                force_full_upload,
+                explain,
+                config_check,
+                list,
+                completions,
+                init,
+                force,
             })
         }
     }
 }
 
+fn option_value(raw_args: &[String], option_index: usize) -> Result<String, String> {
+    match raw_args.get(option_index + 1) {
+        None => Err(format!("Please pass value for '{}'.", raw_args[option_index])),
+        Some(value) => Ok(value.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,7 +360,10 @@ mod tests {
     #[test]
     fn parse_command_passed_as_single_parameter() {
         let raw_args = vec![String::from("test command")];
-        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args { command: String::from("test command") }));
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            ..Args::default()
+        }));
     }
 
     #[test]
@@ -33,6 +375,465 @@ mod tests {
     #[test]
     fn parse_command_passed_as_multiple_parameters() {
         let raw_args = vec![String::from("test"), String::from("command")];
-        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args { command: String::from("test command") }));
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_machine() {
+        let raw_args = vec![String::from("--machine"), String::from("build"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            remote_machine: Some(String::from("build")),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_machine_without_value() {
+        let raw_args = vec![String::from("--machine")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass value for '--machine'.")));
+    }
+
+    #[test]
+    fn parse_machine_without_command() {
+        let raw_args = vec![String::from("--machine"), String::from("build")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass remote command.")));
+    }
+
+    #[test]
+    fn parse_option_after_command_is_part_of_command() {
+        let raw_args = vec![String::from("test"), String::from("--machine"), String::from("build")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test --machine build")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_command_after_separator() {
+        let raw_args = vec![String::from("--"), String::from("--machine"), String::from("build")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("--machine build")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_profile() {
+        let raw_args = vec![String::from("--profile"), String::from("release"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            profile: Some(String::from("release")),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_dry_run() {
+        let raw_args = vec![String::from("--dry-run"), String::from("--machine"), String::from("build"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            remote_machine: Some(String::from("build")),
+            dry_run: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_timeout() {
+        let raw_args = vec![String::from("--timeout"), String::from("60"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            timeout_seconds: Some(60),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_timeout_zero() {
+        let raw_args = vec![String::from("--timeout"), String::from("0"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass positive number of seconds for '--timeout', found '0'.")));
+    }
+
+    #[test]
+    fn parse_timeout_not_a_number() {
+        let raw_args = vec![String::from("--timeout"), String::from("soon"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass positive number of seconds for '--timeout', found 'soon'.")));
+    }
+
+    #[test]
+    fn parse_report_format_json() {
+        let raw_args = vec![String::from("--report-format"), String::from("json"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            report_format: ReportFormat::Json,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_report_format_unknown() {
+        let raw_args = vec![String::from("--report-format"), String::from("xml"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass either 'human' or 'json' for '--report-format', found 'xml'.")));
+    }
+
+    #[test]
+    fn parse_quiet() {
+        let raw_args = vec![String::from("--quiet"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            verbosity: Verbosity::Quiet,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_quiet_short() {
+        let raw_args = vec![String::from("-q"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()).map(|args| args.verbosity), Ok(Verbosity::Quiet));
+    }
+
+    #[test]
+    fn parse_verbose() {
+        let raw_args = vec![String::from("-v"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()).map(|args| args.verbosity), Ok(Verbosity::Verbose));
+    }
+
+    #[test]
+    fn parse_quiet_and_verbose() {
+        let raw_args = vec![String::from("--quiet"), String::from("--verbose"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass either '--quiet' or '--verbose', not both.")));
+    }
+
+    #[test]
+    fn parse_exclude_several_times() {
+        let raw_args = vec![
+            String::from("--exclude"), String::from("*.log"),
+            String::from("--exclude"), String::from("tmp/"),
+            String::from("test command"),
+        ];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            excludes: vec![String::from("*.log"), String::from("tmp/")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_exclude_without_value() {
+        let raw_args = vec![String::from("--exclude")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass value for '--exclude'.")));
+    }
+
+    #[test]
+    fn parse_config() {
+        let raw_args = vec![String::from("--config"), String::from("ci/config"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            config_paths: vec![String::from("ci/config")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_config_stdin() {
+        let raw_args = vec![String::from("--config"), String::from("-"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            config_paths: vec![String::from("-")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_config_several_times() {
+        let raw_args = vec![
+            String::from("--config"), String::from("base"),
+            String::from("--config"), String::from("-"),
+            String::from("--config"), String::from("local"),
+            String::from("test command"),
+        ];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            config_paths: vec![String::from("base"), String::from("-"), String::from("local")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_config_stdin_twice() {
+        let raw_args = vec![String::from("--config"), String::from("-"), String::from("--config"), String::from("-"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass '-' to '--config' only once.")));
+    }
+
+    #[test]
+    fn parse_several_commands() {
+        let raw_args = vec![
+            String::from("--command"), String::from("./gradlew assemble"),
+            String::from("--command"), String::from("./gradlew lint"),
+            String::from("./gradlew"), String::from("test"),
+        ];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("./gradlew assemble"), String::from("./gradlew lint"), String::from("./gradlew test")],
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_command_file_without_command() {
+        let raw_args = vec![String::from("--command-file"), String::from("ci/build.sh")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            command_file: Some(String::from("ci/build.sh")),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_only_command_options() {
+        let raw_args = vec![String::from("--keep-going"), String::from("--command"), String::from("./gradlew assemble")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("./gradlew assemble")],
+            keep_going: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_tty() {
+        let raw_args = vec![String::from("--tty"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            tty: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_no_color() {
+        let raw_args = vec![String::from("--no-color"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            no_color: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_stats() {
+        let raw_args = vec![String::from("--stats"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            stats: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_delete() {
+        let raw_args = vec![String::from("--delete"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            delete: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_machine_readable_progress() {
+        let raw_args = vec![String::from("--machine-readable-progress"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            machine_readable_progress: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_compression_levels() {
+        let raw_args = vec![
+            String::from("--compression-local"), String::from("0"),
+            String::from("--compression-remote"), String::from("9"),
+            String::from("test command"),
+        ];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            local_compression_level: Some(0),
+            remote_compression_level: Some(9),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_compression_local_out_of_range() {
+        let raw_args = vec![String::from("--compression-local"), String::from("10"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("'--compression-local' must be a number from 0 to 9, found '10'.")));
+    }
+
+    #[test]
+    fn parse_compression_remote_not_a_number() {
+        let raw_args = vec![String::from("--compression-remote"), String::from("max"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("'--compression-remote' must be a number from 0 to 9, found 'max'.")));
+    }
+
+    #[test]
+    fn parse_watch() {
+        let raw_args = vec![String::from("--watch"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            watch: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_local() {
+        let raw_args = vec![String::from("--local"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            local: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_upload_only_without_command() {
+        let raw_args = vec![String::from("--upload-only")];
+        let args = Args::parse(raw_args.as_ref()).unwrap();
+        assert_eq!(args.run_mode, RunMode::UploadOnly);
+        assert_eq!(args.run_mode.phases(), &[Phase::Upload]);
+    }
+
+    #[test]
+    fn parse_download_only_without_command() {
+        let raw_args = vec![String::from("--download-only")];
+        let args = Args::parse(raw_args.as_ref()).unwrap();
+        assert_eq!(args.run_mode, RunMode::DownloadOnly);
+        assert_eq!(args.run_mode.phases(), &[Phase::Download]);
+    }
+
+    #[test]
+    fn parse_no_execute_without_command() {
+        let raw_args = vec![String::from("--no-execute")];
+        let args = Args::parse(raw_args.as_ref()).unwrap();
+        assert_eq!(args.run_mode, RunMode::NoExecute);
+        assert_eq!(args.run_mode.phases(), &[Phase::Upload, Phase::Download]);
+    }
+
+    #[test]
+    fn parse_no_execute_and_upload_only() {
+        let raw_args = vec![String::from("--no-execute"), String::from("--upload-only")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass only one of '--upload-only', '--download-only' and '--no-execute'.")));
+    }
+
+    #[test]
+    fn parse_full_run_phases() {
+        let raw_args = vec![String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()).unwrap().run_mode.phases(), &[Phase::Upload, Phase::Execute, Phase::Download]);
+    }
+
+    #[test]
+    fn parse_upload_only_and_download_only() {
+        let raw_args = vec![String::from("--upload-only"), String::from("--download-only")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass only one of '--upload-only', '--download-only' and '--no-execute'.")));
+    }
+
+    #[test]
+    fn parse_local_and_upload_only() {
+        let raw_args = vec![String::from("--local"), String::from("--upload-only"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass either '--local' or partial run mode, '--local' doesn't sync anything.")));
+    }
+
+    #[test]
+    fn parse_since_and_full() {
+        let raw_args = vec![String::from("--since"), String::from("--full"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            since: true,
+            full: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_force_full_upload() {
+        let raw_args = vec![String::from("--force-full-upload"), String::from("test command")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            commands: vec![String::from("test command")],
+            force_full_upload: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_explain_without_command() {
+        let raw_args = vec![String::from("--explain"), String::from("--machine"), String::from("build")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            remote_machine: Some(String::from("build")),
+            explain: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_config_check_without_command() {
+        let raw_args = vec![String::from("--config-check"), String::from("--profile"), String::from("ci")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            profile: Some(String::from("ci")),
+            config_check: true,
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_list_profiles_without_command() {
+        let raw_args = vec![String::from("--list-profiles")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            list: Some(Listing::Profiles),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_list_machines_with_config() {
+        let raw_args = vec![String::from("--config"), String::from("ci/config"), String::from("--list-machines")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            config_paths: vec![String::from("ci/config")],
+            list: Some(Listing::RemoteMachines),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_list_profiles_and_machines() {
+        let raw_args = vec![String::from("--list-profiles"), String::from("--list-machines")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Please pass either '--list-profiles' or '--list-machines', not both.")));
+    }
+
+    #[test]
+    fn parse_completions_without_command() {
+        let raw_args = vec![String::from("--completions"), String::from("fish")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            completions: Some(Shell::Fish),
+            ..Args::default()
+        }));
+    }
+
+    #[test]
+    fn parse_completions_unknown_shell() {
+        let raw_args = vec![String::from("--completions"), String::from("powershell")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Err(String::from("Unknown shell 'powershell', please pass one of 'bash', 'zsh' and 'fish'.")));
+    }
+
+    #[test]
+    fn parse_init_without_command() {
+        let raw_args = vec![String::from("--init"), String::from("--force")];
+        assert_eq!(Args::parse(raw_args.as_ref()), Ok(Args {
+            init: true,
+            force: true,
+            ..Args::default()
+        }));
     }
 }
@@ -0,0 +1,107 @@
+use config::Config;
+use output::Message;
+use output::Output;
+use runner::CommandRunner;
+use ssh;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// rsync compares modification times with 1 second precision, anything above a minute is not a rounding issue.
+const MAX_CLOCK_SKEW_SECONDS: u64 = 60;
+
+// Non-fatal, unreachable machine or "date" failure is reported by the sync that follows.
+pub fn check_clock_skew(config: &Config, output: &Output, runner: &dyn CommandRunner) {
+    let local_epoch_seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+
+    if let Some(message) = remote_epoch_seconds(config, runner).and_then(|remote_epoch_seconds| clock_skew_warning(local_epoch_seconds, remote_epoch_seconds)) {
+        if output.shows(Message::Warning) {
+            eprintln!("{}\n", message);
+        }
+    }
+}
+
+fn remote_epoch_seconds(config: &Config, runner: &dyn CommandRunner) -> Option<u64> {
+    let mut args = ssh::ssh_options(config);
+    // Check is not worth a password prompt, it's skipped instead.
+    args.push(String::from("-o"));
+    args.push(String::from("BatchMode=yes"));
+    args.push(config.remote_machine_target());
+    args.push(String::from("date +%s"));
+
+    let output = runner.output(&config.remote_machine_ssh_path, &args, None).ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+pub fn clock_skew_warning(local_epoch_seconds: u64, remote_epoch_seconds: u64) -> Option<String> {
+    let (skew, direction) = if remote_epoch_seconds >= local_epoch_seconds {
+        (remote_epoch_seconds - local_epoch_seconds, "ahead of")
+    } else {
+        (local_epoch_seconds - remote_epoch_seconds, "behind")
+    };
+
+    if skew <= MAX_CLOCK_SKEW_SECONDS {
+        return None;
+    }
+
+    Some(format!(
+        "Clock of remote machine is {} seconds {} local one, rsync may transfer unchanged files again. Consider setting 'sync_checksum=true'.",
+        skew,
+        direction
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use runner::RecordingRunner;
+
+    #[test]
+    fn remote_epoch_seconds_runs_date_over_ssh() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "1700000000\n");
+
+        assert_eq!(remote_epoch_seconds(&config, &runner), Some(1700000000));
+        assert_eq!(runner.invocations(), vec![(String::from("ssh"), vec![
+            String::from("-o"),
+            String::from("StrictHostKeyChecking=accept-new"),
+            String::from("-o"),
+            String::from("BatchMode=yes"),
+            String::from("machine"),
+            String::from("date +%s"),
+        ])]);
+    }
+
+    #[test]
+    fn remote_epoch_seconds_unreachable_machine() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(remote_epoch_seconds(&config, &RecordingRunner::new(255, "")), None);
+    }
+
+    #[test]
+    fn clock_skew_warning_within_threshold() {
+        assert_eq!(clock_skew_warning(1700000000, 1700000060), None);
+        assert_eq!(clock_skew_warning(1700000060, 1700000000), None);
+    }
+
+    #[test]
+    fn clock_skew_warning_remote_ahead() {
+        assert_eq!(
+            clock_skew_warning(1700000000, 1700000300),
+            Some(String::from("Clock of remote machine is 300 seconds ahead of local one, rsync may transfer unchanged files again. Consider setting 'sync_checksum=true'."))
+        );
+    }
+
+    #[test]
+    fn clock_skew_warning_remote_behind() {
+        assert_eq!(
+            clock_skew_warning(1700000061, 1700000000),
+            Some(String::from("Clock of remote machine is 61 seconds behind local one, rsync may transfer unchanged files again. Consider setting 'sync_checksum=true'."))
+        );
+    }
+}
@@ -0,0 +1,198 @@
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn parse(name: &str) -> Option<Shell> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None
+        }
+    }
+}
+
+// What an option is completed with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Value {
+    Flag,
+    Any,
+    File,
+    Words(&'static str),
+    // Words printed by Mainframer itself, they come from config in working dir.
+    Listing(&'static str),
+}
+
+// Options known to "Args::parse", short aliases are left out since there is nothing to complete in them.
+const OPTIONS: &[(&str, Value)] = &[
+    ("--machine", Value::Listing("--list-machines")),
+    ("--profile", Value::Listing("--list-profiles")),
+    ("--dry-run", Value::Flag),
+    ("--timeout", Value::Any),
+    ("--report-format", Value::Words("human json")),
+    ("--exclude", Value::Any),
+    ("--config", Value::File),
+    ("--command", Value::Any),
+    ("--command-file", Value::File),
+    ("--keep-going", Value::Flag),
+    ("--tty", Value::Flag),
+    ("--no-color", Value::Flag),
+    ("--stats", Value::Flag),
+    ("--delete", Value::Flag),
+    ("--machine-readable-progress", Value::Flag),
+    ("--compression-local", Value::Words("0 1 2 3 4 5 6 7 8 9")),
+    ("--compression-remote", Value::Words("0 1 2 3 4 5 6 7 8 9")),
+    ("--watch", Value::Flag),
+    ("--local", Value::Flag),
+    ("--upload-only", Value::Flag),
+    ("--download-only", Value::Flag),
+    ("--no-execute", Value::Flag),
+    ("--since", Value::Flag),
+    ("--full", Value::Flag),
+    ("--force-full-upload", Value::Flag),
+    ("--explain", Value::Flag),
+    ("--config-check", Value::Flag),
+    ("--list-profiles", Value::Flag),
+    ("--list-machines", Value::Flag),
+    ("--completions", Value::Words("bash zsh fish")),
+    ("--init", Value::Flag),
+    ("--force", Value::Flag),
+    ("--quiet", Value::Flag),
+    ("--verbose", Value::Flag),
+];
+
+// Options can be passed more than once, the rest are completed only until they are present.
+const REPEATABLE_OPTIONS: &[&str] = &["--exclude", "--config", "--command"];
+
+pub fn script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash_script(),
+        Shell::Zsh => zsh_script(),
+        Shell::Fish => fish_script(),
+    }
+}
+
+// Only options are completed, words after the command belong to it.
+fn bash_script() -> String {
+    let mut script = String::from("_mainframer() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n\n    case \"$prev\" in\n");
+
+    for &(option, value) in OPTIONS {
+        let reply = match value {
+            Value::Flag => continue,
+            Value::Any => String::new(),
+            Value::File => String::from("COMPREPLY=($(compgen -f -- \"$cur\")); "),
+            Value::Words(words) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); ", words),
+            Value::Listing(listing) => format!("COMPREPLY=($(compgen -W \"$(mainframer {} 2>/dev/null)\" -- \"$cur\")); ", listing),
+        };
+        script.push_str(&format!("        {}) {}return ;;\n", option, reply));
+    }
+
+    let options: Vec<&str> = OPTIONS.iter().map(|&(option, _)| option).collect();
+    script.push_str(&format!(
+        "    esac\n\n    if [[ \"$cur\" == -* ]]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n    fi\n}}\n\ncomplete -o default -F _mainframer mainframer\n",
+        options.join(" ")
+    ));
+    script
+}
+
+fn zsh_script() -> String {
+    let mut script = String::from("#compdef mainframer\n\n_arguments \\\n");
+
+    for &(option, value) in OPTIONS {
+        let repeat = if REPEATABLE_OPTIONS.contains(&option) { "*" } else { "" };
+        let name = option.trim_start_matches('-');
+        let action = match value {
+            Value::Flag => String::new(),
+            Value::Any => format!(":{}: ", name),
+            Value::File => format!(":{}:_files", name),
+            Value::Words(words) => format!(":{}:({})", name, words),
+            Value::Listing(listing) => format!(":{}:{{compadd -- $(mainframer {} 2>/dev/null)}}", name, listing),
+        };
+        script.push_str(&format!("    '{}{}{}' \\\n", repeat, option, action));
+    }
+
+    script.push_str("    '*::command:_normal'\n");
+    script
+}
+
+fn fish_script() -> String {
+    let mut script = String::new();
+
+    for &(option, value) in OPTIONS {
+        let completion = match value {
+            Value::Flag => String::new(),
+            Value::Any => String::from(" -x"),
+            Value::File => String::from(" -r -F"),
+            Value::Words(words) => format!(" -x -a '{}'", words),
+            Value::Listing(listing) => format!(" -x -a '(mainframer {} 2>/dev/null)'", listing),
+        };
+        script.push_str(&format!("complete -c mainframer -l {}{}\n", option.trim_start_matches('-'), completion));
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use args::Args;
+
+    #[test]
+    fn shell_parse() {
+        assert_eq!(Shell::parse("zsh"), Some(Shell::Zsh));
+        assert_eq!(Shell::parse("powershell"), None);
+    }
+
+    #[test]
+    fn options_are_known_to_args_parse() {
+        for &(option, value) in OPTIONS {
+            let mut raw_args = vec![String::from(option)];
+
+            match value {
+                Value::Flag => {}
+                Value::Words(words) => raw_args.push(String::from(words.split(' ').next().unwrap())),
+                _ => raw_args.push(String::from("1")),
+            }
+
+            raw_args.push(String::from("make"));
+            assert_eq!(Args::parse(&raw_args).map(|args| args.commands.last().cloned()), Ok(Some(String::from("make"))), "{}", option);
+        }
+    }
+
+    #[test]
+    fn bash_script_contains_options() {
+        let script = script(Shell::Bash);
+        for option in &["--machine", "--profile", "--dry-run", "--report-format", "--config", "--watch", "--no-execute", "--verbose"] {
+            assert!(script.contains(option), "{}", option);
+        }
+        assert!(script.contains("        --machine) COMPREPLY=($(compgen -W \"$(mainframer --list-machines 2>/dev/null)\" -- \"$cur\")); return ;;\n"));
+        assert!(script.ends_with("complete -o default -F _mainframer mainframer\n"));
+    }
+
+    #[test]
+    fn bash_script_in_shell() {
+        let script = format!("{}\nCOMP_WORDS=(mainframer --report); COMP_CWORD=1; _mainframer; echo \"${{COMPREPLY[@]}}\"", script(Shell::Bash));
+        let output = ::std::process::Command::new("bash").arg("-c").arg(script).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "--report-format\n");
+    }
+
+    #[test]
+    fn zsh_script_repeatable_option() {
+        let script = script(Shell::Zsh);
+        assert!(script.starts_with("#compdef mainframer\n"));
+        assert!(script.contains("    '*--exclude:exclude: ' \\\n"));
+        assert!(script.contains("    '--profile:profile:{compadd -- $(mainframer --list-profiles 2>/dev/null)}' \\\n"));
+    }
+
+    #[test]
+    fn fish_script_options() {
+        let script = script(Shell::Fish);
+        assert!(script.contains("complete -c mainframer -l dry-run\n"));
+        assert!(script.contains("complete -c mainframer -l config -r -F\n"));
+        assert!(script.contains("complete -c mainframer -l report-format -x -a 'human json'\n"));
+    }
+}
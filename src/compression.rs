@@ -0,0 +1,212 @@
+use config::Config;
+use output::Message;
+use output::Output;
+use runner::CommandRunner;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+use sync;
+
+// Level picked for each remote machine, link speed is measured only on the first run against it.
+const CACHE_FILE: &str = ".mainframer/compression_auto";
+
+// Big enough for the transfer to outweigh connection setup, small enough to go unnoticed on slow links.
+const PROBE_PAYLOAD_BYTES: usize = 2 * 1024 * 1024;
+
+// Slow links gain the most from compression, on fast ones time spent compressing outweighs the saved transfer.
+pub fn level_for_mbps(mbps: f64) -> u8 {
+    if mbps < 5.0 {
+        9
+    } else if mbps < 20.0 {
+        6
+    } else if mbps < 100.0 {
+        3
+    } else if mbps < 500.0 {
+        1
+    } else {
+        0
+    }
+}
+
+// Non-fatal, default levels are used if link speed can't be measured.
+pub fn apply_auto_compression(local_dir_absolute_path: &Path, config: &mut Config, output: &Output, runner: &dyn CommandRunner) {
+    if !config.local_compression_level_auto && !config.remote_compression_level_auto {
+        return;
+    }
+
+    let remote_machine = config.remote_machine_target();
+
+    let level = match read_cache(local_dir_absolute_path).get(&remote_machine) {
+        Some(&level) => level,
+        // Measuring would transfer the probe.
+        None if config.dry_run => return,
+        None => match measure_mbps(local_dir_absolute_path, config, runner) {
+            Err(message) => {
+                if output.shows(Message::Warning) {
+                    eprintln!("Could not measure link speed to remote machine, using default compression levels: {}.\n", message);
+                }
+
+                return;
+            }
+            Ok(mbps) => {
+                let level = level_for_mbps(mbps);
+
+                if write_cache(local_dir_absolute_path, &remote_machine, level).is_err() && output.shows(Message::Warning) {
+                    eprintln!("Could not write '{}', link speed will be measured again next time.\n", CACHE_FILE);
+                }
+
+                level
+            }
+        }
+    };
+
+    if config.local_compression_level_auto {
+        config.local_compression_level = level;
+    }
+
+    if config.remote_compression_level_auto {
+        config.remote_compression_level = level;
+    }
+}
+
+// Connection setup is included, so slow-to-connect machines are treated as slow links.
+fn measure_mbps(local_dir_absolute_path: &Path, config: &Config, runner: &dyn CommandRunner) -> Result<f64, String> {
+    let payload = env::temp_dir().join(format!("mainframer-{}.probe", process::id()));
+
+    if fs::write(&payload, probe_payload()).is_err() {
+        return Err(String::from("could not write probe payload"));
+    }
+
+    let start = Instant::now();
+    let result = sync::upload_probe(local_dir_absolute_path, config, &payload, runner);
+    let seconds = start.elapsed().as_secs_f64();
+
+    let _ = fs::remove_file(&payload);
+
+    result.map(|_| (PROBE_PAYLOAD_BYTES * 8) as f64 / seconds.max(0.001) / 1_000_000.0).map_err(|failure| failure.to_string())
+}
+
+// Same pseudo-random bytes every time, they don't compress so ssh compression doesn't skew the measurement.
+fn probe_payload() -> Vec<u8> {
+    let mut state: u32 = 0x9E37_79B9;
+
+    (0..PROBE_PAYLOAD_BYTES).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }).collect()
+}
+
+fn read_cache(local_dir_absolute_path: &Path) -> BTreeMap<String, u8> {
+    fs::read_to_string(local_dir_absolute_path.join(CACHE_FILE)).map(|content| parse_cache(&content)).unwrap_or_default()
+}
+
+fn write_cache(local_dir_absolute_path: &Path, remote_machine: &str, level: u8) -> io::Result<()> {
+    let mut cache = read_cache(local_dir_absolute_path);
+    cache.insert(String::from(remote_machine), level);
+    fs::write(local_dir_absolute_path.join(CACHE_FILE), format_cache(&cache))
+}
+
+// One "remote_machine=level" line per machine, malformed lines are dropped so they're measured again.
+fn parse_cache(content: &str) -> BTreeMap<String, u8> {
+    content.lines()
+        .filter_map(|line| line.rsplit_once('='))
+        .filter_map(|(remote_machine, level)| level.trim().parse().ok().map(|level| (String::from(remote_machine.trim()), level)))
+        .collect()
+}
+
+fn format_cache(cache: &BTreeMap<String, u8>) -> String {
+    cache.iter().map(|(remote_machine, level)| format!("{}={}\n", remote_machine, level)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use output::Verbosity;
+    use runner::RecordingRunner;
+
+    fn project_dir(name: &str) -> ::std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("mainframer_compression_{}_{}", name, process::id()));
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn level_for_mbps_slow_link() {
+        assert_eq!(level_for_mbps(1.0), 9);
+        assert_eq!(level_for_mbps(10.0), 6);
+    }
+
+    #[test]
+    fn level_for_mbps_medium_link() {
+        assert_eq!(level_for_mbps(50.0), 3);
+        assert_eq!(level_for_mbps(100.0), 1);
+    }
+
+    #[test]
+    fn level_for_mbps_fast_link() {
+        assert_eq!(level_for_mbps(500.0), 0);
+        assert_eq!(level_for_mbps(10_000.0), 0);
+    }
+
+    #[test]
+    fn format_and_parse_cache() {
+        let mut cache = BTreeMap::new();
+        cache.insert(String::from("builder@machine"), 6);
+        cache.insert(String::from("machine:2222"), 0);
+
+        assert_eq!(format_cache(&cache), "builder@machine=6\nmachine:2222=0\n");
+        assert_eq!(parse_cache(&format_cache(&cache)), cache);
+        assert_eq!(parse_cache("garbage\nmachine=fast\n"), BTreeMap::new());
+    }
+
+    #[test]
+    fn apply_auto_compression_measures_and_caches() {
+        let dir = project_dir("measure");
+        let mut config = config_from_str("remote_machine=machine\ncompression_auto=true\nlocal_compression_level=4", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+
+        apply_auto_compression(&dir, &mut config, &Output::new(Verbosity::Quiet, true), &runner);
+        let cache = read_cache(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Recorded rsync returns right away, that's as fast as a link gets.
+        assert_eq!(runner.invocations().len(), 1);
+        assert_eq!(config.local_compression_level, 4);
+        assert_eq!(config.remote_compression_level, 0);
+        assert_eq!(cache.get("machine"), Some(&0));
+    }
+
+    #[test]
+    fn apply_auto_compression_uses_cache() {
+        let dir = project_dir("cache");
+        write_cache(&dir, "machine", 6).unwrap();
+        let mut config = config_from_str("remote_machine=machine\ncompression_auto=true", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+
+        apply_auto_compression(&dir, &mut config, &Output::new(Verbosity::Quiet, true), &runner);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(runner.invocations().is_empty());
+        assert_eq!((config.local_compression_level, config.remote_compression_level), (6, 6));
+    }
+
+    #[test]
+    fn apply_auto_compression_failed_measurement_keeps_defaults() {
+        let dir = project_dir("failed");
+        let mut config = config_from_str("remote_machine=machine\ncompression_auto=true", None).unwrap();
+
+        apply_auto_compression(&dir, &mut config, &Output::new(Verbosity::Quiet, true), &RecordingRunner::new(255, ""));
+        let cache = read_cache(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((config.local_compression_level, config.remote_compression_level), (1, 1));
+        assert_eq!(cache, BTreeMap::new());
+    }
+}
@@ -1,161 +1,3321 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
 use std::path::Path;
+use std::path::PathBuf;
+
+const COMPRESSION_LEVEL_MIN: u8 = 0;
+const COMPRESSION_LEVEL_MAX: u8 = 9;
+
+// Hidden, so it doesn't clutter project dirs of interrupted transfers.
+const DEFAULT_PARTIAL_DIR: &str = ".mainframer-partial";
+
+const KNOWN_KEYS: &[&str] = &[
+    "extends",
+    "remote_machine",
+    "remote_machine_user",
+    "remote_machine_port",
+    "remote_machine_identity_file",
+    "remote_machine_proxy_jump",
+    "remote_machine_keep_alive_interval_seconds",
+    "remote_machine_keep_alive_count_max",
+    "remote_machine_connect_timeout_seconds",
+    "remote_machine_multiplex",
+    "remote_machine_forward_agent",
+    "compression_level",
+    "local_compression_level",
+    "remote_compression_level",
+    "compression_auto",
+    "compression_skip_compress",
+    "rsync_args",
+    "bandwidth_limit_kbps",
+    "sync_checksum",
+    "sync_check_clock_skew",
+    "sync_whole_file",
+    "sync_max_file_size_upload",
+    "sync_max_file_size_download",
+    "sync_parallelism",
+    "sync_partial_dir",
+    "sync_delete_extraneous",
+    "sync_rsync_path",
+    "remote_machine_ssh_path",
+    "remote_machine_ssh_options",
+    "remote_machine_transport",
+    "remote_machine_module",
+    "remote_machine_shell",
+    "remote_machine_strict_host_key_checking",
+    "remote_machine_known_hosts_file",
+    "watch_debounce_millis",
+    "sync_preserve_permissions",
+    "sync_preserve_times",
+    "sync_preserve_links",
+    "sync_copy_links",
+    "sync_ignore_git",
+    "sync_io_timeout_seconds",
+    "remote_folder_prefix",
+    "dry_run",
+    "timeout_seconds",
+    "execution_allocate_tty",
+    "execution_working_dir",
+    "execution_source_profile",
+    "execution_require_remote_tools",
+    "execution_nice",
+    "execution_ionice",
+    "execution_result_paths",
+    "before_upload_hook",
+    "after_download_hook",
+    "sync_back_on_failure",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    FileNotFound { path: PathBuf },
+    // Opened but not read, i.e. closed stdin or content that is not UTF-8.
+    Unreadable { path: PathBuf },
+    // Line that is neither "key=value" pair, comment nor blank.
+    Syntax { line: usize, column: usize, message: String },
+    TypeMismatch { key: String, expected: &'static str, found: String },
+    OutOfRange { key: String, min: i64, max: i64, found: String },
+    UnknownKey { key: String, suggestion: Option<&'static str> },
+    // Values are well-formed but don't make a usable config together, i.e. remote machine is missing.
+    Invalid(String),
+    InProfile { profile: String, error: Box<ConfigError> },
+    InFile { path: PathBuf, error: Box<ConfigError> },
+    // Parsing goes on after invalid value so all errors are reported at once.
+    Multiple(Vec<ConfigError>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::FileNotFound { ref path } => write!(f, "Could not open config file '{}'.", path.to_string_lossy()),
+            ConfigError::Unreadable { ref path } => write!(f, "Could not read config file '{}'.", path.to_string_lossy()),
+            ConfigError::Syntax { line, column, ref message } => write!(f, "Error at line {}, column {}: {}", line, column, message),
+            ConfigError::TypeMismatch { ref key, expected, ref found } => write!(f, "'{}' must be {}, found '{}'.", key, expected, found),
+            ConfigError::OutOfRange { ref key, min, max, ref found } => write!(f, "'{}' must be a number from {} to {}, found '{}'.", key, min, max, found),
+            ConfigError::UnknownKey { ref key, suggestion: None } => write!(f, "Unknown config key '{}'.", key),
+            ConfigError::UnknownKey { ref key, suggestion: Some(known_key) } => write!(f, "Unknown config key '{}'. Did you mean '{}'?", key, known_key),
+            ConfigError::Invalid(ref message) => write!(f, "{}", message),
+            ConfigError::InProfile { ref profile, ref error } => write!(f, "{}", error.to_string().lines()
+                .map(|line| format!("In profile '{}': {}", profile, line))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ConfigError::InFile { ref path, ref error } => write!(f, "Error during parsing config file '{}'\n{}", path.to_string_lossy(), error),
+            ConfigError::Multiple(ref errors) => write!(f, "{}", errors.iter().map(ConfigError::to_string).collect::<Vec<String>>().join("\n")),
+        }
+    }
+}
+
+impl ConfigError {
+    fn in_file(path: &Path, error: ConfigError) -> ConfigError {
+        ConfigError::InFile { path: path.to_path_buf(), error: Box::new(error) }
+    }
+
+    fn from_errors(mut errors: Vec<ConfigError>) -> ConfigError {
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => ConfigError::Multiple(errors)
+        }
+    }
+}
+
+// How files are synced, rsync daemon skips ssh but can't execute remote command.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Transport {
+    #[default]
+    Ssh,
+    Rsyncd,
+}
+
+// Config values as they are specified in config file, without defaults.
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct IntermediateConfig {
+    pub remote_machine: Option<String>,
+    pub remote_machines: BTreeMap<String, String>,
+    pub remote_machine_user: Option<String>,
+    pub remote_machine_port: Option<u16>,
+    pub remote_machine_identity_file: Option<String>,
+    pub remote_machine_proxy_jump: Option<String>,
+    pub remote_machine_keep_alive_interval_seconds: Option<u32>,
+    pub remote_machine_keep_alive_count_max: Option<u32>,
+    pub remote_machine_connect_timeout_seconds: Option<u32>,
+    pub compression_level: Option<u8>,
+    pub local_compression_level: Option<u8>,
+    pub remote_compression_level: Option<u8>,
+    pub compression_auto: Option<bool>,
+    pub compression_skip_compress: Option<Vec<String>>,
+    pub rsync_args: Option<Vec<String>>,
+    pub bandwidth_limit_kbps: Option<u32>,
+    pub remote_folder_prefix: Option<String>,
+    pub dry_run: Option<bool>,
+    pub timeout_seconds: Option<u32>,
+    pub before_upload_hook: Option<String>,
+    pub after_download_hook: Option<String>,
+    pub sync_back_on_failure: Option<bool>,
+    pub remote_machine_multiplex: Option<bool>,
+    pub sync_checksum: Option<bool>,
+    pub sync_check_clock_skew: Option<bool>,
+    pub sync_preserve_permissions: Option<bool>,
+    pub sync_preserve_times: Option<bool>,
+    pub sync_preserve_links: Option<bool>,
+    pub sync_copy_links: Option<bool>,
+    pub sync_ignore_git: Option<bool>,
+    pub remote_env: BTreeMap<String, String>,
+    pub execution_allocate_tty: Option<bool>,
+    pub remote_machine_forward_agent: Option<bool>,
+    pub sync_delete_extraneous: Option<bool>,
+    pub sync_rsync_path: Option<String>,
+    pub remote_machine_ssh_path: Option<String>,
+    pub remote_machine_shell: Option<String>,
+    pub watch_debounce_millis: Option<u32>,
+    pub remote_machine_strict_host_key_checking: Option<bool>,
+    pub remote_machine_known_hosts_file: Option<String>,
+    pub execution_working_dir: Option<String>,
+    pub execution_source_profile: Option<String>,
+    pub execution_require_remote_tools: Option<Vec<String>>,
+    pub execution_nice: Option<i64>,
+    pub execution_ionice: Option<bool>,
+    pub execution_result_paths: Option<Vec<String>>,
+    pub sync_io_timeout_seconds: Option<u32>,
+    pub remote_machine_ssh_options: Option<Vec<String>>,
+    pub remote_machine_transport: Option<Transport>,
+    pub remote_machine_module: Option<String>,
+    pub sync_whole_file: Option<bool>,
+    pub sync_max_file_size_upload: Option<u64>,
+    pub sync_max_file_size_download: Option<u64>,
+    pub sync_parallelism: Option<i64>,
+    // Inner none is explicit "false", so a profile can turn it off.
+    pub sync_partial_dir: Option<Option<String>>,
+    pub profiles: BTreeMap<String, IntermediateConfig>,
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Config {
     pub remote_machine_name: String,
+    pub remote_machine_user: Option<String>,
+    pub remote_machine_port: Option<u16>,
+    pub remote_machine_identity_file: Option<String>,
+    pub remote_machine_proxy_jump: Option<String>,
+    pub remote_machine_keep_alive_interval_seconds: Option<u32>,
+    pub remote_machine_keep_alive_count_max: Option<u32>,
+    pub remote_machine_connect_timeout_seconds: Option<u32>,
     pub local_compression_level: u8,
     pub remote_compression_level: u8,
+    pub rsync_args: Vec<String>,
+    pub bandwidth_limit_kbps: Option<u32>,
+    pub remote_folder_prefix: String,
+    pub dry_run: bool,
+    // Set only by "--force-full-upload" for a single run, there is no config key for it.
+    pub force_full_upload: bool,
+    pub timeout_seconds: Option<u32>,
+    pub before_upload_hook: Option<String>,
+    pub after_download_hook: Option<String>,
+    pub sync_back_on_failure: bool,
+    pub remote_machine_multiplex: bool,
+    pub sync_checksum: bool,
+    // Costs an ssh connection of its own before the upload, so it's opt-in.
+    pub sync_check_clock_skew: bool,
+    pub sync_preserve_permissions: bool,
+    pub sync_preserve_times: bool,
+    pub sync_preserve_links: bool,
+    // Symlinks are replaced with what they point to, i.e. into a shared cache outside of project dir.
+    // Links are not preserved then, so "sync_preserve_links" defaults to false and can't be enabled along with it.
+    pub sync_copy_links: bool,
+    pub sync_ignore_git: bool,
+    pub remote_env: BTreeMap<String, String>,
+    pub execution_allocate_tty: bool,
+    pub remote_machine_forward_agent: bool,
+    pub sync_delete_extraneous: bool,
+    pub sync_rsync_path: String,
+    pub remote_machine_ssh_path: String,
+    // Shell that runs remote command, login shell of remote user pipes it to "bash" if not set.
+    pub remote_machine_shell: Option<String>,
+    // Quiet period after last local change before "--watch" starts next run.
+    pub watch_debounce_millis: u32,
+    // Unknown host keys are accepted and remembered unless strict, changed ones are always rejected.
+    pub remote_machine_strict_host_key_checking: bool,
+    pub remote_machine_known_hosts_file: Option<String>,
+    // Relative to project dir on remote machine, never outside of it.
+    pub execution_working_dir: Option<String>,
+    // Sourced on remote machine before the command, i.e. "~/.profile" or "~/.sdkman/bin/sdkman-init.sh".
+    pub execution_source_profile: Option<String>,
+    // Checked before the first upload, so missing ones fail the run before anything is transferred.
+    pub execution_require_remote_tools: Vec<String>,
+    // Priority of remote command on shared machines, none leaves the one of login shell.
+    pub execution_nice: Option<i64>,
+    // Idle I/O class, so the build only gets disk when nobody else needs it.
+    pub execution_ionice: bool,
+    // Only these are downloaded if not empty, relative to project dir like "execution_working_dir".
+    pub execution_result_paths: Vec<String>,
+    // Aborts sync if no data is transferred for this long, unlike "timeout_seconds" that limits remote command.
+    pub sync_io_timeout_seconds: Option<u32>,
+    // Passed to every ssh invocation as "-o {option}", for ssh options that don't have own keys.
+    pub remote_machine_ssh_options: Vec<String>,
+    pub remote_machine_transport: Transport,
+    // rsync daemon module that holds projects, only used with "rsyncd" transport.
+    pub remote_machine_module: Option<String>,
+    // Delta transfer is skipped if set, unset lets rsync decide (it skips it for local paths only).
+    pub sync_whole_file: Option<bool>,
+    // Larger files are skipped, in bytes.
+    pub sync_max_file_size_upload: Option<u64>,
+    pub sync_max_file_size_download: Option<u64>,
+    // Clamped before use, see "sync::clamp_parallelism()".
+    pub sync_parallelism: Option<i64>,
+    // Dir partially transferred files are kept in, so interrupted transfer resumes instead of starting over.
+    pub sync_partial_dir: Option<String>,
+    // Directions without explicit compression level, their level is picked from measured link speed.
+    pub local_compression_level_auto: bool,
+    pub remote_compression_level_auto: bool,
+    // Extensions of already compressed files, they are sent as is even if compression is enabled.
+    pub compression_skip_compress: Vec<String>,
+}
+
+impl IntermediateConfig {
+    // Layers are merged on top of global config in order, so later ones win, "-" is stdin.
+    pub fn from_files(file_paths: &[&Path], global_config_file: Option<&Path>) -> Result<IntermediateConfig, ConfigError> {
+        let mut config = read_optional_global_config(global_config_file)?;
+
+        for file_path in file_paths {
+            config = merge(config, read_layer(file_path)?);
+        }
+
+        Ok(config)
+    }
+
+    // Project config merged on top of global one, before profile and remote machine are picked.
+    pub fn from_reader<R: Read>(reader: R, source_path: &Path, global_config_file: Option<&Path>) -> Result<IntermediateConfig, ConfigError> {
+        let global_config = read_optional_global_config(global_config_file)?;
+        Ok(merge(global_config, parse_layer(reader, source_path)?))
+    }
+
+    // Sorted, so listing is stable for shell completion.
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    pub fn remote_machine_names(&self) -> Vec<String> {
+        self.remote_machines.keys().cloned().collect()
+    }
 }
 
 impl Config {
-    pub fn from_file(file_path: &Path) -> Result<Config, String> {
-        let mut content = String::new();
+    pub fn from_file(file_path: &Path, global_config_file: Option<&Path>, remote_machine: Option<&str>, profile: Option<&str>) -> Result<Config, ConfigError> {
+        match File::open(file_path) {
+            Err(_) => Err(ConfigError::FileNotFound { path: file_path.to_path_buf() }),
+            Ok(file) => Config::from_reader(file, file_path, global_config_file, remote_machine, profile)
+        }
+    }
 
-        let mut file = match File::open(file_path) {
-            Err(_) => return Err(format!("Could not open config file '{}'.", file_path.to_string_lossy())),
-            Ok(value) => value,
-        };
+    // Errors of merged config and relative referenced files belong to the last layer, it's the one overriding the rest.
+    pub fn from_files(file_paths: &[&Path], global_config_file: Option<&Path>, remote_machine: Option<&str>, profile: Option<&str>) -> Result<Config, ConfigError> {
+        let config = IntermediateConfig::from_files(file_paths, global_config_file)?;
+        let source_path = file_paths.last().map_or(Path::new(""), |file_path| layer_source_path(file_path));
+
+        Config::from_intermediate(config, source_path, remote_machine, profile)
+    }
+
+    // "source_path" is used in messages and to resolve "extends", i.e. "<stdin>" resolves them relative to working dir.
+    pub fn from_reader<R: Read>(reader: R, source_path: &Path, global_config_file: Option<&Path>, remote_machine: Option<&str>, profile: Option<&str>) -> Result<Config, ConfigError> {
+        let config = IntermediateConfig::from_reader(reader, source_path, global_config_file)?;
+        Config::from_intermediate(config, source_path, remote_machine, profile)
+    }
 
-        file.read_to_string(&mut content)
-            .unwrap_or_else(|_| panic!("Could not read config file '{}'.", file_path.to_string_lossy()));
+    fn from_intermediate(config: IntermediateConfig, source_path: &Path, remote_machine: Option<&str>, profile: Option<&str>) -> Result<Config, ConfigError> {
+        let config = apply_profile(config, profile)
+            .and_then(|config| Config::resolve(config, remote_machine))
+            .and_then(|config| substitute_environment_variables(config, &|name| env::var(name).ok()))
+            .and_then(|config| match config.remote_machine_identity_file {
+                None => Ok(config),
+                Some(ref identity_file) => Ok(Config {
+                    remote_machine_identity_file: Some(resolve_referenced_file("remote_machine_identity_file", identity_file, source_path.parent().unwrap_or_else(|| Path::new("")), env::var("HOME").ok())?),
+                    ..config
+                })
+            })
+            .and_then(|config| match config.remote_machine_known_hosts_file {
+                None => Ok(config),
+                Some(ref known_hosts_file) => Ok(Config {
+                    remote_machine_known_hosts_file: Some(resolve_referenced_file("remote_machine_known_hosts_file", known_hosts_file, source_path.parent().unwrap_or_else(|| Path::new("")), env::var("HOME").ok())?),
+                    ..config
+                })
+            });
 
-        match parse_config_from_str(&content) {
-            Err(message) => Err(format!("Error during parsing config file '{}'\n{}", file_path.to_string_lossy(), message)),
+        match config {
+            Err(error) => Err(ConfigError::in_file(source_path, error)),
             Ok(config) => Ok(config)
         }
     }
+
+    // Applies defaults and checks that config is usable.
+    pub fn resolve(config: IntermediateConfig, remote_machine: Option<&str>) -> Result<Config, ConfigError> {
+        if config.remote_machine_transport == Some(Transport::Rsyncd) && config.remote_machine_module.is_none() {
+            return Err(ConfigError::Invalid(String::from("please specify 'remote_machine_module' for 'rsyncd' transport.")));
+        }
+
+        if config.sync_copy_links == Some(true) && config.sync_preserve_links == Some(true) {
+            return Err(ConfigError::Invalid(String::from("please enable either 'sync_copy_links' or 'sync_preserve_links', not both.")));
+        }
+
+        let sync_copy_links = config.sync_copy_links.unwrap_or(false);
+
+        Ok(Config {
+            remote_machine_name: select_remote_machine(config.remote_machine, &config.remote_machines, remote_machine)?,
+            remote_machine_user: config.remote_machine_user,
+            remote_machine_port: config.remote_machine_port,
+            remote_machine_identity_file: config.remote_machine_identity_file.map(|value| expand_tilde(&value, env::var("HOME").ok())),
+            remote_machine_proxy_jump: config.remote_machine_proxy_jump,
+            remote_machine_keep_alive_interval_seconds: config.remote_machine_keep_alive_interval_seconds,
+            remote_machine_keep_alive_count_max: config.remote_machine_keep_alive_count_max,
+            remote_machine_connect_timeout_seconds: config.remote_machine_connect_timeout_seconds,
+            // "compression_level" applies to directions that don't specify their own one.
+            local_compression_level: config.local_compression_level.or(config.compression_level).unwrap_or(1),
+            remote_compression_level: config.remote_compression_level.or(config.compression_level).unwrap_or(1),
+            rsync_args: config.rsync_args.unwrap_or_default(),
+            bandwidth_limit_kbps: config.bandwidth_limit_kbps,
+            remote_folder_prefix: config.remote_folder_prefix.unwrap_or_else(|| String::from("~/mainframer")),
+            dry_run: config.dry_run.unwrap_or(false),
+            force_full_upload: false,
+            timeout_seconds: config.timeout_seconds,
+            before_upload_hook: config.before_upload_hook,
+            after_download_hook: config.after_download_hook,
+            sync_back_on_failure: config.sync_back_on_failure.unwrap_or(true),
+            remote_machine_multiplex: config.remote_machine_multiplex.unwrap_or(false),
+            sync_checksum: config.sync_checksum.unwrap_or(false),
+            sync_check_clock_skew: config.sync_check_clock_skew.unwrap_or(false),
+            sync_preserve_permissions: config.sync_preserve_permissions.unwrap_or(true),
+            sync_preserve_times: config.sync_preserve_times.unwrap_or(true),
+            sync_preserve_links: config.sync_preserve_links.unwrap_or(!sync_copy_links),
+            sync_copy_links,
+            sync_ignore_git: config.sync_ignore_git.unwrap_or(false),
+            remote_env: config.remote_env,
+            execution_allocate_tty: config.execution_allocate_tty.unwrap_or(false),
+            // Anyone with root on remote machine can use forwarded agent while command runs, so it's opt-in.
+            remote_machine_forward_agent: config.remote_machine_forward_agent.unwrap_or(false),
+            sync_delete_extraneous: config.sync_delete_extraneous.unwrap_or(true),
+            sync_rsync_path: config.sync_rsync_path.map_or_else(|| String::from("rsync"), |value| expand_tilde(&value, env::var("HOME").ok())),
+            remote_machine_ssh_path: config.remote_machine_ssh_path.map_or_else(|| String::from("ssh"), |value| expand_tilde(&value, env::var("HOME").ok())),
+            remote_machine_shell: config.remote_machine_shell,
+            watch_debounce_millis: config.watch_debounce_millis.unwrap_or(500),
+            remote_machine_strict_host_key_checking: config.remote_machine_strict_host_key_checking.unwrap_or(false),
+            remote_machine_known_hosts_file: config.remote_machine_known_hosts_file.map(|value| expand_tilde(&value, env::var("HOME").ok())),
+            execution_working_dir: config.execution_working_dir,
+            execution_source_profile: config.execution_source_profile,
+            execution_require_remote_tools: config.execution_require_remote_tools.unwrap_or_default(),
+            execution_nice: config.execution_nice,
+            execution_ionice: config.execution_ionice.unwrap_or(false),
+            execution_result_paths: config.execution_result_paths.unwrap_or_default(),
+            sync_io_timeout_seconds: config.sync_io_timeout_seconds,
+            remote_machine_ssh_options: config.remote_machine_ssh_options.unwrap_or_default(),
+            remote_machine_transport: config.remote_machine_transport.unwrap_or_default(),
+            remote_machine_module: config.remote_machine_module,
+            sync_whole_file: config.sync_whole_file,
+            sync_max_file_size_upload: config.sync_max_file_size_upload,
+            sync_max_file_size_download: config.sync_max_file_size_download,
+            sync_parallelism: config.sync_parallelism,
+            sync_partial_dir: config.sync_partial_dir.unwrap_or(None),
+            local_compression_level_auto: config.compression_auto.unwrap_or(false) && config.local_compression_level.or(config.compression_level).is_none(),
+            remote_compression_level_auto: config.compression_auto.unwrap_or(false) && config.remote_compression_level.or(config.compression_level).is_none(),
+            compression_skip_compress: config.compression_skip_compress.unwrap_or_else(default_skip_compress),
+        })
+    }
+
+    // Resolved values in config file format to see which of the merged ones won, unset ones are left empty.
+    pub fn format_resolved(&self) -> String {
+        let mut lines = vec![
+            format!("remote_machine={}", self.remote_machine_name),
+            format!("remote_machine_user={}", format_optional(&self.remote_machine_user)),
+            format!("remote_machine_port={}", format_optional(&self.remote_machine_port)),
+            format!("remote_machine_identity_file={}", format_optional(&self.remote_machine_identity_file)),
+            format!("remote_machine_proxy_jump={}", format_optional(&self.remote_machine_proxy_jump)),
+            format!("remote_machine_keep_alive_interval_seconds={}", format_optional(&self.remote_machine_keep_alive_interval_seconds)),
+            format!("remote_machine_keep_alive_count_max={}", format_optional(&self.remote_machine_keep_alive_count_max)),
+            format!("remote_machine_connect_timeout_seconds={}", format_optional(&self.remote_machine_connect_timeout_seconds)),
+            format!("remote_machine_multiplex={}", self.remote_machine_multiplex),
+            format!("remote_machine_forward_agent={}", self.remote_machine_forward_agent),
+            format!("remote_machine_ssh_path={}", self.remote_machine_ssh_path),
+            format!("remote_machine_ssh_options={}", self.remote_machine_ssh_options.join(" ")),
+            format!("remote_machine_shell={}", format_optional(&self.remote_machine_shell)),
+            format!("remote_machine_strict_host_key_checking={}", self.remote_machine_strict_host_key_checking),
+            format!("remote_machine_known_hosts_file={}", format_optional(&self.remote_machine_known_hosts_file)),
+            format!("remote_machine_transport={}", match self.remote_machine_transport { Transport::Ssh => "ssh", Transport::Rsyncd => "rsyncd" }),
+            format!("remote_machine_module={}", format_optional(&self.remote_machine_module)),
+            // Auto-tuned levels are left unset so the output parses back into the same config.
+            format!("local_compression_level={}", if self.local_compression_level_auto { String::new() } else { self.local_compression_level.to_string() }),
+            format!("remote_compression_level={}", if self.remote_compression_level_auto { String::new() } else { self.remote_compression_level.to_string() }),
+            format!("compression_auto={}", self.local_compression_level_auto || self.remote_compression_level_auto),
+            format!("compression_skip_compress={}", self.compression_skip_compress.join(" ")),
+            format!("rsync_args={}", self.rsync_args.join(" ")),
+            format!("bandwidth_limit_kbps={}", format_optional(&self.bandwidth_limit_kbps)),
+            format!("sync_checksum={}", self.sync_checksum),
+            format!("sync_check_clock_skew={}", self.sync_check_clock_skew),
+            format!("sync_whole_file={}", format_optional(&self.sync_whole_file)),
+            format!("sync_max_file_size_upload={}", format_optional(&self.sync_max_file_size_upload)),
+            format!("sync_max_file_size_download={}", format_optional(&self.sync_max_file_size_download)),
+            format!("sync_parallelism={}", format_optional(&self.sync_parallelism)),
+            format!("sync_partial_dir={}", format_optional(&self.sync_partial_dir)),
+            format!("sync_delete_extraneous={}", self.sync_delete_extraneous),
+            format!("sync_rsync_path={}", self.sync_rsync_path),
+            format!("sync_preserve_permissions={}", self.sync_preserve_permissions),
+            format!("sync_preserve_times={}", self.sync_preserve_times),
+            format!("sync_preserve_links={}", self.sync_preserve_links),
+            format!("sync_copy_links={}", self.sync_copy_links),
+            format!("sync_ignore_git={}", self.sync_ignore_git),
+            format!("sync_io_timeout_seconds={}", format_optional(&self.sync_io_timeout_seconds)),
+            format!("sync_back_on_failure={}", self.sync_back_on_failure),
+            format!("watch_debounce_millis={}", self.watch_debounce_millis),
+            format!("remote_folder_prefix={}", self.remote_folder_prefix),
+            format!("dry_run={}", self.dry_run),
+            format!("timeout_seconds={}", format_optional(&self.timeout_seconds)),
+            format!("execution_allocate_tty={}", self.execution_allocate_tty),
+            format!("execution_working_dir={}", format_optional(&self.execution_working_dir)),
+            format!("execution_source_profile={}", format_optional(&self.execution_source_profile)),
+            format!("execution_require_remote_tools={}", self.execution_require_remote_tools.join(" ")),
+            format!("execution_nice={}", format_optional(&self.execution_nice)),
+            format!("execution_ionice={}", self.execution_ionice),
+            format!("execution_result_paths={}", self.execution_result_paths.join(" ")),
+            format!("before_upload_hook={}", format_optional(&self.before_upload_hook)),
+            format!("after_download_hook={}", format_optional(&self.after_download_hook)),
+        ];
+
+        lines.extend(self.remote_env.iter().map(|(name, value)| format!("remote_env.{}={}", name, value)));
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    // SSH destination of the remote machine, "user@host" if user is set separately.
+    // IPv6 host is passed to ssh without brackets, i.e. "test@::1".
+    pub fn remote_machine_target(&self) -> String {
+        self.format_remote_machine_target(false)
+    }
+
+    // rsync splits destination on ":" so IPv6 host has to be bracketed, i.e. "test@[::1]".
+    pub fn remote_machine_rsync_target(&self) -> String {
+        self.format_remote_machine_target(true)
+    }
+
+    fn format_remote_machine_target(&self, bracket_ipv6: bool) -> String {
+        let (user, host) = match self.remote_machine_name.rfind('@') {
+            None => ("", self.remote_machine_name.as_str()),
+            Some(index) => self.remote_machine_name.split_at(index + 1)
+        };
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        let host = if bracket_ipv6 && host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            String::from(host)
+        };
+
+        match self.remote_machine_user {
+            None => format!("{}{}", user, host),
+            Some(ref remote_machine_user) => format!("{}@{}{}", remote_machine_user, user, host)
+        }
+    }
+}
+
+fn format_optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+// Directory of global configuration: "${XDG_CONFIG_HOME}/mainframer" or "${HOME}/.config/mainframer".
+pub fn global_config_dir() -> Option<PathBuf> {
+    resolve_global_config_dir(env::var("XDG_CONFIG_HOME").ok(), env::var("HOME").ok())
+}
+
+fn resolve_global_config_dir(xdg_config_home: Option<String>, home_dir: Option<String>) -> Option<PathBuf> {
+    match (xdg_config_home.filter(|value| !value.is_empty()), home_dir) {
+        (Some(xdg_config_home), _) => Some(Path::new(&xdg_config_home).join("mainframer")),
+        (None, Some(home_dir)) => Some(Path::new(&home_dir).join(".config/mainframer")),
+        (None, None) => None
+    }
+}
+
+// Global config is optional, project one is merged on top of it.
+pub fn global_config_file() -> Option<PathBuf> {
+    global_config_dir().map(|dir| dir.join("config")).filter(|file| file.exists())
+}
+
+fn read_optional_global_config(file_path: Option<&Path>) -> Result<IntermediateConfig, ConfigError> {
+    match file_path {
+        None => Ok(IntermediateConfig::default()),
+        Some(file_path) => read_global_config(file_path)
+    }
+}
+
+fn read_layer(file_path: &Path) -> Result<IntermediateConfig, ConfigError> {
+    if file_path == Path::new("-") {
+        return parse_layer(io::stdin(), layer_source_path(file_path));
+    }
+
+    match File::open(file_path) {
+        Err(_) => Err(ConfigError::FileNotFound { path: file_path.to_path_buf() }),
+        Ok(file) => parse_layer(file, file_path)
+    }
+}
+
+// Stdin is named in messages, "extends" of it are resolved relative to working dir.
+fn layer_source_path(file_path: &Path) -> &Path {
+    if file_path == Path::new("-") {
+        Path::new("<stdin>")
+    } else {
+        file_path
+    }
+}
+
+fn parse_layer<R: Read>(reader: R, source_path: &Path) -> Result<IntermediateConfig, ConfigError> {
+    read_config_from(reader, source_path, &mut vec![])
+}
+
+fn read_global_config(file_path: &Path) -> Result<IntermediateConfig, ConfigError> {
+    read_config(file_path, &mut vec![])
+}
+
+// Reads config merged on top of configs it "extends", the same way as layers and global config are merged.
+fn read_config(file_path: &Path, extending_files: &mut Vec<PathBuf>) -> Result<IntermediateConfig, ConfigError> {
+    match File::open(file_path) {
+        Err(_) => Err(ConfigError::FileNotFound { path: file_path.to_path_buf() }),
+        Ok(file) => read_config_from(file, file_path, extending_files)
+    }
+}
+
+fn read_config_from<R: Read>(mut reader: R, file_path: &Path, extending_files: &mut Vec<PathBuf>) -> Result<IntermediateConfig, ConfigError> {
+    let mut content = String::new();
+
+    if reader.read_to_string(&mut content).is_err() {
+        return Err(ConfigError::Unreadable { path: file_path.to_path_buf() });
+    }
+
+    let canonical_file_path = fs::canonicalize(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+
+    if extending_files.contains(&canonical_file_path) {
+        extending_files.push(canonical_file_path);
+
+        return Err(ConfigError::Invalid(format!(
+            "Config files extend each other: {}.",
+            extending_files.iter().map(|path| format!("'{}'", path.to_string_lossy())).collect::<Vec<String>>().join(" → ")
+        )));
+    }
+
+    extending_files.push(canonical_file_path);
+
+    let config = match validate_syntax(&content).and_then(|_| parse_config_from_str(&content)) {
+        Err(error) => return Err(ConfigError::in_file(file_path, error)),
+        Ok(config) => config
+    };
+
+    match find_value(&content, "extends") {
+        None => Ok(config),
+        Some(base_file) => {
+            let base_file_path = file_path.parent().unwrap_or_else(|| Path::new("")).join(base_file);
+            let base_config = read_config(&base_file_path, extending_files)?;
+
+            Ok(merge(base_config, config))
+        }
+    }
 }
 
 // Parses config content as https://en.wikipedia.org/wiki/.properties
 fn find_value(config_content: &str, key: &str) -> Option<String> {
-    match config_content.find(&format!("{}=", key)) {
+    match config_content.lines().filter_map(parse_line).find(|&(line_key, _)| line_key == key) {
         None => None,
-        Some(start_index) => {
-            let content_starting_with_key = &config_content[start_index..config_content.len()];
-            let value_start_index = match content_starting_with_key.find('=') {
-                None => return None,
-                Some(index) => index
-            };
-
-            let line_end_index = match content_starting_with_key.find('\n') {
-                None => content_starting_with_key.len(),
-                Some(index) => index
-            };
-
-            let value = (&content_starting_with_key[value_start_index + 1..line_end_index]).trim();
-
-            match value.len() {
-                0 => None,
-                _ => Some(String::from(value))
-            }
+        Some((_, value)) => match value.len() {
+            0 => None,
+            _ => Some(String::from(value))
         }
     }
 }
 
-fn parse_config_from_str(config_content: &str) -> Result<Config, String> {
-    Ok(Config {
-        remote_machine_name: match find_value(&config_content, &"remote_machine") {
-            None => return Err("please specify 'remote_machine'.".to_string()),
-            Some(value) => value
-        },
-        local_compression_level: match find_value(&config_content, &"local_compression_level") {
-            None => 1,
-            Some(value) => match value.parse() {
-                Err(_) => return Err(format!("'local_compression_level' must be a positive number, found '{}'.", value)),
-                Ok(value) => value
-            }
-        },
-        remote_compression_level: match find_value(&config_content, &"remote_compression_level") {
-            None => 1,
-            Some(value) => match value.parse() {
-                Err(_) => return Err(format!("'remote_compression_level' must be a positive number, found '{}'.", value)),
-                Ok(value) => value
+// Named machines are specified as "remote_machine.{name}" keys.
+fn find_named_remote_machines(config_content: &str) -> Result<BTreeMap<String, String>, ConfigError> {
+    let mut remote_machines = BTreeMap::new();
+
+    for (key, value) in config_content.lines().filter_map(parse_line) {
+        let name = match key.strip_prefix("remote_machine.") {
+            None => continue,
+            Some(name) => name
+        };
+
+        // Unlike other keys an empty named machine can't mean "not set", it's most likely a forgotten host.
+        if name.is_empty() || value.is_empty() {
+            return Err(ConfigError::Invalid(format!("'{}' is present but empty, remove it or set machine host.", key)));
+        }
+
+        remote_machines.insert(String::from(name), String::from(value));
+    }
+
+    Ok(remote_machines)
+}
+
+// Environment variables of remote command are specified as "remote_env.{NAME}" keys, empty value is a valid one.
+fn find_remote_env(config_content: &str) -> Result<BTreeMap<String, String>, ConfigError> {
+    let mut remote_env = BTreeMap::new();
+
+    for (key, value) in config_content.lines().filter_map(parse_line) {
+        let name = match key.strip_prefix("remote_env.") {
+            None => continue,
+            Some(name) => name
+        };
+
+        let is_valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        if !is_valid_name {
+            return Err(ConfigError::Invalid(format!("'{}' must name an environment variable with letters, digits and '_', found '{}'.", key, name)));
+        }
+
+        remote_env.insert(String::from(name), String::from(value));
+    }
+
+    Ok(remote_env)
+}
+
+// Profiles are specified as "profile.{name}.{key}" keys where "{key}" is any other config key.
+fn find_profiles(config_content: &str) -> Result<BTreeMap<String, IntermediateConfig>, ConfigError> {
+    let mut profile_contents: BTreeMap<&str, String> = BTreeMap::new();
+
+    for (key, value) in config_content.lines().filter_map(parse_line) {
+        let name_and_key = match key.strip_prefix("profile.") {
+            None => continue,
+            Some(name_and_key) => name_and_key
+        };
+
+        match name_and_key.find('.') {
+            Some(index) if index > 0 && index + 1 < name_and_key.len() => profile_contents
+                .entry(&name_and_key[..index])
+                .or_default()
+                .push_str(&format!("{}={}\n", &name_and_key[index + 1..], value)),
+            _ => return Err(ConfigError::Invalid(format!("'{}' must be in 'profile.{{name}}.{{key}}' form.", key)))
+        }
+    }
+
+    let mut profiles = BTreeMap::new();
+    let mut errors = Vec::new();
+
+    for (name, content) in profile_contents {
+        let result = match parse_config_from_str(&content) {
+            Ok(ref profile) if !profile.profiles.is_empty() => Err(ConfigError::Invalid(String::from("profiles can't be nested."))),
+            result => result
+        };
+
+        match result {
+            Err(error) => errors.push(ConfigError::InProfile { profile: String::from(name), error: Box::new(error) }),
+            Ok(profile) => {
+                profiles.insert(String::from(name), profile);
             }
-        },
-    })
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(profiles),
+        _ => Err(ConfigError::from_errors(errors))
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Profile values override top-level ones, other values are inherited.
+pub fn apply_profile(mut config: IntermediateConfig, profile: Option<&str>) -> Result<IntermediateConfig, ConfigError> {
+    let name = match profile {
+        None => return Ok(config),
+        Some(name) => name
+    };
 
-    #[test]
-    fn find_value_no_new_line_at_the_end() {
-        let content = "key=value";
-        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    match config.profiles.remove(name) {
+        Some(profile_config) => Ok(merge(config, profile_config)),
+        None if config.profiles.is_empty() => Err(ConfigError::Invalid(format!("profile '{}' is not defined.", name))),
+        None => Err(ConfigError::Invalid(format!(
+            "profile '{}' is not defined, available: {}.",
+            name,
+            config.profiles.keys().cloned().collect::<Vec<String>>().join(", ")
+        )))
     }
+}
 
-    #[test]
-    fn find_value_new_line_at_the_end() {
-        let content = "key=value\n";
-        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+// Reports lines that are neither "key=value" pairs, comments nor blank.
+fn validate_syntax(config_content: &str) -> Result<(), ConfigError> {
+    for (line_index, line) in config_content.lines().enumerate() {
+        let trimmed_line = line.trim_start();
+        let column = line.len() - trimmed_line.len() + 1;
+
+        if trimmed_line.is_empty() || is_comment(line) {
+            continue;
+        }
+
+        match parse_line(line) {
+            None => return Err(ConfigError::Syntax {
+                line: line_index + 1,
+                column,
+                message: format!("expected 'key=value', found '{}'.", trimmed_line.trim_end()),
+            }),
+            Some(("", _)) => return Err(ConfigError::Syntax {
+                line: line_index + 1,
+                column,
+                message: String::from("key is missing before '='."),
+            }),
+            Some(_) => ()
+        }
     }
 
-    #[test]
-    fn find_value_trims_value() {
-        let content = "key=value  ";
-        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    Ok(())
+}
+
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    if is_comment(line) {
+        return None;
     }
 
-    #[test]
-    fn find_value_no_value_after_equals_sign() {
-        let content = "key=";
-        assert_eq!(find_value(content, "key"), None);
+    line.find('=').map(|separator_index| (line[..separator_index].trim(), line[separator_index + 1..].trim()))
+}
+
+fn is_comment(line: &str) -> bool {
+    let trimmed_line = line.trim_start();
+    trimmed_line.starts_with('#') || trimmed_line.starts_with('!')
+}
+
+// ssh does not expand "~" in "-i" value when it's passed as a separate argument.
+fn expand_tilde(path: &str, home_dir: Option<String>) -> String {
+    match home_dir {
+        Some(ref home_dir) if path == "~" => home_dir.clone(),
+        Some(ref home_dir) if path.starts_with("~/") => format!("{}{}", home_dir, &path[1..]),
+        _ => String::from(path)
     }
+}
 
-    #[test]
-    fn find_value_whitespace_instead_of_value() {
-        let content = "key= ";
-        assert_eq!(find_value(content, "key"), None);
+// ssh would resolve relative path against its working dir, make it relative to config file instead.
+fn resolve_referenced_file(key: &str, file: &str, config_dir: &Path, home_dir: Option<String>) -> Result<String, ConfigError> {
+    let path = PathBuf::from(expand_tilde(file, home_dir));
+
+    let path = if path.is_relative() {
+        config_dir.join(path)
+    } else {
+        path
+    };
+
+    if path.exists() {
+        Ok(path.to_string_lossy().into_owned())
+    } else {
+        Err(ConfigError::Invalid(format!("'{}' points to missing file '{}'.", key, path.to_string_lossy())))
     }
+}
 
-    #[test]
-    fn parse_config_from_str_all_fields() {
-        let content = "remote_machine=test@machine\nlocal_compression_level=2\nremote_compression_level=3";
-        assert_eq!(parse_config_from_str(content), Ok(Config {
-            remote_machine_name: String::from("test@machine"),
-            local_compression_level: 2,
-            remote_compression_level: 3,
-        }));
+fn select_remote_machine(default_remote_machine: Option<String>, named_remote_machines: &BTreeMap<String, String>, remote_machine: Option<&str>) -> Result<String, ConfigError> {
+    match (default_remote_machine, remote_machine) {
+        (Some(_), _) if !named_remote_machines.is_empty() => Err(ConfigError::Invalid(String::from("please specify either 'remote_machine' or 'remote_machine.{name}' keys, not both."))),
+        (Some(value), None) => Ok(value),
+        (None, None) if named_remote_machines.is_empty() => Err(ConfigError::Invalid(String::from("please specify 'remote_machine'."))),
+        (None, None) => Err(ConfigError::Invalid(format!(
+            "please pick remote machine with '--machine', available: {}.",
+            named_remote_machines.keys().cloned().collect::<Vec<String>>().join(", ")
+        ))),
+        (_, Some(name)) => match named_remote_machines.get(name) {
+            None => Err(ConfigError::Invalid(format!("please specify 'remote_machine.{}'.", name))),
+            Some(value) => Ok(value.clone())
+        }
     }
+}
 
-    #[test]
-    fn parse_config_from_str_all_fields_unordered() {
-        let content = "remote_compression_level=3\nremote_machine=test@machine\nlocal_compression_level=2";
-        assert_eq!(parse_config_from_str(content), Ok(Config {
-            remote_machine_name: String::from("test@machine"),
-            local_compression_level: 2,
-            remote_compression_level: 3,
-        }));
+fn validate_keys(config_content: &str) -> Result<(), ConfigError> {
+    let errors: Vec<ConfigError> = config_content.lines()
+        .filter_map(parse_line)
+        .map(|(key, _)| key)
+        .filter(|key| !KNOWN_KEYS.contains(key) && !key.starts_with("remote_machine.") && !key.starts_with("remote_env.") && !key.starts_with("profile."))
+        .map(|key| ConfigError::UnknownKey { key: String::from(key), suggestion: suggest_key(key) })
+        .collect();
+
+    match errors.len() {
+        0 => Ok(()),
+        _ => Err(ConfigError::from_errors(errors))
     }
+}
 
-    #[test]
-    fn parse_config_from_str_only_remote_machine_name() {
-        let content = "remote_machine=test@machine";
-        assert_eq!(parse_config_from_str(content), Ok(Config {
-            remote_machine_name: String::from("test@machine"),
-            local_compression_level: 1, // Default value.
-            remote_compression_level: 1, // Default value.
-        }));
+fn suggest_key(unknown_key: &str) -> Option<&'static str> {
+    KNOWN_KEYS.iter()
+        .map(|known_key| (edit_distance(unknown_key, known_key), *known_key))
+        .filter(|&(distance, _)| distance <= 2)
+        .min()
+        .map(|(_, known_key)| known_key)
+}
+
+// Levenshtein distance.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b_chars.len()).collect();
+
+    for (a_index, a_char) in a.chars().enumerate() {
+        let mut current_row = vec![a_index + 1];
+
+        for (b_index, b_char) in b_chars.iter().enumerate() {
+            let substitution_cost = if a_char == *b_char { 0 } else { 1 };
+
+            current_row.push(*[
+                previous_row[b_index + 1] + 1,
+                current_row[b_index] + 1,
+                previous_row[b_index] + substitution_cost,
+            ].iter().min().unwrap());
+        }
+
+        previous_row = current_row;
     }
 
-    #[test]
-    fn parse_config_from_str_no_remote_machine_name() {
-        let content = "local_compression_level=2\nremote_compression_level=3";
-        assert_eq!(parse_config_from_str(content), Err(String::from("please specify 'remote_machine'.")));
+    previous_row[b_chars.len()]
+}
+
+fn parse_positive_number(config_content: &str, key: &str) -> Result<Option<u32>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(number) if number > 0 => Ok(Some(number)),
+            _ => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a positive number", found: value })
+        }
     }
+}
 
-    #[test]
-    fn parse_config_from_str_local_compression_level_not_a_number() {
-        let content = "remote_machine=test@machine\nlocal_compression_level=yooo";
-        assert_eq!(parse_config_from_str(content), Err(String::from("'local_compression_level' must be a positive number, found 'yooo'.")));
+// "true" picks the default dir. Relative dir is created next to every file being transferred, so it can't go up.
+fn parse_partial_dir(config_content: &str, key: &str) -> Result<Option<Option<String>>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(ref value) if value == "true" => Ok(Some(Some(String::from(DEFAULT_PARTIAL_DIR)))),
+        Some(ref value) if value == "false" => Ok(Some(None)),
+        Some(value) => if value.split('/').any(|component| component == "..") {
+            Err(ConfigError::TypeMismatch { key: String::from(key), expected: "either 'true', 'false' or a dir path without '..'", found: value })
+        } else {
+            Ok(Some(Some(value)))
+        }
+    }
+}
+
+// Range is up to the user, i.e. parallelism is clamped rather than rejected.
+fn parse_integer(config_content: &str, key: &str) -> Result<Option<i64>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(number) => Ok(Some(number)),
+            _ => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a number", found: value })
+        }
     }
+}
 
-    #[test]
-    fn parse_config_from_str_remote_compression_level_not_a_number() {
-        let content = "remote_machine=test@machine\nremote_compression_level=wut";
-        assert_eq!(parse_config_from_str(content), Err(String::from("'remote_compression_level' must be a positive number, found 'wut'.")));
+// Same range as "nice" accepts, negative ones need root on remote machine.
+fn parse_nice(config_content: &str, key: &str) -> Result<Option<i64>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(niceness) if (-20..=19).contains(&niceness) => Ok(Some(niceness)),
+            _ => Err(ConfigError::OutOfRange { key: String::from(key), min: -20, max: 19, found: value })
+        }
+    }
+}
+
+// Trailing "/" is dropped, "." means project dir itself.
+fn parse_project_relative_path(config_content: &str, key: &str) -> Result<Option<String>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => {
+            if !is_inside_project_dir(&value) {
+                Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a path inside of project dir", found: value })
+            } else {
+                Ok(Some(String::from(value.trim_end_matches('/'))).filter(|path| !path.is_empty() && path != "."))
+            }
+        }
+    }
+}
+
+// Whitespace-separated, same rules as for a single path apply to each one.
+fn parse_project_relative_paths(config_content: &str, key: &str) -> Result<Option<Vec<String>>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.split_whitespace().find(|path| !is_inside_project_dir(path)) {
+            Some(path) => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a whitespace-separated list of paths inside of project dir", found: String::from(path) }),
+            None => Ok(Some(value.split_whitespace().map(|path| String::from(path.trim_end_matches('/'))).collect()))
+        }
+    }
+}
+
+fn is_inside_project_dir(path: &str) -> bool {
+    !path.starts_with('/') && !path.starts_with('~') && !path.split('/').any(|component| component == "..")
+}
+
+fn parse_boolean(config_content: &str, key: &str) -> Result<Option<bool>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(boolean) => Ok(Some(boolean)),
+            Err(_) => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "either 'true' or 'false'", found: value })
+        }
+    }
+}
+
+// Inclusive range of compression levels accepted by rsync (zlib), level 0 disables compression.
+pub fn compression_level_bounds() -> (u8, u8) {
+    (COMPRESSION_LEVEL_MIN, COMPRESSION_LEVEL_MAX)
+}
+
+fn parse_compression_level(config_content: &str, key: &str) -> Result<Option<u8>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => validate_compression_level(key, &value).map(Some)
+    }
+}
+
+// Shared with command line options so both report invalid level the same way.
+pub fn validate_compression_level(key: &str, value: &str) -> Result<u8, ConfigError> {
+    let (min, max) = compression_level_bounds();
+
+    match value.parse() {
+        Ok(level) if level >= min && level <= max => Ok(level),
+        _ => Err(ConfigError::OutOfRange { key: String::from(key), min: i64::from(min), max: i64::from(max), found: String::from(value) })
+    }
+}
+
+pub fn parse_config_from_str(config_content: &str) -> Result<IntermediateConfig, ConfigError> {
+    let mut errors = Vec::new();
+
+    collect_error(validate_keys(config_content), &mut errors);
+
+    let config = IntermediateConfig {
+        remote_machine: find_value(config_content, "remote_machine"),
+        remote_machines: collect_error(find_named_remote_machines(config_content), &mut errors),
+        remote_machine_user: find_value(config_content, "remote_machine_user"),
+        remote_machine_port: collect_error(parse_port(config_content, "remote_machine_port"), &mut errors),
+        remote_machine_identity_file: find_value(config_content, "remote_machine_identity_file"),
+        remote_machine_proxy_jump: collect_error(parse_proxy_jump(config_content, "remote_machine_proxy_jump"), &mut errors),
+        remote_machine_keep_alive_interval_seconds: collect_error(parse_positive_number(config_content, "remote_machine_keep_alive_interval_seconds"), &mut errors),
+        remote_machine_keep_alive_count_max: collect_error(parse_positive_number(config_content, "remote_machine_keep_alive_count_max"), &mut errors),
+        remote_machine_connect_timeout_seconds: collect_error(parse_positive_number(config_content, "remote_machine_connect_timeout_seconds"), &mut errors),
+        compression_level: collect_error(parse_compression_level(config_content, "compression_level"), &mut errors),
+        local_compression_level: collect_error(parse_compression_level(config_content, "local_compression_level"), &mut errors),
+        remote_compression_level: collect_error(parse_compression_level(config_content, "remote_compression_level"), &mut errors),
+        compression_auto: collect_error(parse_boolean(config_content, "compression_auto"), &mut errors),
+        compression_skip_compress: collect_error(parse_extensions(config_content, "compression_skip_compress"), &mut errors),
+        // Passed to rsync as is, separated by whitespace.
+        rsync_args: find_value(config_content, "rsync_args")
+            .map(|value| value.split_whitespace().map(String::from).collect()),
+        bandwidth_limit_kbps: collect_error(parse_positive_number(config_content, "bandwidth_limit_kbps"), &mut errors),
+        remote_folder_prefix: find_value(config_content, "remote_folder_prefix")
+            .map(|value| String::from(value.trim_end_matches('/'))),
+        dry_run: collect_error(parse_boolean(config_content, "dry_run"), &mut errors),
+        timeout_seconds: collect_error(parse_positive_number(config_content, "timeout_seconds"), &mut errors),
+        // Local shell commands, run in project dir.
+        before_upload_hook: find_value(config_content, "before_upload_hook"),
+        after_download_hook: find_value(config_content, "after_download_hook"),
+        sync_back_on_failure: collect_error(parse_boolean(config_content, "sync_back_on_failure"), &mut errors),
+        remote_machine_multiplex: collect_error(parse_boolean(config_content, "remote_machine_multiplex"), &mut errors),
+        sync_checksum: collect_error(parse_boolean(config_content, "sync_checksum"), &mut errors),
+        sync_check_clock_skew: collect_error(parse_boolean(config_content, "sync_check_clock_skew"), &mut errors),
+        sync_preserve_permissions: collect_error(parse_boolean(config_content, "sync_preserve_permissions"), &mut errors),
+        sync_preserve_times: collect_error(parse_boolean(config_content, "sync_preserve_times"), &mut errors),
+        sync_preserve_links: collect_error(parse_boolean(config_content, "sync_preserve_links"), &mut errors),
+        sync_copy_links: collect_error(parse_boolean(config_content, "sync_copy_links"), &mut errors),
+        sync_ignore_git: collect_error(parse_boolean(config_content, "sync_ignore_git"), &mut errors),
+        remote_env: collect_error(find_remote_env(config_content), &mut errors),
+        profiles: collect_error(find_profiles(config_content), &mut errors),
+        execution_allocate_tty: collect_error(parse_boolean(config_content, "execution_allocate_tty"), &mut errors),
+        remote_machine_forward_agent: collect_error(parse_boolean(config_content, "remote_machine_forward_agent"), &mut errors),
+        sync_delete_extraneous: collect_error(parse_boolean(config_content, "sync_delete_extraneous"), &mut errors),
+        // Binaries to run locally, looked up in PATH unless they contain a path.
+        sync_rsync_path: find_value(config_content, "sync_rsync_path"),
+        remote_machine_ssh_path: find_value(config_content, "remote_machine_ssh_path"),
+        remote_machine_shell: find_value(config_content, "remote_machine_shell"),
+        watch_debounce_millis: collect_error(parse_positive_number(config_content, "watch_debounce_millis"), &mut errors),
+        remote_machine_strict_host_key_checking: collect_error(parse_boolean(config_content, "remote_machine_strict_host_key_checking"), &mut errors),
+        remote_machine_known_hosts_file: find_value(config_content, "remote_machine_known_hosts_file"),
+        execution_working_dir: collect_error(parse_project_relative_path(config_content, "execution_working_dir"), &mut errors),
+        execution_source_profile: find_value(config_content, "execution_source_profile"),
+        execution_require_remote_tools: collect_error(parse_tools(config_content, "execution_require_remote_tools"), &mut errors),
+        execution_nice: collect_error(parse_nice(config_content, "execution_nice"), &mut errors),
+        execution_ionice: collect_error(parse_boolean(config_content, "execution_ionice"), &mut errors),
+        execution_result_paths: collect_error(parse_project_relative_paths(config_content, "execution_result_paths"), &mut errors),
+        sync_io_timeout_seconds: collect_error(parse_positive_number(config_content, "sync_io_timeout_seconds"), &mut errors),
+        remote_machine_ssh_options: collect_error(parse_ssh_options(config_content, "remote_machine_ssh_options"), &mut errors),
+        remote_machine_transport: collect_error(parse_transport(config_content, "remote_machine_transport"), &mut errors),
+        remote_machine_module: collect_error(parse_module(config_content, "remote_machine_module"), &mut errors),
+        sync_whole_file: collect_error(parse_boolean(config_content, "sync_whole_file"), &mut errors),
+        sync_max_file_size_upload: collect_error(parse_file_size(config_content, "sync_max_file_size_upload"), &mut errors),
+        sync_max_file_size_download: collect_error(parse_file_size(config_content, "sync_max_file_size_download"), &mut errors),
+        sync_parallelism: collect_error(parse_integer(config_content, "sync_parallelism"), &mut errors),
+        sync_partial_dir: collect_error(parse_partial_dir(config_content, "sync_partial_dir"), &mut errors),
+    };
+
+    match errors.len() {
+        0 => Ok(config),
+        _ => Err(ConfigError::from_errors(errors))
+    }
+}
+
+// Keeps parsing after invalid value so all errors are reported at once.
+fn collect_error<T: Default>(result: Result<T, ConfigError>, errors: &mut Vec<ConfigError>) -> T {
+    result.unwrap_or_else(|error| {
+        match error {
+            ConfigError::Multiple(several_errors) => errors.extend(several_errors),
+            error => errors.push(error)
+        }
+        T::default()
+    })
+}
+
+fn parse_port(config_content: &str, key: &str) -> Result<Option<u16>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.parse() {
+            Ok(port) if port >= 1 => Ok(Some(port)),
+            _ => Err(ConfigError::OutOfRange { key: String::from(key), min: 1, max: i64::from(u16::MAX), found: value })
+        }
+    }
+}
+
+// Several hops are separated with commas same as in "ssh -J".
+fn parse_proxy_jump(config_content: &str, key: &str) -> Result<Option<String>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => if value.split(',').all(|hop| !hop.is_empty() && !hop.contains(char::is_whitespace)) {
+            Ok(Some(value))
+        } else {
+            Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a comma-separated list of hosts", found: value })
+        }
+    }
+}
+
+// Options are separated by whitespace, each one in "Name=Value" form same as in "ssh -o".
+fn parse_ssh_options(config_content: &str, key: &str) -> Result<Option<Vec<String>>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => {
+            let options: Vec<String> = value.split_whitespace().map(String::from).collect();
+
+            match options.iter().find(|option| !is_ssh_option(option)) {
+                Some(option) => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a whitespace-separated list of 'Name=Value' ssh options", found: option.clone() }),
+                None => Ok(Some(options))
+            }
+        }
+    }
+}
+
+fn is_ssh_option(option: &str) -> bool {
+    match option.find('=') {
+        None => false,
+        Some(index) => index > 0 && option[..index].chars().all(|c| c.is_ascii_alphanumeric()) && index + 1 < option.len()
+    }
+}
+
+// Archives, media and packages, compressing them again only costs CPU.
+pub fn default_skip_compress() -> Vec<String> {
+    ["7z", "aar", "apk", "avi", "bz2", "deb", "gz", "iso", "jar", "jpeg", "jpg", "mkv", "mov", "mp3", "mp4", "png", "rar", "rpm", "tgz", "webm", "webp", "xz", "zip", "zst"]
+        .iter()
+        .map(|extension| String::from(*extension))
+        .collect()
+}
+
+// Without leading dot, rsync matches them against the end of file names.
+fn parse_extensions(config_content: &str, key: &str) -> Result<Option<Vec<String>>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => {
+            let extensions: Vec<String> = value.split_whitespace().map(String::from).collect();
+
+            match extensions.iter().find(|extension| !extension.chars().all(|c| c.is_ascii_alphanumeric())) {
+                Some(extension) => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a whitespace-separated list of file extensions like 'jpg zip'", found: extension.clone() }),
+                None => Ok(Some(extensions))
+            }
+        }
+    }
+}
+
+// Names or paths of binaries, anything else could be mistaken for shell syntax.
+fn parse_tools(config_content: &str, key: &str) -> Result<Option<Vec<String>>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => {
+            let tools: Vec<String> = value.split_whitespace().map(String::from).collect();
+
+            match tools.iter().find(|tool| !tool.chars().all(|c| c.is_ascii_alphanumeric() || "._+-/".contains(c))) {
+                Some(tool) => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a whitespace-separated list of binaries like 'gradle cargo'", found: tool.clone() }),
+                None => Ok(Some(tools))
+            }
+        }
+    }
+}
+
+fn parse_file_size(config_content: &str, key: &str) -> Result<Option<u64>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match parse_size(&value) {
+            Some(size) if size > 0 => Ok(Some(size)),
+            _ => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "a size like '512K', '100M' or '1G'", found: value })
+        }
+    }
+}
+
+// Bytes with optional binary suffix same as rsync accepts, i.e. "100M" is 100 MiB.
+fn parse_size(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], 1 << 10),
+        'M' => (&value[..value.len() - 1], 1 << 20),
+        'G' => (&value[..value.len() - 1], 1 << 30),
+        'T' => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1)
+    };
+
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_transport(config_content: &str, key: &str) -> Result<Option<Transport>, ConfigError> {
+    match find_value(config_content, key) {
+        None => Ok(None),
+        Some(value) => match value.as_str() {
+            "ssh" => Ok(Some(Transport::Ssh)),
+            "rsyncd" => Ok(Some(Transport::Rsyncd)),
+            _ => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "either 'ssh' or 'rsyncd'", found: value })
+        }
+    }
+}
+
+// Module is the first component of "rsync://host/module/path" so it can't contain "/".
+fn parse_module(config_content: &str, key: &str) -> Result<Option<String>, ConfigError> {
+    match find_value(config_content, key) {
+        Some(ref value) if value.contains('/') || value.contains(char::is_whitespace) => Err(ConfigError::TypeMismatch { key: String::from(key), expected: "an rsync daemon module name", found: value.clone() }),
+        value => Ok(value)
+    }
+}
+
+// Values of "override_config" win, base config machine is used only if override one doesn't specify any.
+pub fn merge(base_config: IntermediateConfig, override_config: IntermediateConfig) -> IntermediateConfig {
+    let overrides_remote_machine = override_config.remote_machine.is_some() || !override_config.remote_machines.is_empty();
+
+    let mut remote_env = base_config.remote_env;
+    remote_env.extend(override_config.remote_env);
+
+    // Profile with the same name replaces the base one as a whole.
+    let mut profiles = base_config.profiles;
+    profiles.extend(override_config.profiles);
+
+    IntermediateConfig {
+        remote_machine: if overrides_remote_machine { override_config.remote_machine } else { base_config.remote_machine },
+        remote_machines: if overrides_remote_machine { override_config.remote_machines } else { base_config.remote_machines },
+        remote_machine_user: override_config.remote_machine_user.or(base_config.remote_machine_user),
+        remote_machine_port: override_config.remote_machine_port.or(base_config.remote_machine_port),
+        remote_machine_identity_file: override_config.remote_machine_identity_file.or(base_config.remote_machine_identity_file),
+        remote_machine_proxy_jump: override_config.remote_machine_proxy_jump.or(base_config.remote_machine_proxy_jump),
+        remote_machine_keep_alive_interval_seconds: override_config.remote_machine_keep_alive_interval_seconds.or(base_config.remote_machine_keep_alive_interval_seconds),
+        remote_machine_keep_alive_count_max: override_config.remote_machine_keep_alive_count_max.or(base_config.remote_machine_keep_alive_count_max),
+        remote_machine_connect_timeout_seconds: override_config.remote_machine_connect_timeout_seconds.or(base_config.remote_machine_connect_timeout_seconds),
+        compression_level: override_config.compression_level.or(base_config.compression_level),
+        local_compression_level: override_config.local_compression_level.or(base_config.local_compression_level),
+        remote_compression_level: override_config.remote_compression_level.or(base_config.remote_compression_level),
+        compression_auto: override_config.compression_auto.or(base_config.compression_auto),
+        compression_skip_compress: override_config.compression_skip_compress.or(base_config.compression_skip_compress),
+        rsync_args: override_config.rsync_args.or(base_config.rsync_args),
+        bandwidth_limit_kbps: override_config.bandwidth_limit_kbps.or(base_config.bandwidth_limit_kbps),
+        remote_folder_prefix: override_config.remote_folder_prefix.or(base_config.remote_folder_prefix),
+        dry_run: override_config.dry_run.or(base_config.dry_run),
+        timeout_seconds: override_config.timeout_seconds.or(base_config.timeout_seconds),
+        before_upload_hook: override_config.before_upload_hook.or(base_config.before_upload_hook),
+        after_download_hook: override_config.after_download_hook.or(base_config.after_download_hook),
+        sync_back_on_failure: override_config.sync_back_on_failure.or(base_config.sync_back_on_failure),
+        remote_machine_multiplex: override_config.remote_machine_multiplex.or(base_config.remote_machine_multiplex),
+        sync_checksum: override_config.sync_checksum.or(base_config.sync_checksum),
+        sync_check_clock_skew: override_config.sync_check_clock_skew.or(base_config.sync_check_clock_skew),
+        sync_preserve_permissions: override_config.sync_preserve_permissions.or(base_config.sync_preserve_permissions),
+        sync_preserve_times: override_config.sync_preserve_times.or(base_config.sync_preserve_times),
+        sync_preserve_links: override_config.sync_preserve_links.or(base_config.sync_preserve_links),
+        sync_copy_links: override_config.sync_copy_links.or(base_config.sync_copy_links),
+        sync_ignore_git: override_config.sync_ignore_git.or(base_config.sync_ignore_git),
+        execution_allocate_tty: override_config.execution_allocate_tty.or(base_config.execution_allocate_tty),
+        remote_machine_forward_agent: override_config.remote_machine_forward_agent.or(base_config.remote_machine_forward_agent),
+        sync_delete_extraneous: override_config.sync_delete_extraneous.or(base_config.sync_delete_extraneous),
+        sync_rsync_path: override_config.sync_rsync_path.or(base_config.sync_rsync_path),
+        remote_machine_ssh_path: override_config.remote_machine_ssh_path.or(base_config.remote_machine_ssh_path),
+        remote_machine_shell: override_config.remote_machine_shell.or(base_config.remote_machine_shell),
+        watch_debounce_millis: override_config.watch_debounce_millis.or(base_config.watch_debounce_millis),
+        remote_machine_strict_host_key_checking: override_config.remote_machine_strict_host_key_checking.or(base_config.remote_machine_strict_host_key_checking),
+        remote_machine_known_hosts_file: override_config.remote_machine_known_hosts_file.or(base_config.remote_machine_known_hosts_file),
+        execution_working_dir: override_config.execution_working_dir.or(base_config.execution_working_dir),
+        execution_source_profile: override_config.execution_source_profile.or(base_config.execution_source_profile),
+        execution_require_remote_tools: override_config.execution_require_remote_tools.or(base_config.execution_require_remote_tools),
+        execution_nice: override_config.execution_nice.or(base_config.execution_nice),
+        execution_ionice: override_config.execution_ionice.or(base_config.execution_ionice),
+        execution_result_paths: override_config.execution_result_paths.or(base_config.execution_result_paths),
+        sync_io_timeout_seconds: override_config.sync_io_timeout_seconds.or(base_config.sync_io_timeout_seconds),
+        remote_machine_ssh_options: override_config.remote_machine_ssh_options.or(base_config.remote_machine_ssh_options),
+        remote_machine_transport: override_config.remote_machine_transport.or(base_config.remote_machine_transport),
+        remote_machine_module: override_config.remote_machine_module.or(base_config.remote_machine_module),
+        sync_whole_file: override_config.sync_whole_file.or(base_config.sync_whole_file),
+        sync_max_file_size_upload: override_config.sync_max_file_size_upload.or(base_config.sync_max_file_size_upload),
+        sync_max_file_size_download: override_config.sync_max_file_size_download.or(base_config.sync_max_file_size_download),
+        sync_parallelism: override_config.sync_parallelism.or(base_config.sync_parallelism),
+        sync_partial_dir: override_config.sync_partial_dir.or(base_config.sync_partial_dir),
+        remote_env,
+        profiles,
+    }
+}
+
+// Config without files around it, used by tests of other modules.
+#[cfg(test)]
+pub fn config_from_str(config_content: &str, remote_machine: Option<&str>) -> Result<Config, ConfigError> {
+    parse_config_from_str(config_content).and_then(|config| Config::resolve(config, remote_machine))
+}
+
+// Expands "${NAME}" and "${NAME:-default}" references to environment variables in string values.
+// "remote_env" values are left as is, they are expanded by remote shell with environment of remote machine.
+fn substitute_environment_variables(config: Config, environment_variable: &dyn Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+    let substitute = |value: &str, key: &str| substitute_environment_variables_in_value(value, key, environment_variable);
+
+    let substitute_optional = |value: Option<String>, key: &str| match value {
+        None => Ok(None),
+        Some(value) => substitute(&value, key).map(Some)
+    };
+
+    let substitute_list = |values: Vec<String>, key: &str| values.iter()
+        .map(|value| substitute(value, key))
+        .collect::<Result<Vec<String>, ConfigError>>();
+
+    Ok(Config {
+        remote_machine_name: substitute(&config.remote_machine_name, "remote_machine")?,
+        remote_machine_user: substitute_optional(config.remote_machine_user, "remote_machine_user")?,
+        remote_machine_identity_file: substitute_optional(config.remote_machine_identity_file, "remote_machine_identity_file")?,
+        remote_machine_proxy_jump: substitute_optional(config.remote_machine_proxy_jump, "remote_machine_proxy_jump")?,
+        remote_machine_known_hosts_file: substitute_optional(config.remote_machine_known_hosts_file, "remote_machine_known_hosts_file")?,
+        remote_machine_ssh_path: substitute(&config.remote_machine_ssh_path, "remote_machine_ssh_path")?,
+        remote_machine_ssh_options: substitute_list(config.remote_machine_ssh_options, "remote_machine_ssh_options")?,
+        remote_machine_shell: substitute_optional(config.remote_machine_shell, "remote_machine_shell")?,
+        remote_machine_module: substitute_optional(config.remote_machine_module, "remote_machine_module")?,
+        remote_folder_prefix: substitute(&config.remote_folder_prefix, "remote_folder_prefix")?,
+        rsync_args: substitute_list(config.rsync_args, "rsync_args")?,
+        before_upload_hook: substitute_optional(config.before_upload_hook, "before_upload_hook")?,
+        after_download_hook: substitute_optional(config.after_download_hook, "after_download_hook")?,
+        sync_rsync_path: substitute(&config.sync_rsync_path, "sync_rsync_path")?,
+        sync_partial_dir: substitute_optional(config.sync_partial_dir, "sync_partial_dir")?,
+        execution_working_dir: substitute_optional(config.execution_working_dir, "execution_working_dir")?,
+        execution_source_profile: substitute_optional(config.execution_source_profile, "execution_source_profile")?,
+        execution_require_remote_tools: substitute_list(config.execution_require_remote_tools, "execution_require_remote_tools")?,
+        execution_result_paths: substitute_list(config.execution_result_paths, "execution_result_paths")?,
+        compression_skip_compress: substitute_list(config.compression_skip_compress, "compression_skip_compress")?,
+        ..config
+    })
+}
+
+fn substitute_environment_variables_in_value(value: &str, key: &str, environment_variable: &dyn Fn(&str) -> Option<String>) -> Result<String, ConfigError> {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(reference_start_index) = rest.find("${") {
+        result.push_str(&rest[..reference_start_index]);
+
+        let reference = &rest[reference_start_index + 2..];
+
+        let reference_end_index = match reference.find('}') {
+            None => return Err(ConfigError::Invalid(format!("'{}' contains unterminated '${{' in '{}'.", key, value))),
+            Some(index) => index
+        };
+
+        let (name, default_value) = match reference[..reference_end_index].find(":-") {
+            None => (&reference[..reference_end_index], None),
+            Some(index) => (&reference[..index], Some(&reference[index + 2..reference_end_index]))
+        };
+
+        // Same as in shell, default value is used for both unset and empty variables.
+        let variable_value = match default_value {
+            None => environment_variable(name),
+            Some(default_value) => environment_variable(name)
+                .filter(|variable_value| !variable_value.is_empty())
+                .or_else(|| Some(String::from(default_value)))
+        };
+
+        match variable_value {
+            None => return Err(ConfigError::Invalid(format!("'{}' references environment variable '{}' which is not set.", key, name))),
+            Some(variable_value) => result.push_str(&variable_value)
+        }
+
+        rest = &reference[reference_end_index + 1..];
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_value_no_new_line_at_the_end() {
+        let content = "key=value";
+        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    }
+
+    #[test]
+    fn find_value_new_line_at_the_end() {
+        let content = "key=value\n";
+        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    }
+
+    #[test]
+    fn find_value_trims_value() {
+        let content = "key=value  ";
+        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    }
+
+    #[test]
+    fn find_value_no_value_after_equals_sign() {
+        let content = "key=";
+        assert_eq!(find_value(content, "key"), None);
+    }
+
+    #[test]
+    fn find_value_whitespace_instead_of_value() {
+        let content = "key= ";
+        assert_eq!(find_value(content, "key"), None);
+    }
+
+    #[test]
+    fn config_from_str_all_fields() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=2\nremote_compression_level=3";
+        assert_eq!(config_from_str(content, None), Ok(Config {
+            remote_machine_name: String::from("test@machine"),
+            remote_machine_user: None,
+            remote_machine_port: None,
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 2,
+            remote_compression_level: 3,
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_all_fields_unordered() {
+        let content = "remote_compression_level=3\nremote_machine=test@machine\nlocal_compression_level=2";
+        assert_eq!(config_from_str(content, None), Ok(Config {
+            remote_machine_name: String::from("test@machine"),
+            remote_machine_user: None,
+            remote_machine_port: None,
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 2,
+            remote_compression_level: 3,
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_only_remote_machine_name() {
+        let content = "remote_machine=test@machine";
+        assert_eq!(config_from_str(content, None), Ok(Config {
+            remote_machine_name: String::from("test@machine"),
+            remote_machine_user: None,
+            remote_machine_port: None,
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 1, // Default value.
+            remote_compression_level: 1, // Default value.
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_no_remote_machine_name() {
+        let content = "local_compression_level=2\nremote_compression_level=3";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::Invalid(String::from("please specify 'remote_machine'."))));
+    }
+
+    #[test]
+    fn config_from_str_local_compression_level_not_a_number() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=yooo";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("local_compression_level"), min: 0, max: 9, found: String::from("yooo") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_compression_level_not_a_number() {
+        let content = "remote_machine=test@machine\nremote_compression_level=wut";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("remote_compression_level"), min: 0, max: 9, found: String::from("wut") }));
+    }
+
+    #[test]
+    fn find_value_ignores_keys_with_same_prefix() {
+        let content = "key_suffix=other\nkey=value";
+        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    }
+
+    #[test]
+    fn config_from_str_only_remote_machine_user() {
+        let content = "remote_machine=machine\nremote_machine_user=test";
+        assert_eq!(config_from_str(content, None), Ok(Config {
+            remote_machine_name: String::from("machine"),
+            remote_machine_user: Some(String::from("test")),
+            remote_machine_port: None,
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 1, // Default value.
+            remote_compression_level: 1, // Default value.
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_empty_remote_machine_user() {
+        let content = "remote_machine=machine\nremote_machine_user=";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_user), Ok(None));
+    }
+
+    #[test]
+    fn remote_machine_target_without_user() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(config.remote_machine_target(), "machine");
+    }
+
+    #[test]
+    fn remote_machine_target_with_user() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_user=test", None).unwrap();
+        assert_eq!(config.remote_machine_target(), "test@machine");
+    }
+
+    #[test]
+    fn remote_machine_target_ipv4() {
+        let config = config_from_str("remote_machine=test@192.168.1.10", None).unwrap();
+        assert_eq!((config.remote_machine_target(), config.remote_machine_rsync_target()), (String::from("test@192.168.1.10"), String::from("test@192.168.1.10")));
+    }
+
+    #[test]
+    fn remote_machine_target_hostname() {
+        let config = config_from_str("remote_machine=build.machine\nremote_machine_user=test", None).unwrap();
+        assert_eq!((config.remote_machine_target(), config.remote_machine_rsync_target()), (String::from("test@build.machine"), String::from("test@build.machine")));
+    }
+
+    #[test]
+    fn remote_machine_target_ipv6() {
+        let config = config_from_str("remote_machine=fe80::1\nremote_machine_user=test", None).unwrap();
+        assert_eq!((config.remote_machine_target(), config.remote_machine_rsync_target()), (String::from("test@fe80::1"), String::from("test@[fe80::1]")));
+    }
+
+    #[test]
+    fn remote_machine_target_bracketed_ipv6() {
+        let config = config_from_str("remote_machine=test@[::1]", None).unwrap();
+        assert_eq!((config.remote_machine_target(), config.remote_machine_rsync_target()), (String::from("test@::1"), String::from("test@[::1]")));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_port() {
+        let content = "remote_machine=test@machine\nremote_machine_port=2222";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_port), Ok(Some(2222)));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_port_zero() {
+        let content = "remote_machine=test@machine\nremote_machine_port=0";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("remote_machine_port"), min: 1, max: 65535, found: String::from("0") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_port_too_big() {
+        let content = "remote_machine=test@machine\nremote_machine_port=65536";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("remote_machine_port"), min: 1, max: 65535, found: String::from("65536") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_port_not_a_number() {
+        let content = "remote_machine=test@machine\nremote_machine_port=ssh";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("remote_machine_port"), min: 1, max: 65535, found: String::from("ssh") }));
+    }
+
+    #[test]
+    fn expand_tilde_home_prefix() {
+        assert_eq!(expand_tilde("~/.ssh/id_rsa", Some(String::from("/home/test"))), "/home/test/.ssh/id_rsa");
+    }
+
+    #[test]
+    fn expand_tilde_home_only() {
+        assert_eq!(expand_tilde("~", Some(String::from("/home/test"))), "/home/test");
+    }
+
+    #[test]
+    fn expand_tilde_absolute_path() {
+        assert_eq!(expand_tilde("/keys/id_rsa", Some(String::from("/home/test"))), "/keys/id_rsa");
+    }
+
+    #[test]
+    fn expand_tilde_other_user_home() {
+        assert_eq!(expand_tilde("~other/id_rsa", Some(String::from("/home/test"))), "~other/id_rsa");
+    }
+
+    #[test]
+    fn expand_tilde_no_home() {
+        assert_eq!(expand_tilde("~/.ssh/id_rsa", None), "~/.ssh/id_rsa");
+    }
+
+    #[test]
+    fn resolve_identity_file_tilde() {
+        let home_dir = env::temp_dir().join(format!("mainframer_identity_file_tilde_{}", std::process::id()));
+        fs::create_dir_all(home_dir.join(".ssh")).unwrap();
+        fs::write(home_dir.join(".ssh/id_rsa"), "").unwrap();
+
+        let result = resolve_referenced_file("remote_machine_identity_file", "~/.ssh/id_rsa", Path::new("/project/.mainframer"), Some(home_dir.to_string_lossy().into_owned()));
+        fs::remove_dir_all(&home_dir).unwrap();
+
+        assert_eq!(result, Ok(home_dir.join(".ssh/id_rsa").to_string_lossy().into_owned()));
+    }
+
+    #[test]
+    fn resolve_identity_file_relative() {
+        let config_dir = env::temp_dir().join(format!("mainframer_identity_file_relative_{}", std::process::id()));
+        fs::create_dir_all(config_dir.join("keys")).unwrap();
+        fs::write(config_dir.join("keys/id_rsa"), "").unwrap();
+
+        let result = resolve_referenced_file("remote_machine_identity_file", "keys/id_rsa", &config_dir, None);
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert_eq!(result, Ok(config_dir.join("keys/id_rsa").to_string_lossy().into_owned()));
+    }
+
+    #[test]
+    fn resolve_identity_file_absolute() {
+        let identity_file = env::temp_dir().join(format!("mainframer_identity_file_absolute_{}", std::process::id()));
+        fs::write(&identity_file, "").unwrap();
+
+        let result = resolve_referenced_file("remote_machine_identity_file", &identity_file.to_string_lossy(), Path::new("/project/.mainframer"), None);
+        fs::remove_file(&identity_file).unwrap();
+
+        assert_eq!(result, Ok(identity_file.to_string_lossy().into_owned()));
+    }
+
+    #[test]
+    fn resolve_identity_file_missing() {
+        assert_eq!(
+            resolve_referenced_file("remote_machine_identity_file", "keys/missing", Path::new("/project/.mainframer"), None),
+            Err(ConfigError::Invalid(String::from("'remote_machine_identity_file' points to missing file '/project/.mainframer/keys/missing'.")))
+        );
+    }
+
+    #[test]
+    fn resolve_referenced_file_missing_known_hosts_file() {
+        assert_eq!(
+            resolve_referenced_file("remote_machine_known_hosts_file", "known_hosts", Path::new("/project/.mainframer"), None),
+            Err(ConfigError::Invalid(String::from("'remote_machine_known_hosts_file' points to missing file '/project/.mainframer/known_hosts'.")))
+        );
+    }
+
+    fn write_layers(name: &str, contents: &[&str]) -> (PathBuf, Vec<PathBuf>) {
+        let dir = env::temp_dir().join(format!("mainframer_layers_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let file_paths = contents.iter().enumerate().map(|(index, content)| {
+            let file_path = dir.join(format!("config{}", index));
+            fs::write(&file_path, content).unwrap();
+            file_path
+        }).collect();
+
+        (dir, file_paths)
+    }
+
+    #[test]
+    fn from_files_two_layers() {
+        let (dir, file_paths) = write_layers("two", &[
+            "remote_machine=base.machine\nremote_machine_port=2222\nlocal_compression_level=2",
+            "remote_machine=override.machine\nremote_compression_level=7",
+        ]);
+        let file_paths: Vec<&Path> = file_paths.iter().map(PathBuf::as_path).collect();
+
+        let result = Config::from_files(&file_paths, None, None, None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = result.unwrap();
+        assert_eq!(config.remote_machine_name, "override.machine");
+        assert_eq!(config.remote_machine_port, Some(2222));
+        assert_eq!((config.local_compression_level, config.remote_compression_level), (2, 7));
+    }
+
+    #[test]
+    fn from_files_three_layers_later_wins() {
+        let (dir, file_paths) = write_layers("three", &[
+            "remote_machine=base.machine\nremote_machine_user=base\nsync_checksum=true\ntimeout_seconds=60",
+            "remote_machine_user=secret\ntimeout_seconds=120",
+            "timeout_seconds=300\nsync_checksum=false",
+        ]);
+        let file_paths: Vec<&Path> = file_paths.iter().map(PathBuf::as_path).collect();
+
+        let result = Config::from_files(&file_paths, None, None, None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = result.unwrap();
+        assert_eq!(config.remote_machine_target(), "secret@base.machine");
+        assert_eq!(config.timeout_seconds, Some(300));
+        assert!(!config.sync_checksum);
+    }
+
+    #[test]
+    fn from_files_error_names_the_layer() {
+        let (dir, file_paths) = write_layers("error", &[
+            "remote_machine=base.machine",
+            "remote_machine_port=0",
+            "sync_checksum=false",
+        ]);
+        let file_paths: Vec<&Path> = file_paths.iter().map(PathBuf::as_path).collect();
+
+        let result = Config::from_files(&file_paths, None, None, None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Err(ConfigError::InFile {
+            path: dir.join("config1"),
+            error: Box::new(ConfigError::OutOfRange { key: String::from("remote_machine_port"), min: 1, max: 65535, found: String::from("0") }),
+        }));
+    }
+
+    #[test]
+    fn from_files_missing_layer() {
+        let file_path = Path::new("/mainframer/missing/config");
+        assert_eq!(Config::from_files(&[file_path], None, None, None), Err(ConfigError::FileNotFound { path: file_path.to_path_buf() }));
+    }
+
+    #[test]
+    fn from_file_resolves_known_hosts_file_relative_to_config_dir() {
+        let config_dir = env::temp_dir().join(format!("mainframer_known_hosts_file_{}", std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(config_dir.join("config"), "remote_machine=test@machine\nremote_machine_known_hosts_file=known_hosts").unwrap();
+        fs::write(config_dir.join("known_hosts"), "").unwrap();
+
+        let result = Config::from_file(&config_dir.join("config"), None, None, None).map(|config| config.remote_machine_known_hosts_file);
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert_eq!(result, Ok(Some(config_dir.join("known_hosts").to_string_lossy().into_owned())));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_identity_file() {
+        let content = "remote_machine=test@machine\nremote_machine_identity_file=/keys/id_rsa";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_identity_file), Ok(Some(String::from("/keys/id_rsa"))));
+    }
+
+    #[test]
+    fn config_from_str_empty_remote_machine_identity_file() {
+        let content = "remote_machine=test@machine\nremote_machine_identity_file=";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_identity_file), Ok(None));
+    }
+
+    #[test]
+    fn config_from_str_rsync_args() {
+        let content = "remote_machine=test@machine\nrsync_args=--progress  --partial";
+        assert_eq!(config_from_str(content, None).map(|config| config.rsync_args), Ok(vec![String::from("--progress"), String::from("--partial")]));
+    }
+
+    #[test]
+    fn config_from_str_single_rsync_arg() {
+        let content = "remote_machine=test@machine\nrsync_args=--progress";
+        assert_eq!(config_from_str(content, None).map(|config| config.rsync_args), Ok(vec![String::from("--progress")]));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_ssh_options() {
+        let content = "remote_machine=test@machine\nremote_machine_ssh_options=Ciphers=aes128-ctr,aes256-ctr  Compression=yes ConnectTimeout=10";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_ssh_options), Ok(vec![
+            String::from("Ciphers=aes128-ctr,aes256-ctr"),
+            String::from("Compression=yes"),
+            String::from("ConnectTimeout=10"),
+        ]));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_ssh_options_not_name_value() {
+        let content = "remote_machine=test@machine\nremote_machine_ssh_options=Compression=yes -C";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("remote_machine_ssh_options"),
+            expected: "a whitespace-separated list of 'Name=Value' ssh options",
+            found: String::from("-C"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_transport_rsyncd() {
+        let content = "remote_machine=machine\nremote_machine_transport=rsyncd\nremote_machine_module=projects";
+        assert_eq!(
+            config_from_str(content, None).map(|config| (config.remote_machine_transport, config.remote_machine_module)),
+            Ok((Transport::Rsyncd, Some(String::from("projects"))))
+        );
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_transport_default() {
+        let content = "remote_machine=machine";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_transport), Ok(Transport::Ssh));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_transport_unknown() {
+        let content = "remote_machine=machine\nremote_machine_transport=rsh";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("remote_machine_transport"),
+            expected: "either 'ssh' or 'rsyncd'",
+            found: String::from("rsh"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_sync_copy_links() {
+        let config = config_from_str("remote_machine=machine\nsync_copy_links=true", None).unwrap();
+        assert_eq!((config.sync_copy_links, config.sync_preserve_links), (true, false));
+    }
+
+    #[test]
+    fn config_from_str_sync_copy_links_and_preserve_links() {
+        let content = "remote_machine=machine\nsync_copy_links=true\nsync_preserve_links=true";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::Invalid(String::from("please enable either 'sync_copy_links' or 'sync_preserve_links', not both."))));
+    }
+
+    #[test]
+    fn config_from_str_sync_copy_links_not_a_boolean() {
+        let content = "remote_machine=machine\nsync_copy_links=always";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("sync_copy_links"), expected: "either 'true' or 'false'", found: String::from("always") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_transport_rsyncd_without_module() {
+        let content = "remote_machine=machine\nremote_machine_transport=rsyncd";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::Invalid(String::from("please specify 'remote_machine_module' for 'rsyncd' transport."))));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_module_with_slash() {
+        let content = "remote_machine=machine\nremote_machine_transport=rsyncd\nremote_machine_module=projects/mine";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("remote_machine_module"),
+            expected: "an rsync daemon module name",
+            found: String::from("projects/mine"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_sync_whole_file_not_a_boolean() {
+        let content = "remote_machine=test@machine\nsync_whole_file=auto";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("sync_whole_file"), expected: "either 'true' or 'false'", found: String::from("auto") }));
+    }
+
+    #[test]
+    fn format_resolved_shows_merged_values() {
+        let global_config = parse_config_from_str("remote_machine=global@machine\ncompression_level=4\nremote_env.TOOL=global").unwrap();
+        let project_config = parse_config_from_str("remote_machine=project@machine\nremote_compression_level=9\nremote_env.BUILD_TYPE=release").unwrap();
+        let config = Config::resolve(merge(global_config, project_config), None).unwrap();
+        let resolved = config.format_resolved();
+        let lines: Vec<&str> = resolved.lines().collect();
+
+        assert!(lines.contains(&"remote_machine=project@machine"));
+        assert!(lines.contains(&"local_compression_level=4"));
+        assert!(lines.contains(&"remote_compression_level=9"));
+        assert!(lines.contains(&"remote_machine_port="));
+        assert!(lines.ends_with(&["remote_env.BUILD_TYPE=release", "remote_env.TOOL=global"]));
+    }
+
+    #[test]
+    fn format_resolved_parses_back() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222\nrsync_args=--partial --progress\nsync_whole_file=true", None).unwrap();
+        assert_eq!(config_from_str(&config.format_resolved(), None), Ok(config));
+    }
+
+    #[test]
+    fn config_from_str_compression_auto() {
+        let config = config_from_str("remote_machine=machine\ncompression_auto=true", None).unwrap();
+        assert!(config.local_compression_level_auto);
+        assert!(config.remote_compression_level_auto);
+    }
+
+    #[test]
+    fn config_from_str_compression_auto_explicit_level_wins() {
+        let config = config_from_str("remote_machine=machine\ncompression_auto=true\nremote_compression_level=7", None).unwrap();
+        assert!(config.local_compression_level_auto);
+        assert!(!config.remote_compression_level_auto);
+
+        let config = config_from_str("remote_machine=machine\ncompression_auto=true\ncompression_level=3", None).unwrap();
+        assert!(!config.local_compression_level_auto);
+        assert!(!config.remote_compression_level_auto);
+    }
+
+    #[test]
+    fn format_resolved_compression_auto_parses_back() {
+        let config = config_from_str("remote_machine=machine\ncompression_auto=true\nlocal_compression_level=4", None).unwrap();
+        assert_eq!(config_from_str(&config.format_resolved(), None), Ok(config));
+    }
+
+    #[test]
+    fn config_from_str_compression_skip_compress_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(config.compression_skip_compress, default_skip_compress());
+        assert!(config.compression_skip_compress.contains(&String::from("jar")));
+    }
+
+    #[test]
+    fn config_from_str_compression_skip_compress() {
+        let content = "remote_machine=machine\ncompression_skip_compress=jpg  zip 7z";
+        assert_eq!(config_from_str(content, None).map(|config| config.compression_skip_compress), Ok(vec![
+            String::from("jpg"),
+            String::from("zip"),
+            String::from("7z"),
+        ]));
+    }
+
+    #[test]
+    fn config_from_str_compression_skip_compress_invalid_extension() {
+        let content = "remote_machine=machine\ncompression_skip_compress=jpg .zip";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("compression_skip_compress"),
+            expected: "a whitespace-separated list of file extensions like 'jpg zip'",
+            found: String::from(".zip"),
+        }));
+    }
+
+    #[test]
+    fn parse_size_suffixes() {
+        assert_eq!(parse_size("100M"), Some(100 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("512k"), Some(512 * 1024));
+        assert_eq!(parse_size("2T"), Some(2 * 1024 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn parse_size_bare_bytes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+    }
+
+    #[test]
+    fn parse_size_invalid() {
+        assert_eq!(parse_size("100X"), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("1.5G"), None);
+        assert_eq!(parse_size("-1K"), None);
+        assert_eq!(parse_size("99999999999T"), None);
+    }
+
+    #[test]
+    fn config_from_str_sync_max_file_size() {
+        let content = "remote_machine=test@machine\nsync_max_file_size_upload=100M\nsync_max_file_size_download=1G";
+        assert_eq!(
+            config_from_str(content, None).map(|config| (config.sync_max_file_size_upload, config.sync_max_file_size_download)),
+            Ok((Some(100 * 1024 * 1024), Some(1024 * 1024 * 1024)))
+        );
+    }
+
+    #[test]
+    fn config_from_str_sync_parallelism() {
+        let content = "remote_machine=test@machine\nsync_parallelism=-2";
+        assert_eq!(config_from_str(content, None).map(|config| config.sync_parallelism), Ok(Some(-2)));
+    }
+
+    #[test]
+    fn config_from_str_sync_partial_dir() {
+        let config = |value: &str| config_from_str(&format!("remote_machine=machine\nsync_partial_dir={}", value), None).map(|config| config.sync_partial_dir);
+        assert_eq!(config("true"), Ok(Some(String::from(".mainframer-partial"))));
+        assert_eq!(config("false"), Ok(None));
+        assert_eq!(config(".partial"), Ok(Some(String::from(".partial"))));
+        assert_eq!(config(""), Ok(None));
+    }
+
+    #[test]
+    fn config_from_str_sync_partial_dir_up() {
+        let content = "remote_machine=machine\nsync_partial_dir=../partial";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("sync_partial_dir"),
+            expected: "either 'true', 'false' or a dir path without '..'",
+            found: String::from("../partial"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_sync_parallelism_invalid() {
+        let content = "remote_machine=test@machine\nsync_parallelism=many";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("sync_parallelism"),
+            expected: "a number",
+            found: String::from("many"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_sync_max_file_size_invalid() {
+        let content = "remote_machine=test@machine\nsync_max_file_size_upload=100X";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("sync_max_file_size_upload"),
+            expected: "a size like '512K', '100M' or '1G'",
+            found: String::from("100X"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_empty_rsync_args() {
+        let content = "remote_machine=test@machine\nrsync_args=";
+        assert_eq!(config_from_str(content, None).map(|config| config.rsync_args), Ok(vec![]));
+    }
+
+    #[test]
+    fn config_from_str_named_remote_machine() {
+        let content = "remote_machine.build=build@machine\nremote_machine.test=test@machine";
+        assert_eq!(config_from_str(content, Some("test")).map(|config| config.remote_machine_name), Ok(String::from("test@machine")));
+    }
+
+    #[test]
+    fn config_from_str_named_remote_machine_not_picked() {
+        let content = "remote_machine.build=build@machine\nremote_machine.test=test@machine";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::Invalid(String::from("please pick remote machine with '--machine', available: build, test."))));
+    }
+
+    #[test]
+    fn config_from_str_named_remote_machine_unknown() {
+        let content = "remote_machine.build=build@machine";
+        assert_eq!(config_from_str(content, Some("test")), Err(ConfigError::Invalid(String::from("please specify 'remote_machine.test'."))));
+    }
+
+    #[test]
+    fn config_from_str_named_remote_machine_without_named_machines() {
+        let content = "remote_machine=test@machine";
+        assert_eq!(config_from_str(content, Some("test")), Err(ConfigError::Invalid(String::from("please specify 'remote_machine.test'."))));
+    }
+
+    #[test]
+    fn config_from_str_named_remote_machine_empty() {
+        let content = "remote_machine.build=build-host\nremote_machine.test=";
+        assert_eq!(config_from_str(content, Some("build")), Err(ConfigError::Invalid(String::from("'remote_machine.test' is present but empty, remove it or set machine host."))));
+    }
+
+    #[test]
+    fn config_from_str_named_remote_machine_without_name() {
+        let content = "remote_machine.=build-host";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::Invalid(String::from("'remote_machine.' is present but empty, remove it or set machine host."))));
+    }
+
+    #[test]
+    fn parse_config_from_str_remote_env() {
+        let content = "remote_machine=test@machine\nremote_env.JAVA_HOME=/opt/jdk\nremote_env.CI=true\nremote_env.EMPTY=";
+        let mut remote_env = BTreeMap::new();
+        remote_env.insert(String::from("CI"), String::from("true"));
+        remote_env.insert(String::from("EMPTY"), String::new());
+        remote_env.insert(String::from("JAVA_HOME"), String::from("/opt/jdk"));
+        assert_eq!(parse_config_from_str(content).map(|config| config.remote_env), Ok(remote_env));
+    }
+
+    #[test]
+    fn parse_config_from_str_remote_env_invalid_name() {
+        let content = "remote_machine=test@machine\nremote_env.1ST=value";
+        assert_eq!(parse_config_from_str(content), Err(ConfigError::Invalid(String::from("'remote_env.1ST' must name an environment variable with letters, digits and '_', found '1ST'."))));
+    }
+
+    #[test]
+    fn parse_config_from_str_remote_env_empty_name() {
+        let content = "remote_machine=test@machine\nremote_env.=value";
+        assert_eq!(parse_config_from_str(content), Err(ConfigError::Invalid(String::from("'remote_env.' must name an environment variable with letters, digits and '_', found ''."))));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_and_named_remote_machines() {
+        let content = "remote_machine=test@machine\nremote_machine.build=build@machine";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::Invalid(String::from("please specify either 'remote_machine' or 'remote_machine.{name}' keys, not both."))));
+    }
+
+    #[test]
+    fn merge_extended_config_inherits_missing_keys() {
+        let base_config = parse_config_from_str("remote_machine=test@machine\nlocal_compression_level=2").unwrap();
+        let config = parse_config_from_str("remote_compression_level=3").unwrap();
+        assert_eq!(Config::resolve(merge(base_config, config), None), Ok(Config {
+            remote_machine_name: String::from("test@machine"),
+            remote_machine_user: None,
+            remote_machine_port: None,
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 2,
+            remote_compression_level: 3,
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn merge_extended_config_overrides_keys() {
+        let base_config = parse_config_from_str("remote_machine=base@machine\nremote_machine_port=2222\nlocal_compression_level=2\nremote_compression_level=3").unwrap();
+        let config = parse_config_from_str("extends=base\nremote_machine=test@machine\nremote_compression_level=4").unwrap();
+        assert_eq!(Config::resolve(merge(base_config, config), None), Ok(Config {
+            remote_machine_name: String::from("test@machine"),
+            remote_machine_user: None,
+            remote_machine_port: Some(2222),
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 2,
+            remote_compression_level: 4,
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn from_file_extends_chain() {
+        let dir = env::temp_dir().join(format!("mainframer_config_extends_chain_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config"), "extends=base\nlocal_compression_level=2").unwrap();
+        fs::write(dir.join("base"), "extends=other\nremote_machine=test@machine").unwrap();
+        fs::write(dir.join("other"), "remote_machine=other@machine\nremote_compression_level=4").unwrap();
+
+        let result = Config::from_file(&dir.join("config"), None, None, None).map(|config| (config.remote_machine_name, config.local_compression_level, config.remote_compression_level));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Ok((String::from("test@machine"), 2, 4)));
+    }
+
+    #[test]
+    fn from_file_extends_merges_same_as_layers() {
+        let dir = env::temp_dir().join(format!("mainframer_config_extends_same_as_layers_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("base"), "remote_machine.build=build@machine\nprofile.fast.local_compression_level=1\nprofile.fast.remote_compression_level=2").unwrap();
+        fs::write(dir.join("layer"), "remote_machine=test@machine\nprofile.fast.local_compression_level=3").unwrap();
+        fs::write(dir.join("config"), "extends=base\nremote_machine=test@machine\nprofile.fast.local_compression_level=3").unwrap();
+
+        let compression = |config: Config| (config.remote_machine_name, config.local_compression_level, config.remote_compression_level);
+        let extended = Config::from_file(&dir.join("config"), None, None, Some("fast")).map(compression);
+        let layered = Config::from_files(&[&dir.join("base"), &dir.join("layer")], None, None, Some("fast")).map(compression);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(extended, Ok((String::from("test@machine"), 3, 1)));
+        assert_eq!(extended, layered);
+    }
+
+    #[test]
+    fn from_file_named_machine_defined_once_in_extended_config() {
+        let dir = env::temp_dir().join(format!("mainframer_config_named_machine_extended_{}", std::process::id()));
+        fs::create_dir_all(dir.join("app")).unwrap();
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("shared"), "remote_machine_port=2222\nremote_machine.build=build@machine\nremote_machine.gpu=gpu.machine").unwrap();
+        fs::write(dir.join("app/config"), "extends=../shared\nlocal_compression_level=2").unwrap();
+        fs::write(dir.join("lib/config"), "remote_machine_user=ci\nextends=../shared").unwrap();
+
+        let target = |config: Config| (config.remote_machine_target(), config.remote_machine_port);
+        let app = Config::from_file(&dir.join("app/config"), None, Some("build"), None).map(target);
+        let lib = Config::from_file(&dir.join("lib/config"), None, Some("gpu"), None).map(target);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(app, Ok((String::from("build@machine"), Some(2222))));
+        assert_eq!(lib, Ok((String::from("ci@gpu.machine"), Some(2222))));
+    }
+
+    #[test]
+    fn from_file_extends_cycle() {
+        let dir = env::temp_dir().join(format!("mainframer_config_extends_cycle_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config"), "extends=base\nremote_machine=test@machine").unwrap();
+        fs::write(dir.join("base"), "extends=config").unwrap();
+
+        let result = Config::from_file(&dir.join("config"), None, None, None);
+        let dir_path = fs::canonicalize(&dir).unwrap().to_string_lossy().into_owned();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Err(ConfigError::Invalid(format!(
+            "Config files extend each other: '{dir}/config' → '{dir}/base' → '{dir}/config'.",
+            dir = dir_path
+        ))));
+    }
+
+    #[test]
+    fn from_file_extends_relative_to_config_dir() {
+        let dir = env::temp_dir().join(format!("mainframer_config_extends_relative_{}", std::process::id()));
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(dir.join("config"), "extends=shared/base\nlocal_compression_level=2").unwrap();
+        fs::write(dir.join("shared/base"), "remote_machine=test@machine\nlocal_compression_level=5").unwrap();
+
+        let result = Config::from_file(&dir.join("config"), None, None, None).map(|config| (config.remote_machine_name, config.local_compression_level));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Ok((String::from("test@machine"), 2)));
+    }
+
+    #[test]
+    fn from_reader_in_memory() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=4";
+        let result = Config::from_reader(content.as_bytes(), Path::new("<stdin>"), None, None, None).map(|config| (config.remote_machine_name, config.local_compression_level));
+        assert_eq!(result, Ok((String::from("test@machine"), 4)));
+    }
+
+    #[test]
+    fn from_file_missing() {
+        let result = Config::from_file(Path::new("/nonexistent/.mainframer/config"), None, None, None);
+        assert_eq!(result, Err(ConfigError::FileNotFound { path: PathBuf::from("/nonexistent/.mainframer/config") }));
+        assert_eq!(result.unwrap_err().to_string(), "Could not open config file '/nonexistent/.mainframer/config'.");
+    }
+
+    #[test]
+    fn from_reader_not_utf8() {
+        let result = Config::from_reader(&[b'r', 0xff, 0xfe, b'\n'][..], Path::new("<stdin>"), None, None, None);
+        assert_eq!(result, Err(ConfigError::Unreadable { path: PathBuf::from("<stdin>") }));
+        assert_eq!(result.unwrap_err().to_string(), "Could not read config file '<stdin>'.");
+    }
+
+    #[test]
+    fn from_reader_error_references_source() {
+        let result = Config::from_reader("local_compression_level=4".as_bytes(), Path::new("<stdin>"), None, None, None);
+        assert_eq!(result.map_err(|error| error.to_string()), Err(String::from("Error during parsing config file '<stdin>'\nplease specify 'remote_machine'.")));
+    }
+
+    fn test_environment_variable(name: &str) -> Option<String> {
+        match name {
+            "BUILD_HOST" => Some(String::from("build.machine")),
+            "BUILD_USER" => Some(String::from("test")),
+            "EMPTY" => Some(String::new()),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_without_references() {
+        assert_eq!(substitute_environment_variables_in_value("machine", "key", &test_environment_variable), Ok(String::from("machine")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_whole_value() {
+        assert_eq!(substitute_environment_variables_in_value("${BUILD_HOST}", "key", &test_environment_variable), Ok(String::from("build.machine")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_several_references() {
+        assert_eq!(substitute_environment_variables_in_value("${BUILD_USER}@${BUILD_HOST}:22", "key", &test_environment_variable), Ok(String::from("test@build.machine:22")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_default_for_unset() {
+        assert_eq!(substitute_environment_variables_in_value("${UNSET:-machine}", "key", &test_environment_variable), Ok(String::from("machine")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_default_for_empty() {
+        assert_eq!(substitute_environment_variables_in_value("${EMPTY:-machine}", "key", &test_environment_variable), Ok(String::from("machine")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_default_ignored_for_set() {
+        assert_eq!(substitute_environment_variables_in_value("${BUILD_HOST:-machine}", "key", &test_environment_variable), Ok(String::from("build.machine")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_unset() {
+        assert_eq!(substitute_environment_variables_in_value("${UNSET}", "key", &test_environment_variable), Err(ConfigError::Invalid(String::from("'key' references environment variable 'UNSET' which is not set."))));
+    }
+
+    #[test]
+    fn substitute_environment_variables_in_value_unterminated() {
+        assert_eq!(substitute_environment_variables_in_value("${BUILD_HOST", "key", &test_environment_variable), Err(ConfigError::Invalid(String::from("'key' contains unterminated '${' in '${BUILD_HOST'."))));
+    }
+
+    #[test]
+    fn substitute_environment_variables_all_string_fields() {
+        let config = config_from_str("remote_machine=${BUILD_HOST}\nremote_machine_user=${BUILD_USER}\nremote_machine_identity_file=/keys/${BUILD_USER}", None).unwrap();
+        let config = substitute_environment_variables(config, &test_environment_variable).unwrap();
+        assert_eq!(config.remote_machine_target(), "test@build.machine");
+        assert_eq!(config.remote_machine_identity_file, Some(String::from("/keys/test")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_known_hosts_file_hooks_and_lists() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_known_hosts_file=/hosts/${BUILD_HOST}\nbefore_upload_hook=make ${BUILD_USER}\nexecution_source_profile=${UNSET:-~/.profile}\nsync_partial_dir=.partial-${BUILD_USER}\nrsync_args=--chown=${BUILD_USER}", None).unwrap();
+        let config = substitute_environment_variables(config, &test_environment_variable).unwrap();
+        assert_eq!(config.remote_machine_known_hosts_file, Some(String::from("/hosts/build.machine")));
+        assert_eq!(config.before_upload_hook, Some(String::from("make test")));
+        assert_eq!(config.execution_source_profile, Some(String::from("~/.profile")));
+        assert_eq!(config.sync_partial_dir, Some(String::from(".partial-test")));
+        assert_eq!(config.rsync_args, vec![String::from("--chown=test")]);
+    }
+
+    #[test]
+    fn substitute_environment_variables_leaves_remote_env() {
+        let config = config_from_str("remote_machine=machine\nremote_env.PATH=${PATH}:/opt/bin\nremote_env.HOST=${BUILD_HOST}\nremote_env.USER=${UNSET}", None).unwrap();
+        let config = substitute_environment_variables(config, &test_environment_variable).unwrap();
+        assert_eq!(config.remote_env.get("PATH"), Some(&String::from("${PATH}:/opt/bin")));
+        assert_eq!(config.remote_env.get("HOST"), Some(&String::from("${BUILD_HOST}")));
+        assert_eq!(config.remote_env.get("USER"), Some(&String::from("${UNSET}")));
+    }
+
+    #[test]
+    fn substitute_environment_variables_names_key() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_user=${UNSET}", None).unwrap();
+        assert_eq!(substitute_environment_variables(config, &test_environment_variable), Err(ConfigError::Invalid(String::from("'remote_machine_user' references environment variable 'UNSET' which is not set."))));
+    }
+
+    #[test]
+    fn find_value_ignores_comments() {
+        let content = "# key=comment\n! key=comment\nkey=value";
+        assert_eq!(find_value(content, "key"), Some(String::from("value")));
+    }
+
+    #[test]
+    fn edit_distance_same() {
+        assert_eq!(edit_distance("remote_machine", "remote_machine"), 0);
+    }
+
+    #[test]
+    fn edit_distance_missing_char() {
+        assert_eq!(edit_distance("remote_machne", "remote_machine"), 1);
+    }
+
+    #[test]
+    fn edit_distance_different() {
+        assert_eq!(edit_distance("abc", "xyz"), 3);
+    }
+
+    #[test]
+    fn edit_distance_empty() {
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn config_from_str_unknown_key() {
+        let content = "remote_machine=test@machine\nsomething=value";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::UnknownKey { key: String::from("something"), suggestion: None }));
+    }
+
+    #[test]
+    fn config_from_str_unknown_key_suggestion() {
+        let content = "remote_machne=test@machine";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::UnknownKey { key: String::from("remote_machne"), suggestion: Some("remote_machine") }));
+    }
+
+    #[test]
+    fn parse_config_from_str_several_errors() {
+        let content = "remote_machine=test@machine\nremote_machin_port=22\nremote_machine_port=0\nlocal_compression_level=10\ndry_run=yes";
+        assert_eq!(parse_config_from_str(content), Err(ConfigError::Multiple(vec![
+            ConfigError::UnknownKey { key: String::from("remote_machin_port"), suggestion: Some("remote_machine_port") },
+            ConfigError::OutOfRange { key: String::from("remote_machine_port"), min: 1, max: 65535, found: String::from("0") },
+            ConfigError::OutOfRange { key: String::from("local_compression_level"), min: 0, max: 9, found: String::from("10") },
+            ConfigError::TypeMismatch { key: String::from("dry_run"), expected: "either 'true' or 'false'", found: String::from("yes") },
+        ])));
+    }
+
+    #[test]
+    fn config_from_str_several_unknown_keys() {
+        let content = "remote_machine=test@machine\nlocal_compresion_level=2\nsomething=value";
+        assert_eq!(config_from_str(content, None).map_err(|error| error.to_string()), Err(String::from(
+            "Unknown config key 'local_compresion_level'. Did you mean 'local_compression_level'?\nUnknown config key 'something'."
+        )));
+    }
+
+    #[test]
+    fn config_from_str_comments_are_not_keys() {
+        let content = "# something=value\nremote_machine=test@machine";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_name), Ok(String::from("test@machine")));
+    }
+
+    #[test]
+    fn validate_syntax_valid() {
+        let content = "# comment\n\nremote_machine=test@machine\n  local_compression_level = 2\n";
+        assert_eq!(validate_syntax(content), Ok(()));
+    }
+
+    #[test]
+    fn validate_syntax_missing_equals_sign() {
+        let content = "remote_machine=test@machine\n  local_compression_level 2";
+        assert_eq!(validate_syntax(content), Err(ConfigError::Syntax { line: 2, column: 3, message: String::from("expected 'key=value', found 'local_compression_level 2'.") }));
+    }
+
+    #[test]
+    fn validate_syntax_missing_key() {
+        let content = "=test@machine";
+        assert_eq!(validate_syntax(content), Err(ConfigError::Syntax { line: 1, column: 1, message: String::from("key is missing before '='.") }));
+    }
+
+    #[test]
+    fn config_from_str_compression_level_zero() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=0\nremote_compression_level=0";
+        assert_eq!(config_from_str(content, None).map(|config| (config.local_compression_level, config.remote_compression_level)), Ok((0, 0)));
+    }
+
+    #[test]
+    fn config_from_str_local_compression_level_too_big() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=10";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("local_compression_level"), min: 0, max: 9, found: String::from("10") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_compression_level_negative() {
+        let content = "remote_machine=test@machine\nremote_compression_level=-1";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("remote_compression_level"), min: 0, max: 9, found: String::from("-1") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_folder_prefix() {
+        let content = "remote_machine=test@machine\nremote_folder_prefix=/scratch/builds";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_folder_prefix), Ok(String::from("/scratch/builds")));
+    }
+
+    #[test]
+    fn config_from_str_remote_folder_prefix_trailing_slash() {
+        let content = "remote_machine=test@machine\nremote_folder_prefix=/scratch/builds/";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_folder_prefix), Ok(String::from("/scratch/builds")));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_proxy_jump() {
+        let content = "remote_machine=test@machine\nremote_machine_proxy_jump=bastion";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_proxy_jump), Ok(Some(String::from("bastion"))));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_proxy_jump_several_hops() {
+        let content = "remote_machine=test@machine\nremote_machine_proxy_jump=user@bastion:2222,inner";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_proxy_jump), Ok(Some(String::from("user@bastion:2222,inner"))));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_proxy_jump_empty_hop() {
+        let content = "remote_machine=test@machine\nremote_machine_proxy_jump=bastion,,inner";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_proxy_jump"), expected: "a comma-separated list of hosts", found: String::from("bastion,,inner") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_proxy_jump_whitespace() {
+        let content = "remote_machine=test@machine\nremote_machine_proxy_jump=bastion inner";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_proxy_jump"), expected: "a comma-separated list of hosts", found: String::from("bastion inner") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_keep_alive() {
+        let content = "remote_machine=test@machine\nremote_machine_keep_alive_interval_seconds=30\nremote_machine_keep_alive_count_max=4";
+        assert_eq!(
+            config_from_str(content, None).map(|config| (config.remote_machine_keep_alive_interval_seconds, config.remote_machine_keep_alive_count_max)),
+            Ok((Some(30), Some(4)))
+        );
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_keep_alive_interval_seconds_zero() {
+        let content = "remote_machine=test@machine\nremote_machine_keep_alive_interval_seconds=0";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_keep_alive_interval_seconds"), expected: "a positive number", found: String::from("0") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_keep_alive_count_max_negative() {
+        let content = "remote_machine=test@machine\nremote_machine_keep_alive_count_max=-3";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_keep_alive_count_max"), expected: "a positive number", found: String::from("-3") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_connect_timeout_seconds_zero() {
+        let content = "remote_machine=test@machine\nremote_machine_connect_timeout_seconds=0";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_connect_timeout_seconds"), expected: "a positive number", found: String::from("0") }));
+    }
+
+    #[test]
+    fn compression_level_bounds_zlib_range() {
+        assert_eq!(compression_level_bounds(), (0, 9));
+    }
+
+    #[test]
+    fn config_from_str_compression_level_bounds_are_valid() {
+        let (min, max) = compression_level_bounds();
+        let content = format!("remote_machine=test@machine\nlocal_compression_level={}\nremote_compression_level={}", min, max);
+        assert_eq!(config_from_str(&content, None).map(|config| (config.local_compression_level, config.remote_compression_level)), Ok((min, max)));
+    }
+
+    #[test]
+    fn config_from_str_only_compression_level() {
+        let content = "remote_machine=test@machine\ncompression_level=6";
+        assert_eq!(config_from_str(content, None).map(|config| (config.local_compression_level, config.remote_compression_level)), Ok((6, 6)));
+    }
+
+    #[test]
+    fn config_from_str_compression_level_and_direction_override() {
+        let content = "remote_machine=test@machine\ncompression_level=6\nremote_compression_level=2";
+        assert_eq!(config_from_str(content, None).map(|config| (config.local_compression_level, config.remote_compression_level)), Ok((6, 2)));
+    }
+
+    #[test]
+    fn config_from_str_compression_level_too_big() {
+        let content = "remote_machine=test@machine\ncompression_level=10";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("compression_level"), min: 0, max: 9, found: String::from("10") }));
+    }
+
+    #[test]
+    fn config_from_str_dry_run_true() {
+        let content = "remote_machine=test@machine\ndry_run=true";
+        assert_eq!(config_from_str(content, None).map(|config| config.dry_run), Ok(true));
+    }
+
+    #[test]
+    fn config_from_str_dry_run_false() {
+        let content = "remote_machine=test@machine\ndry_run=false";
+        assert_eq!(config_from_str(content, None).map(|config| config.dry_run), Ok(false));
+    }
+
+    #[test]
+    fn config_from_str_dry_run_not_a_boolean() {
+        let content = "remote_machine=test@machine\ndry_run=yes";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("dry_run"), expected: "either 'true' or 'false'", found: String::from("yes") }));
+    }
+
+    #[test]
+    fn config_from_str_sync_back_on_failure_false() {
+        let content = "remote_machine=test@machine\nsync_back_on_failure=false";
+        assert_eq!(config_from_str(content, None).map(|config| config.sync_back_on_failure), Ok(false));
+    }
+
+    #[test]
+    fn config_from_str_sync_back_on_failure_not_a_boolean() {
+        let content = "remote_machine=test@machine\nsync_back_on_failure=no";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("sync_back_on_failure"), expected: "either 'true' or 'false'", found: String::from("no") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_multiplex() {
+        let content = "remote_machine=test@machine\nremote_machine_multiplex=true";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_multiplex), Ok(true));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_multiplex_not_a_boolean() {
+        let content = "remote_machine=test@machine\nremote_machine_multiplex=1";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_multiplex"), expected: "either 'true' or 'false'", found: String::from("1") }));
+    }
+
+    #[test]
+    fn config_from_str_sync_checksum_not_a_boolean() {
+        let content = "remote_machine=test@machine\nsync_checksum=on";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("sync_checksum"), expected: "either 'true' or 'false'", found: String::from("on") }));
+    }
+
+    #[test]
+    fn config_from_str_sync_check_clock_skew() {
+        assert_eq!(config_from_str("remote_machine=test@machine", None).map(|config| config.sync_check_clock_skew), Ok(false));
+        assert_eq!(config_from_str("remote_machine=test@machine\nsync_check_clock_skew=true", None).map(|config| config.sync_check_clock_skew), Ok(true));
+    }
+
+    #[test]
+    fn config_from_str_sync_preserve_flags() {
+        let content = "remote_machine=test@machine\nsync_preserve_permissions=false\nsync_preserve_links=false";
+        assert_eq!(
+            config_from_str(content, None).map(|config| (config.sync_preserve_permissions, config.sync_preserve_times, config.sync_preserve_links)),
+            Ok((false, true, false))
+        );
+    }
+
+    #[test]
+    fn config_from_str_sync_preserve_times_not_a_boolean() {
+        let content = "remote_machine=test@machine\nsync_preserve_times=0";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("sync_preserve_times"), expected: "either 'true' or 'false'", found: String::from("0") }));
+    }
+
+    #[test]
+    fn config_from_str_execution_allocate_tty() {
+        let content = "remote_machine=test@machine\nexecution_allocate_tty=true";
+        assert_eq!(config_from_str(content, None).map(|config| config.execution_allocate_tty), Ok(true));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_forward_agent_not_a_boolean() {
+        let content = "remote_machine=test@machine\nremote_machine_forward_agent=yes";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_forward_agent"), expected: "either 'true' or 'false'", found: String::from("yes") }));
+    }
+
+    #[test]
+    fn config_from_str_bandwidth_limit_kbps() {
+        let content = "remote_machine=test@machine\nbandwidth_limit_kbps=1024";
+        assert_eq!(config_from_str(content, None).map(|config| config.bandwidth_limit_kbps), Ok(Some(1024)));
+    }
+
+    #[test]
+    fn config_from_str_bandwidth_limit_kbps_zero() {
+        let content = "remote_machine=test@machine\nbandwidth_limit_kbps=0";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("bandwidth_limit_kbps"), expected: "a positive number", found: String::from("0") }));
+    }
+
+    #[test]
+    fn parse_config_from_str_only_specified_values() {
+        let content = "remote_machine=test@machine\nremote_compression_level=3";
+        assert_eq!(parse_config_from_str(content), Ok(IntermediateConfig {
+            remote_machine: Some(String::from("test@machine")),
+            remote_compression_level: Some(3),
+            ..IntermediateConfig::default()
+        }));
+    }
+
+    #[test]
+    fn parse_config_from_str_empty() {
+        assert_eq!(parse_config_from_str(""), Ok(IntermediateConfig::default()));
+    }
+
+    #[test]
+    fn resolve_minimal_config() {
+        let config = IntermediateConfig {
+            remote_machine: Some(String::from("test@machine")),
+            ..IntermediateConfig::default()
+        };
+        assert_eq!(Config::resolve(config, None), Ok(Config {
+            remote_machine_name: String::from("test@machine"),
+            remote_machine_user: None,
+            remote_machine_port: None,
+            remote_machine_identity_file: None,
+            remote_machine_proxy_jump: None,
+            remote_machine_keep_alive_interval_seconds: None,
+            remote_machine_keep_alive_count_max: None,
+            remote_machine_connect_timeout_seconds: None,
+            local_compression_level: 1,
+            remote_compression_level: 1,
+            rsync_args: vec![],
+            bandwidth_limit_kbps: None,
+            remote_folder_prefix: String::from("~/mainframer"),
+            dry_run: false,
+            force_full_upload: false,
+            timeout_seconds: None,
+            before_upload_hook: None,
+            after_download_hook: None,
+            sync_back_on_failure: true,
+            remote_machine_multiplex: false,
+            sync_checksum: false,
+            sync_check_clock_skew: false,
+            sync_preserve_permissions: true,
+            sync_preserve_times: true,
+            sync_preserve_links: true,
+            sync_copy_links: false,
+            sync_ignore_git: false,
+            remote_env: BTreeMap::new(),
+            execution_allocate_tty: false,
+            remote_machine_forward_agent: false,
+            sync_delete_extraneous: true,
+            sync_rsync_path: String::from("rsync"),
+            remote_machine_ssh_path: String::from("ssh"),
+            remote_machine_shell: None,
+            watch_debounce_millis: 500,
+            remote_machine_strict_host_key_checking: false,
+            remote_machine_known_hosts_file: None,
+            execution_working_dir: None,
+            execution_source_profile: None,
+            execution_require_remote_tools: vec![],
+            execution_nice: None,
+            execution_ionice: false,
+            execution_result_paths: vec![],
+            sync_io_timeout_seconds: None,
+            remote_machine_ssh_options: vec![],
+            remote_machine_transport: Transport::Ssh,
+            remote_machine_module: None,
+            sync_whole_file: None,
+            sync_max_file_size_upload: None,
+            sync_max_file_size_download: None,
+            sync_parallelism: None,
+            sync_partial_dir: None,
+            local_compression_level_auto: false,
+            remote_compression_level_auto: false,
+            compression_skip_compress: default_skip_compress(),
+        }));
+    }
+
+    #[test]
+    fn parse_config_from_str_hooks() {
+        let content = "remote_machine=test@machine\nbefore_upload_hook=make lockfile\nafter_download_hook=./run-tests.sh --pulled";
+        assert_eq!(parse_config_from_str(content), Ok(IntermediateConfig {
+            remote_machine: Some(String::from("test@machine")),
+            before_upload_hook: Some(String::from("make lockfile")),
+            after_download_hook: Some(String::from("./run-tests.sh --pulled")),
+            ..IntermediateConfig::default()
+        }));
+    }
+
+    #[test]
+    fn parse_config_from_str_empty_hook() {
+        let content = "remote_machine=test@machine\nbefore_upload_hook=";
+        assert_eq!(parse_config_from_str(content).map(|config| config.before_upload_hook), Ok(None));
+    }
+
+    #[test]
+    fn resolve_hooks() {
+        let config = IntermediateConfig {
+            remote_machine: Some(String::from("test@machine")),
+            after_download_hook: Some(String::from("./run-tests.sh")),
+            ..IntermediateConfig::default()
+        };
+        assert_eq!(
+            Config::resolve(config, None).map(|config| (config.before_upload_hook, config.after_download_hook)),
+            Ok((None, Some(String::from("./run-tests.sh"))))
+        );
+    }
+
+    #[test]
+    fn merge_override_wins() {
+        let base_config = IntermediateConfig {
+            remote_machine_user: Some(String::from("base")),
+            local_compression_level: Some(5),
+            remote_compression_level: Some(5),
+            ..IntermediateConfig::default()
+        };
+        let override_config = IntermediateConfig {
+            remote_machine: Some(String::from("machine")),
+            remote_machine_user: Some(String::from("override")),
+            remote_compression_level: Some(2),
+            ..IntermediateConfig::default()
+        };
+        assert_eq!(merge(base_config, override_config), IntermediateConfig {
+            remote_machine: Some(String::from("machine")),
+            remote_machine_user: Some(String::from("override")),
+            local_compression_level: Some(5),
+            remote_compression_level: Some(2),
+            ..IntermediateConfig::default()
+        });
+    }
+
+    #[test]
+    fn merge_base_remote_machine_when_override_has_none() {
+        let base_config = IntermediateConfig {
+            remote_machine: Some(String::from("machine")),
+            remote_machine_identity_file: Some(String::from("~/.ssh/id_rsa")),
+            ..IntermediateConfig::default()
+        };
+        let override_config = IntermediateConfig {
+            remote_machine_port: Some(2222),
+            ..IntermediateConfig::default()
+        };
+        assert_eq!(merge(base_config, override_config), IntermediateConfig {
+            remote_machine: Some(String::from("machine")),
+            remote_machine_port: Some(2222),
+            remote_machine_identity_file: Some(String::from("~/.ssh/id_rsa")),
+            ..IntermediateConfig::default()
+        });
+    }
+
+    #[test]
+    fn merge_named_remote_machines_replace_base_remote_machine() {
+        let base_config = parse_config_from_str("remote_machine=machine\nremote_machine_user=test").unwrap();
+        let override_config = parse_config_from_str("remote_machine.build=build-host").unwrap();
+        let merged_config = merge(base_config, override_config);
+        assert_eq!(merged_config.remote_machine, None);
+        assert_eq!(merged_config.remote_machines.get("build"), Some(&String::from("build-host")));
+        assert_eq!(merged_config.remote_machine_user, Some(String::from("test")));
+    }
+
+    #[test]
+    fn merge_remote_env() {
+        let base_config = parse_config_from_str("remote_env.CI=false\nremote_env.JAVA_HOME=/opt/jdk").unwrap();
+        let override_config = parse_config_from_str("remote_env.CI=true").unwrap();
+        let merged_config = merge(base_config, override_config);
+        assert_eq!(merged_config.remote_env.get("CI"), Some(&String::from("true")));
+        assert_eq!(merged_config.remote_env.get("JAVA_HOME"), Some(&String::from("/opt/jdk")));
+    }
+
+    #[test]
+    fn from_file_global_config() {
+        let dir = env::temp_dir().join(format!("mainframer_config_global_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("global"), "remote_machine_user=test\nlocal_compression_level=5").unwrap();
+        fs::write(dir.join("config"), "remote_machine=machine\nlocal_compression_level=2").unwrap();
+
+        let result = Config::from_file(&dir.join("config"), Some(&dir.join("global")), None, None)
+            .map(|config| (config.remote_machine_target(), config.local_compression_level));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Ok((String::from("test@machine"), 2)));
+    }
+
+    #[test]
+    fn parse_config_from_str_binary_paths() {
+        let content = "remote_machine=test@machine\nsync_rsync_path=/opt/rsync/bin/rsync\nremote_machine_ssh_path=~/bin/ssh-wrapper";
+        assert_eq!(
+            parse_config_from_str(content).map(|config| (config.sync_rsync_path, config.remote_machine_ssh_path)),
+            Ok((Some(String::from("/opt/rsync/bin/rsync")), Some(String::from("~/bin/ssh-wrapper"))))
+        );
+    }
+
+    #[test]
+    fn resolve_binary_paths() {
+        let config = IntermediateConfig {
+            remote_machine: Some(String::from("test@machine")),
+            sync_rsync_path: Some(String::from("rsync3")),
+            ..IntermediateConfig::default()
+        };
+        assert_eq!(
+            Config::resolve(config, None).map(|config| (config.sync_rsync_path, config.remote_machine_ssh_path)),
+            Ok((String::from("rsync3"), String::from("ssh")))
+        );
+    }
+
+    #[test]
+    fn config_from_str_watch_debounce_millis() {
+        let content = "remote_machine=test@machine\nwatch_debounce_millis=1500";
+        assert_eq!(config_from_str(content, None).map(|config| config.watch_debounce_millis), Ok(1500));
+    }
+
+    #[test]
+    fn config_from_str_watch_debounce_millis_zero() {
+        let content = "remote_machine=test@machine\nwatch_debounce_millis=0";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("watch_debounce_millis"), expected: "a positive number", found: String::from("0") }));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_strict_host_key_checking() {
+        let content = "remote_machine=test@machine\nremote_machine_strict_host_key_checking=true";
+        assert_eq!(config_from_str(content, None).map(|config| config.remote_machine_strict_host_key_checking), Ok(true));
+    }
+
+    #[test]
+    fn config_from_str_remote_machine_strict_host_key_checking_not_boolean() {
+        let content = "remote_machine=test@machine\nremote_machine_strict_host_key_checking=yes";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("remote_machine_strict_host_key_checking"), expected: "either 'true' or 'false'", found: String::from("yes") }));
+    }
+
+    #[test]
+    fn config_from_str_execution_working_dir() {
+        let content = "remote_machine=test@machine\nexecution_working_dir=services/api/";
+        assert_eq!(config_from_str(content, None).map(|config| config.execution_working_dir), Ok(Some(String::from("services/api"))));
+    }
+
+    #[test]
+    fn config_from_str_execution_working_dir_project_dir() {
+        let content = "remote_machine=test@machine\nexecution_working_dir=.";
+        assert_eq!(config_from_str(content, None).map(|config| config.execution_working_dir), Ok(None));
+    }
+
+    #[test]
+    fn config_from_str_execution_source_profile() {
+        let content = "remote_machine=test@machine\nexecution_source_profile=~/.profile";
+        assert_eq!(config_from_str(content, None).map(|config| config.execution_source_profile), Ok(Some(String::from("~/.profile"))));
+        assert_eq!(config_from_str("remote_machine=test@machine", None).map(|config| config.execution_source_profile), Ok(None));
+    }
+
+    #[test]
+    fn config_from_str_execution_require_remote_tools() {
+        let content = "remote_machine=test@machine\nexecution_require_remote_tools=gradle  cargo /opt/bin/node";
+        assert_eq!(config_from_str(content, None).map(|config| config.execution_require_remote_tools), Ok(vec![
+            String::from("gradle"),
+            String::from("cargo"),
+            String::from("/opt/bin/node"),
+        ]));
+    }
+
+    #[test]
+    fn config_from_str_execution_require_remote_tools_invalid() {
+        let content = "remote_machine=test@machine\nexecution_require_remote_tools=gradle $(reboot)";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("execution_require_remote_tools"),
+            expected: "a whitespace-separated list of binaries like 'gradle cargo'",
+            found: String::from("$(reboot)"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_execution_nice() {
+        let content = "remote_machine=test@machine\nexecution_nice=-5\nexecution_ionice=true";
+        let config = config_from_str(content, None).unwrap();
+        assert_eq!(config.execution_nice, Some(-5));
+        assert!(config.execution_ionice);
+    }
+
+    #[test]
+    fn config_from_str_execution_nice_out_of_range() {
+        let content = "remote_machine=test@machine\nexecution_nice=20";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::OutOfRange { key: String::from("execution_nice"), min: -20, max: 19, found: String::from("20") }));
+    }
+
+    #[test]
+    fn config_from_str_execution_result_paths() {
+        let content = "remote_machine=test@machine\nexecution_result_paths=build/outputs/ target/release";
+        assert_eq!(config_from_str(content, None).map(|config| config.execution_result_paths), Ok(vec![
+            String::from("build/outputs"),
+            String::from("target/release"),
+        ]));
+    }
+
+    #[test]
+    fn config_from_str_execution_result_paths_outside_of_project_dir() {
+        let content = "remote_machine=test@machine\nexecution_result_paths=build/outputs ../other";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch {
+            key: String::from("execution_result_paths"),
+            expected: "a whitespace-separated list of paths inside of project dir",
+            found: String::from("../other"),
+        }));
+    }
+
+    #[test]
+    fn config_from_str_execution_working_dir_absolute() {
+        let content = "remote_machine=test@machine\nexecution_working_dir=/etc";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("execution_working_dir"), expected: "a path inside of project dir", found: String::from("/etc") }));
+    }
+
+    #[test]
+    fn config_from_str_execution_working_dir_escapes_project_dir() {
+        let content = "remote_machine=test@machine\nexecution_working_dir=services/../../other";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("execution_working_dir"), expected: "a path inside of project dir", found: String::from("services/../../other") }));
+    }
+
+    #[test]
+    fn config_from_str_sync_io_timeout_seconds() {
+        let content = "remote_machine=test@machine\nsync_io_timeout_seconds=30";
+        assert_eq!(config_from_str(content, None).map(|config| config.sync_io_timeout_seconds), Ok(Some(30)));
+    }
+
+    #[test]
+    fn config_from_str_sync_io_timeout_seconds_negative() {
+        let content = "remote_machine=test@machine\nsync_io_timeout_seconds=-5";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("sync_io_timeout_seconds"), expected: "a positive number", found: String::from("-5") }));
+    }
+
+    #[test]
+    fn parse_config_from_str_profiles() {
+        let content = "remote_machine=test@machine\nprofile.release.remote_compression_level=9\nprofile.release.execution_working_dir=app\nprofile.ci.remote_machine=ci@machine";
+        let config = parse_config_from_str(content).unwrap();
+        assert_eq!(config.profiles.keys().collect::<Vec<&String>>(), vec!["ci", "release"]);
+        assert_eq!(config.profiles["release"].remote_compression_level, Some(9));
+        assert_eq!(config.profiles["release"].execution_working_dir, Some(String::from("app")));
+        assert_eq!(config.profiles["ci"].remote_machine, Some(String::from("ci@machine")));
+    }
+
+    #[test]
+    fn parse_config_from_str_profile_invalid_value() {
+        let content = "remote_machine=test@machine\nprofile.release.remote_compression_level=max\nprofile.release.sync_chcksum=true";
+        let result = parse_config_from_str(content);
+        assert_eq!(result, Err(ConfigError::InProfile {
+            profile: String::from("release"),
+            error: Box::new(ConfigError::Multiple(vec![
+                ConfigError::UnknownKey { key: String::from("sync_chcksum"), suggestion: Some("sync_checksum") },
+                ConfigError::OutOfRange { key: String::from("remote_compression_level"), min: 0, max: 9, found: String::from("max") },
+            ])),
+        }));
+        assert_eq!(result.unwrap_err().to_string(), String::from(
+            "In profile 'release': Unknown config key 'sync_chcksum'. Did you mean 'sync_checksum'?\n\
+             In profile 'release': 'remote_compression_level' must be a number from 0 to 9, found 'max'."
+        ));
+    }
+
+    #[test]
+    fn parse_config_from_str_profile_without_key() {
+        let content = "remote_machine=test@machine\nprofile.release=true";
+        assert_eq!(parse_config_from_str(content), Err(ConfigError::Invalid(String::from("'profile.release' must be in 'profile.{name}.{key}' form."))));
+    }
+
+    #[test]
+    fn parse_config_from_str_nested_profile() {
+        let content = "remote_machine=test@machine\nprofile.release.profile.fast.dry_run=true";
+        assert_eq!(parse_config_from_str(content), Err(ConfigError::InProfile {
+            profile: String::from("release"),
+            error: Box::new(ConfigError::Invalid(String::from("profiles can't be nested."))),
+        }));
+    }
+
+    #[test]
+    fn profile_names_sorted() {
+        let content = "remote_machine=test@machine\nprofile.release.dry_run=true\nprofile.ci.dry_run=false\nprofile.debug.sync_checksum=true\nprofile.ci.remote_compression_level=9";
+        assert_eq!(parse_config_from_str(content).map(|config| config.profile_names()), Ok(vec![
+            String::from("ci"),
+            String::from("debug"),
+            String::from("release"),
+        ]));
+    }
+
+    #[test]
+    fn remote_machine_names_sorted() {
+        let content = "remote_machine.linux=linux-host\nremote_machine.arm=arm-host\nremote_machine.gpu=gpu-host";
+        assert_eq!(parse_config_from_str(content).map(|config| config.remote_machine_names()), Ok(vec![
+            String::from("arm"),
+            String::from("gpu"),
+            String::from("linux"),
+        ]));
+    }
+
+    #[test]
+    fn from_reader_lists_names_without_remote_machine() {
+        let content = "profile.ci.dry_run=true";
+        let config = IntermediateConfig::from_reader(content.as_bytes(), Path::new("<stdin>"), None).unwrap();
+        assert_eq!((config.profile_names(), config.remote_machine_names()), (vec![String::from("ci")], vec![]));
+    }
+
+    #[test]
+    fn apply_profile_overrides_and_inherits() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=2\nremote_compression_level=3\nprofile.release.remote_compression_level=9";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, Some("release")))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!((config.remote_machine_name, config.local_compression_level, config.remote_compression_level), (String::from("test@machine"), 2, 9));
+    }
+
+    #[test]
+    fn apply_profile_overrides_only_local_compression_level() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=2\nremote_compression_level=3\nprofile.offline.local_compression_level=0";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, Some("offline")))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!((config.local_compression_level, config.remote_compression_level), (0, 3));
+    }
+
+    #[test]
+    fn apply_profile_inherits_shared_compression_level() {
+        let content = "remote_machine=test@machine\ncompression_level=4\nprofile.offline.local_compression_level=0";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, Some("offline")))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!((config.local_compression_level, config.remote_compression_level), (0, 4));
+    }
+
+    #[test]
+    fn apply_profile_inherits_auto_compression_of_other_direction() {
+        let content = "remote_machine=test@machine\ncompression_auto=true\nprofile.offline.local_compression_level=0";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, Some("offline")))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!((config.local_compression_level, config.local_compression_level_auto, config.remote_compression_level_auto), (0, false, true));
+    }
+
+    #[test]
+    fn apply_profile_overrides_both_compression_levels() {
+        let content = "remote_machine=test@machine\nlocal_compression_level=2\nremote_compression_level=3\nprofile.release.local_compression_level=7\nprofile.release.remote_compression_level=8";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, Some("release")))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!((config.local_compression_level, config.remote_compression_level), (7, 8));
+    }
+
+    #[test]
+    fn apply_profile_overrides_remote_machine() {
+        let content = "remote_machine=test@machine\nremote_machine_user=test\nprofile.ci.remote_machine=ci.machine";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, Some("ci")))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!(config.remote_machine_target(), "test@ci.machine");
+    }
+
+    #[test]
+    fn apply_profile_not_selected() {
+        let content = "remote_machine=test@machine\nprofile.release.remote_compression_level=9";
+        let config = parse_config_from_str(content)
+            .and_then(|config| apply_profile(config, None))
+            .and_then(|config| Config::resolve(config, None))
+            .unwrap();
+        assert_eq!(config.remote_compression_level, 1);
+    }
+
+    #[test]
+    fn apply_profile_missing() {
+        let content = "remote_machine=test@machine\nprofile.release.dry_run=true\nprofile.ci.dry_run=false";
+        assert_eq!(
+            parse_config_from_str(content).and_then(|config| apply_profile(config, Some("debug"))),
+            Err(ConfigError::Invalid(String::from("profile 'debug' is not defined, available: ci, release.")))
+        );
+    }
+
+    #[test]
+    fn apply_profile_no_profiles() {
+        assert_eq!(
+            parse_config_from_str("remote_machine=test@machine").and_then(|config| apply_profile(config, Some("debug"))),
+            Err(ConfigError::Invalid(String::from("profile 'debug' is not defined.")))
+        );
+    }
+
+    #[test]
+    fn resolve_config_without_remote_machine() {
+        let config = IntermediateConfig {
+            local_compression_level: Some(2),
+            ..IntermediateConfig::default()
+        };
+        assert_eq!(Config::resolve(config, None), Err(ConfigError::Invalid(String::from("please specify 'remote_machine'."))));
+    }
+
+    #[test]
+    fn resolve_global_config_dir_xdg_config_home() {
+        assert_eq!(resolve_global_config_dir(Some(String::from("/xdg")), Some(String::from("/home/test"))), Some(PathBuf::from("/xdg/mainframer")));
+    }
+
+    #[test]
+    fn resolve_global_config_dir_home() {
+        assert_eq!(resolve_global_config_dir(Some(String::new()), Some(String::from("/home/test"))), Some(PathBuf::from("/home/test/.config/mainframer")));
+    }
+
+    #[test]
+    fn resolve_global_config_dir_unknown() {
+        assert_eq!(resolve_global_config_dir(None, None), None);
+    }
+
+    #[test]
+    fn config_from_str_timeout_seconds() {
+        let content = "remote_machine=test@machine\ntimeout_seconds=600";
+        assert_eq!(config_from_str(content, None).map(|config| config.timeout_seconds), Ok(Some(600)));
+    }
+
+    #[test]
+    fn config_from_str_timeout_seconds_negative() {
+        let content = "remote_machine=test@machine\ntimeout_seconds=-1";
+        assert_eq!(config_from_str(content, None), Err(ConfigError::TypeMismatch { key: String::from("timeout_seconds"), expected: "a positive number", found: String::from("-1") }));
     }
 }
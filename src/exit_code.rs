@@ -0,0 +1,114 @@
+use preflight::PreflightError;
+use sync::SyncFailure;
+use RunError;
+
+// Exit codes of Mainframer itself, the ones of remote command are passed through as is.
+// Reserved ones are taken from BSD "sysexits.h", so CI can tell "build failed" from "couldn't connect".
+// Remote command can exit with a reserved code too, Mainframer can't tell them apart then.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExitCode {
+    // Exit code of remote command, or of local one with "--local".
+    Command(i32),
+    // Failures not covered by others, i.e. rsync failing on its own.
+    Failure,
+    // Invalid command line arguments.
+    Usage,
+    // Remote machine could not be reached or is not set up for the run, i.e. project dir is not writable.
+    Connection,
+    // Local machine is not ready for the run: missing binaries, unreadable files, failed hooks.
+    LocalSetup,
+    // Invalid or missing config.
+    Config,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Command(exit_code) => exit_code,
+            ExitCode::Failure => 1,
+            ExitCode::Usage => 64,
+            ExitCode::Connection => 69,
+            ExitCode::LocalSetup => 70,
+            ExitCode::Config => 78,
+        }
+    }
+}
+
+impl From<&RunError> for ExitCode {
+    fn from(error: &RunError) -> ExitCode {
+        match *error {
+            RunError::NoCommand => ExitCode::Usage,
+            RunError::RemoteCommandOverRsyncd => ExitCode::Config,
+            RunError::WorkingDir | RunError::CommandFile(_) | RunError::Ignore(_) | RunError::ChangedFilesList => ExitCode::LocalSetup,
+            RunError::BeforeUploadHook(_) | RunError::AfterDownloadHook(_) => ExitCode::LocalSetup,
+            RunError::Upload(SyncFailure::Connection(_)) | RunError::Download(SyncFailure::Connection(_)) => ExitCode::Connection,
+            RunError::Upload(SyncFailure::Other(_)) | RunError::Download(SyncFailure::Other(_)) => ExitCode::Failure,
+        }
+    }
+}
+
+impl From<&PreflightError> for ExitCode {
+    fn from(error: &PreflightError) -> ExitCode {
+        match *error {
+            PreflightError::ProjectDir(_) => ExitCode::Connection,
+            PreflightError::RemoteTools(_) => ExitCode::Config,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_reserved() {
+        assert_eq!(ExitCode::Failure.code(), 1);
+        assert_eq!(ExitCode::Usage.code(), 64);
+        assert_eq!(ExitCode::Connection.code(), 69);
+        assert_eq!(ExitCode::LocalSetup.code(), 70);
+        assert_eq!(ExitCode::Config.code(), 78);
+    }
+
+    #[test]
+    fn code_command() {
+        assert_eq!(ExitCode::Command(0).code(), 0);
+        assert_eq!(ExitCode::Command(3).code(), 3);
+    }
+
+    #[test]
+    fn from_run_error_config() {
+        assert_eq!(ExitCode::from(&RunError::RemoteCommandOverRsyncd), ExitCode::Config);
+    }
+
+    #[test]
+    fn from_run_error_usage() {
+        assert_eq!(ExitCode::from(&RunError::NoCommand), ExitCode::Usage);
+    }
+
+    #[test]
+    fn from_run_error_connection() {
+        assert_eq!(ExitCode::from(&RunError::Upload(SyncFailure::Connection(String::from("unreachable")))), ExitCode::Connection);
+        assert_eq!(ExitCode::from(&RunError::Download(SyncFailure::Connection(String::from("unreachable")))), ExitCode::Connection);
+    }
+
+    #[test]
+    fn from_run_error_local_setup() {
+        assert_eq!(ExitCode::from(&RunError::WorkingDir), ExitCode::LocalSetup);
+        assert_eq!(ExitCode::from(&RunError::CommandFile(String::from("build.sh"))), ExitCode::LocalSetup);
+        assert_eq!(ExitCode::from(&RunError::Ignore(String::from("unreadable"))), ExitCode::LocalSetup);
+        assert_eq!(ExitCode::from(&RunError::ChangedFilesList), ExitCode::LocalSetup);
+        assert_eq!(ExitCode::from(&RunError::BeforeUploadHook(String::from("exit code 1"))), ExitCode::LocalSetup);
+        assert_eq!(ExitCode::from(&RunError::AfterDownloadHook(String::from("exit code 1"))), ExitCode::LocalSetup);
+    }
+
+    #[test]
+    fn from_run_error_sync_failure() {
+        assert_eq!(ExitCode::from(&RunError::Upload(SyncFailure::Other(String::from("rsync exit code '23'")))), ExitCode::Failure);
+    }
+
+    #[test]
+    fn from_preflight_error() {
+        assert_eq!(ExitCode::from(&PreflightError::ProjectDir(String::from("Remote path /opt is not writable by builder."))), ExitCode::Connection);
+        assert_eq!(ExitCode::from(&PreflightError::RemoteTools(vec![String::from("gradle")])), ExitCode::Config);
+    }
+}
@@ -0,0 +1,66 @@
+use config::Config;
+use output::Message;
+use output::Output;
+use signal;
+use std::path::Path;
+use std::process::Command;
+
+// Runs hook with local shell in project dir, hook output goes to Mainframer output as is.
+pub fn run_local_hook(hook_command: &str, local_dir_absolute_path: &Path, config: &Config, output: &Output) -> Result<(), String> {
+    if config.dry_run {
+        if output.shows(Message::Notice) {
+            println!("Dry run, would run local hook '{}'.", hook_command);
+        }
+
+        return Ok(());
+    }
+
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(hook_command)
+        .current_dir(local_dir_absolute_path)
+        .spawn()
+        .and_then(|mut process| {
+            signal::register_child(process.id());
+            let exit_status = process.wait();
+            signal::unregister_child(process.id());
+            exit_status
+        });
+
+    match result {
+        Err(_) => Err(format!("could not run '{}'", hook_command)),
+        Ok(exit_status) => match exit_status.code() {
+            Some(0) => Ok(()),
+            Some(exit_code) => Err(format!("'{}' exited with code {}", hook_command, exit_code)),
+            None => Err(format!("'{}' was terminated", hook_command))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use output::Verbosity;
+    use std::env;
+
+    fn config() -> Config {
+        config_from_str("remote_machine=machine", None).unwrap()
+    }
+
+    #[test]
+    fn run_local_hook_success() {
+        assert_eq!(run_local_hook("true", &env::temp_dir(), &config(), &Output::new(Verbosity::Quiet, true)), Ok(()));
+    }
+
+    #[test]
+    fn run_local_hook_failure() {
+        assert_eq!(run_local_hook("exit 3", &env::temp_dir(), &config(), &Output::new(Verbosity::Quiet, true)), Err(String::from("'exit 3' exited with code 3")));
+    }
+
+    #[test]
+    fn run_local_hook_dry_run() {
+        let config = config_from_str("remote_machine=machine\ndry_run=true", None).unwrap();
+        assert_eq!(run_local_hook("exit 3", &env::temp_dir(), &config, &Output::new(Verbosity::Quiet, true)), Ok(()));
+    }
+}
@@ -1,14 +1,35 @@
+use config;
+use std::fs::File;
+use std::io::prelude::*;
 use std::path::Path;
 use std::path::PathBuf;
 
+// Common patterns apply to both directions, "localignore" only to local → remote sync
+// and "remoteignore" only to remote → local one, a pattern present in both is ignored both ways.
+// Patterns passed with "--exclude" apply to both directions after all ignore files.
+// "include" lists the only files that are uploaded if present, downloads are not limited so build outputs still come back.
 pub struct Ignore {
-    pub common_ignore_file: Option<PathBuf>,
+    pub common_patterns: Vec<String>,
     pub local_ignore_file: Option<PathBuf>,
     pub remote_ignore_file: Option<PathBuf>,
+    pub cli_patterns: Vec<String>,
+    pub include_patterns: Vec<String>,
+}
+
+// All patterns that apply to each sync direction, in the same order they're passed to rsync.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IgnorePatterns {
+    pub upload: Vec<String>,
+    pub download: Vec<String>,
 }
 
 impl Ignore {
-    pub fn from_working_dir(working_dir: &Path) -> Ignore {
+    pub fn from_working_dir(working_dir: &Path) -> Result<Ignore, String> {
+        let global_ignore_file = config::global_config_dir().map(|dir| dir.join("ignore"));
+        Ignore::from_dirs(working_dir, global_ignore_file.as_deref())
+    }
+
+    fn from_dirs(working_dir: &Path, global_ignore_file: Option<&Path>) -> Result<Ignore, String> {
         let mut common_ignore_file = working_dir.to_path_buf();
         common_ignore_file.push(".mainframer/ignore");
 
@@ -18,12 +39,11 @@ impl Ignore {
         let mut remote_ignore_file = working_dir.to_path_buf();
         remote_ignore_file.push(".mainframer/remoteignore");
 
-        Ignore {
-            common_ignore_file: if common_ignore_file.exists() {
-                Some(common_ignore_file.to_path_buf())
-            } else {
-                None
-            },
+        let mut include_file = working_dir.to_path_buf();
+        include_file.push(".mainframer/include");
+
+        Ok(Ignore {
+            common_patterns: collect_ignore_patterns(&common_ignore_file, global_ignore_file)?,
             local_ignore_file: if local_ignore_file.exists() {
                 Some(local_ignore_file.to_path_buf())
             } else {
@@ -34,6 +54,220 @@ impl Ignore {
             } else {
                 None
             },
+            cli_patterns: vec![],
+            include_patterns: read_ignore_patterns(&include_file)?,
+        })
+    }
+
+    // "ignore" patterns go to both directions, "localignore" ones only to upload and "remoteignore" ones only to download.
+    pub fn patterns(&self) -> Result<IgnorePatterns, String> {
+        Ok(IgnorePatterns {
+            upload: self.direction_patterns(self.local_ignore_file.as_deref())?,
+            download: self.direction_patterns(self.remote_ignore_file.as_deref())?,
+        })
+    }
+
+    fn direction_patterns(&self, direction_ignore_file: Option<&Path>) -> Result<Vec<String>, String> {
+        let mut patterns = self.common_patterns.clone();
+
+        if let Some(direction_ignore_file) = direction_ignore_file {
+            patterns.extend(read_ignore_patterns(direction_ignore_file)?);
+        }
+
+        patterns.extend(self.cli_patterns.iter().cloned());
+
+        Ok(patterns)
+    }
+}
+
+// Global patterns go first so project ones are applied after them, absent files are skipped.
+pub fn collect_ignore_patterns(project_ignore: &Path, global_ignore: Option<&Path>) -> Result<Vec<String>, String> {
+    let mut patterns = Vec::new();
+
+    if let Some(global_ignore) = global_ignore {
+        patterns.extend(read_ignore_patterns(global_ignore)?);
+    }
+
+    patterns.extend(read_ignore_patterns(project_ignore)?);
+
+    Ok(patterns)
+}
+
+fn read_ignore_patterns(ignore_file: &Path) -> Result<Vec<String>, String> {
+    if !ignore_file.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut content = String::new();
+
+    let read_result = File::open(ignore_file).and_then(|mut file| file.read_to_string(&mut content));
+
+    if read_result.is_err() {
+        return Err(format!("Could not read ignore file '{}'.", ignore_file.to_string_lossy()));
+    }
+
+    Ok(parse_ignore_patterns(&content))
+}
+
+// Same rules as rsync uses for "--exclude-from" files: blank lines and lines starting with "#" or ";" are skipped.
+fn parse_ignore_patterns(content: &str) -> Vec<String> {
+    content.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mainframer_ignore_{}_{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_ignore_patterns_skips_comments_and_blank_lines() {
+        let content = "# comment\nbuild/\n\n  \n; comment\n*.iml\n";
+        assert_eq!(parse_ignore_patterns(content), vec![String::from("build/"), String::from("*.iml")]);
+    }
+
+    #[test]
+    fn collect_ignore_patterns_merges_global_and_project() {
+        let dir = temp_dir("merge");
+        fs::write(dir.join("global"), ".idea\n# comment\n.DS_Store").unwrap();
+        fs::write(dir.join("project"), "build/").unwrap();
+
+        let patterns = collect_ignore_patterns(&dir.join("project"), Some(&dir.join("global")));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(patterns, Ok(vec![String::from(".idea"), String::from(".DS_Store"), String::from("build/")]));
+    }
+
+    #[test]
+    fn collect_ignore_patterns_missing_global() {
+        let dir = temp_dir("missing_global");
+        fs::write(dir.join("project"), "build/").unwrap();
+
+        let patterns = collect_ignore_patterns(&dir.join("project"), Some(&dir.join("global")));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(patterns, Ok(vec![String::from("build/")]));
+    }
+
+    #[test]
+    fn collect_ignore_patterns_missing_project() {
+        let dir = temp_dir("missing_project");
+        fs::write(dir.join("global"), ".idea").unwrap();
+
+        let patterns = collect_ignore_patterns(&dir.join("project"), Some(&dir.join("global")));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(patterns, Ok(vec![String::from(".idea")]));
+    }
+
+    #[test]
+    fn collect_ignore_patterns_no_files() {
+        let dir = temp_dir("no_files");
+
+        let patterns = collect_ignore_patterns(&dir.join("project"), None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(patterns, Ok(vec![]));
+    }
+
+    fn patterns_for_files(name: &str, files: &[(&str, &str)]) -> Result<IgnorePatterns, String> {
+        let dir = temp_dir(name);
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+
+        for &(file, content) in files {
+            fs::write(dir.join(".mainframer").join(file), content).unwrap();
         }
+
+        let patterns = Ignore::from_dirs(&dir, None).and_then(|ignore| ignore.patterns());
+        fs::remove_dir_all(&dir).unwrap();
+        patterns
+    }
+
+    #[test]
+    fn patterns_all_presence_combinations() {
+        let all_files = [("ignore", "common"), ("localignore", "local"), ("remoteignore", "remote")];
+
+        for combination in 0..8 {
+            let files: Vec<(&str, &str)> = all_files.iter()
+                .enumerate()
+                .filter(|&(index, _)| combination & (1 << index) != 0)
+                .map(|(_, file)| *file)
+                .collect();
+
+            let present = |file: &str| files.iter().any(|&(name, _)| name == file);
+            let expected = |direction_file: &str, direction_pattern: &str| {
+                let mut patterns = vec![];
+                if present("ignore") {
+                    patterns.push(String::from("common"));
+                }
+                if present(direction_file) {
+                    patterns.push(String::from(direction_pattern));
+                }
+                patterns
+            };
+
+            assert_eq!(
+                patterns_for_files(&format!("combination_{}", combination), &files),
+                Ok(IgnorePatterns {
+                    upload: expected("localignore", "local"),
+                    download: expected("remoteignore", "remote"),
+                }),
+                "files: {:?}", files
+            );
+        }
+    }
+
+    #[test]
+    fn from_dirs_include_patterns() {
+        let dir = temp_dir("include");
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+        fs::write(dir.join(".mainframer/include"), "# allowlist\nsrc/\nCargo.toml\n").unwrap();
+
+        let include_patterns = Ignore::from_dirs(&dir, None).map(|ignore| ignore.include_patterns);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(include_patterns, Ok(vec![String::from("src/"), String::from("Cargo.toml")]));
+    }
+
+    #[test]
+    fn patterns_skip_comments_and_blank_lines() {
+        assert_eq!(
+            patterns_for_files("comments", &[("ignore", "# comment\nbuild/\n\n"), ("localignore", "; comment\n*.iml\n  \n")]),
+            Ok(IgnorePatterns {
+                upload: vec![String::from("build/"), String::from("*.iml")],
+                download: vec![String::from("build/")],
+            })
+        );
+    }
+
+    #[test]
+    fn patterns_cli_patterns_go_last() {
+        let dir = temp_dir("cli_patterns");
+        fs::write(dir.join("localignore"), "build/").unwrap();
+
+        let ignore = Ignore {
+            common_patterns: vec![String::from(".idea")],
+            local_ignore_file: Some(dir.join("localignore")),
+            remote_ignore_file: None,
+            cli_patterns: vec![String::from("*.log")],
+            include_patterns: vec![],
+        };
+        let patterns = ignore.patterns();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(patterns, Ok(IgnorePatterns {
+            upload: vec![String::from(".idea"), String::from("build/"), String::from("*.log")],
+            download: vec![String::from(".idea"), String::from("*.log")],
+        }));
     }
 }
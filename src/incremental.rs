@@ -0,0 +1,210 @@
+use config::Config;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use sync;
+use watch::Snapshot;
+
+// Inside of ".mainframer" so it's never synced itself.
+const MARKER_FILE: &str = ".mainframer/last_sync";
+
+// Start time of the last successful upload, where it went and files it uploaded, the latter are needed to detect deletions.
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Marker {
+    pub timestamp_seconds: u64,
+    pub destination: String,
+    pub files: BTreeSet<PathBuf>,
+}
+
+// Remote machine and project dir on it, same project synced elsewhere has nothing there yet.
+pub fn destination(local_dir_absolute_path: &Path, config: &Config) -> String {
+    format!("{}:{}", config.remote_machine_target(), sync::project_dir_on_remote_machine(local_dir_absolute_path, config))
+}
+
+// Missing, unreadable or other destination's marker means the next upload has to be a full one.
+pub fn read_marker(local_dir_absolute_path: &Path, destination: &str) -> Option<Marker> {
+    fs::read_to_string(local_dir_absolute_path.join(MARKER_FILE)).ok()
+        .and_then(|content| parse_marker(&content))
+        .filter(|marker| marker.destination == destination)
+}
+
+pub fn write_marker(local_dir_absolute_path: &Path, marker: &Marker) -> io::Result<()> {
+    fs::write(local_dir_absolute_path.join(MARKER_FILE), format_marker(marker))
+}
+
+// First line is a Unix timestamp in seconds, second one is destination, then one file per line relative to project dir.
+fn format_marker(marker: &Marker) -> String {
+    let mut content = format!("{}\n{}\n", marker.timestamp_seconds, marker.destination);
+
+    for file in &marker.files {
+        content.push_str(&format!("{}\n", file.to_string_lossy()));
+    }
+
+    content
+}
+
+fn parse_marker(content: &str) -> Option<Marker> {
+    let mut lines = content.lines();
+
+    let timestamp_seconds = lines.next()?.trim().parse().ok()?;
+    let destination = String::from(lines.next()?);
+
+    Some(Marker {
+        timestamp_seconds,
+        destination,
+        files: lines.filter(|line| !line.is_empty()).map(PathBuf::from).collect(),
+    })
+}
+
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+// Files modified since the marker or not uploaded last time, plus files that were uploaded last time but are gone now if deletions are synced.
+// Files without modification time are always considered changed, new ones too since renames and copies can keep an old one.
+pub fn changed_files(snapshot: &Snapshot, marker: &Marker, include_deleted: bool) -> Vec<PathBuf> {
+    let modified = snapshot.iter()
+        .filter(|&(file, &(modified, _))| !marker.files.contains(file) || modified.is_none_or(|modified| unix_seconds(modified) >= marker.timestamp_seconds))
+        .map(|(file, _)| file.clone());
+
+    let deleted = marker.files.iter()
+        .filter(|file| include_deleted && !snapshot.contains_key(*file))
+        .cloned();
+
+    modified.chain(deleted).collect()
+}
+
+// List for rsync "--files-from", unique per run same as ssh control socket.
+pub fn write_files_from(files: &[PathBuf]) -> io::Result<PathBuf> {
+    let path = env::temp_dir().join(format!("mainframer-{}.files", process::id()));
+    let content: String = files.iter().map(|file| format!("{}\n", file.to_string_lossy())).collect();
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+// Marker for the upload that started at "start" and uploaded the "snapshot" to "destination".
+pub fn marker_for(snapshot: &Snapshot, start: SystemTime, destination: &str) -> Marker {
+    Marker {
+        timestamp_seconds: unix_seconds(start),
+        destination: String::from(destination),
+        files: snapshot.keys().cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn time(seconds: u64) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    fn marker(timestamp_seconds: u64, files: &[&str]) -> Marker {
+        Marker {
+            timestamp_seconds,
+            destination: String::from("machine:~/mainframer/project"),
+            files: files.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn format_and_parse_marker() {
+        let marker = marker(1700000000, &["build.gradle", "src/Main.java"]);
+        assert_eq!(format_marker(&marker), "1700000000\nmachine:~/mainframer/project\nbuild.gradle\nsrc/Main.java\n");
+        assert_eq!(parse_marker(&format_marker(&marker)), Some(marker));
+    }
+
+    #[test]
+    fn parse_marker_invalid_timestamp() {
+        assert_eq!(parse_marker("yesterday\nbuild.gradle\n"), None);
+        assert_eq!(parse_marker(""), None);
+        assert_eq!(parse_marker("1700000000"), None);
+    }
+
+    #[test]
+    fn write_and_read_marker() {
+        let dir = env::temp_dir().join(format!("mainframer_incremental_marker_{}", process::id()));
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+
+        let no_marker = read_marker(&dir, "machine:~/mainframer/project");
+        write_marker(&dir, &marker(1700000000, &["src/Main.java"])).unwrap();
+        let written_marker = read_marker(&dir, "machine:~/mainframer/project");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(no_marker, None);
+        assert_eq!(written_marker, Some(marker(1700000000, &["src/Main.java"])));
+    }
+
+    #[test]
+    fn read_marker_of_other_destination() {
+        let dir = env::temp_dir().join(format!("mainframer_incremental_other_destination_{}", process::id()));
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+
+        write_marker(&dir, &marker(1700000000, &["src/Main.java"])).unwrap();
+        let other_machine_marker = read_marker(&dir, "other:~/mainframer/project");
+        let other_dir_marker = read_marker(&dir, "machine:/tmp/project");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(other_machine_marker, None);
+        assert_eq!(other_dir_marker, None);
+    }
+
+    #[test]
+    fn destination_of_config() {
+        let config = config_from_str("remote_machine=user@machine\nremote_folder_prefix=/tmp", None).unwrap();
+        assert_eq!(destination(Path::new("/home/user/project"), &config), "user@machine:/tmp/home/user/project");
+    }
+
+    #[test]
+    fn changed_files_skips_older_files() {
+        let mut snapshot = BTreeMap::new();
+        snapshot.insert(PathBuf::from("old.txt"), (time(99), 1));
+        snapshot.insert(PathBuf::from("same_second.txt"), (time(100), 1));
+        snapshot.insert(PathBuf::from("new.txt"), (time(101), 1));
+        snapshot.insert(PathBuf::from("unknown_time.txt"), (None, 1));
+
+        assert_eq!(changed_files(&snapshot, &marker(100, &["old.txt"]), true), vec![
+            PathBuf::from("new.txt"),
+            PathBuf::from("same_second.txt"),
+            PathBuf::from("unknown_time.txt"),
+        ]);
+    }
+
+    #[test]
+    fn changed_files_includes_deleted_files() {
+        let mut snapshot = BTreeMap::new();
+        snapshot.insert(PathBuf::from("kept.txt"), (time(10), 1));
+
+        let marker = marker(100, &["kept.txt", "removed.txt"]);
+        assert_eq!(changed_files(&snapshot, &marker, true), vec![PathBuf::from("removed.txt")]);
+        assert_eq!(changed_files(&snapshot, &marker, false), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn changed_files_includes_renamed_file_with_old_time() {
+        let mut snapshot = BTreeMap::new();
+        snapshot.insert(PathBuf::from("kept.txt"), (time(10), 1));
+        snapshot.insert(PathBuf::from("renamed.txt"), (time(10), 1));
+
+        let marker = marker(100, &["kept.txt", "original.txt"]);
+        assert_eq!(changed_files(&snapshot, &marker, true), vec![PathBuf::from("renamed.txt"), PathBuf::from("original.txt")]);
+        assert_eq!(changed_files(&snapshot, &marker, false), vec![PathBuf::from("renamed.txt")]);
+    }
+
+    #[test]
+    fn marker_for_snapshot() {
+        let mut snapshot = BTreeMap::new();
+        snapshot.insert(PathBuf::from("src/Main.java"), (time(10), 1));
+
+        assert_eq!(marker_for(&snapshot, UNIX_EPOCH + Duration::from_millis(1700000000500), "machine:~/mainframer/project"), marker(1700000000, &["src/Main.java"]));
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub const CONFIG_TEMPLATE: &str = "\
+# Remote machine name from SSH config or hostname / IP address.
+remote_machine=user@build-machine
+
+# Compression levels from 0 (no compression) to 9 (best compression),
+# for local → remote and remote → local sync.
+local_compression_level=1
+remote_compression_level=1
+";
+
+// Config template and empty direction-specific ignore files, existing ones are kept unless "force".
+pub fn init(project_dir: &Path, force: bool) -> Result<Vec<PathBuf>, String> {
+    let config_dir = project_dir.join(".mainframer");
+
+    let files = vec![
+        (config_dir.join("config"), CONFIG_TEMPLATE),
+        (config_dir.join("localignore"), ""),
+        (config_dir.join("remoteignore"), ""),
+    ];
+
+    if !force {
+        if let Some((existing_file, _)) = files.iter().find(|(file, _)| file.exists()) {
+            return Err(format!("'{}' already exists, pass '--force' to overwrite it.", existing_file.to_string_lossy()));
+        }
+    }
+
+    if fs::create_dir_all(&config_dir).is_err() {
+        return Err(format!("Could not create '{}'.", config_dir.to_string_lossy()));
+    }
+
+    for (file, content) in &files {
+        if fs::write(file, content).is_err() {
+            return Err(format!("Could not write '{}'.", file.to_string_lossy()));
+        }
+    }
+
+    Ok(files.into_iter().map(|(file, _)| file).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use config::parse_config_from_str;
+    use std::env;
+
+    #[test]
+    fn config_template_parses() {
+        assert!(parse_config_from_str(CONFIG_TEMPLATE).is_ok());
+        assert_eq!(config_from_str(CONFIG_TEMPLATE, None).map(|config| config.remote_machine_name), Ok(String::from("user@build-machine")));
+    }
+
+    #[test]
+    fn init_creates_files() {
+        let dir = env::temp_dir().join(format!("mainframer_init_creates_files_{}", std::process::id()));
+
+        let result = init(&dir, false);
+        let config_content = fs::read_to_string(dir.join(".mainframer/config"));
+        let local_ignore_exists = dir.join(".mainframer/localignore").exists();
+        let remote_ignore_exists = dir.join(".mainframer/remoteignore").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.map(|files| files.len()), Ok(3));
+        assert_eq!(config_content.unwrap(), CONFIG_TEMPLATE);
+        assert!(local_ignore_exists);
+        assert!(remote_ignore_exists);
+    }
+
+    #[test]
+    fn init_keeps_existing_files() {
+        let dir = env::temp_dir().join(format!("mainframer_init_keeps_existing_files_{}", std::process::id()));
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+        fs::write(dir.join(".mainframer/localignore"), "build/").unwrap();
+
+        let result = init(&dir, false);
+        let local_ignore_content = fs::read_to_string(dir.join(".mainframer/localignore")).unwrap();
+        let config_exists = dir.join(".mainframer/config").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Err(format!("'{}' already exists, pass '--force' to overwrite it.", dir.join(".mainframer/localignore").to_string_lossy())));
+        assert_eq!(local_ignore_content, "build/");
+        assert!(!config_exists);
+    }
+
+    #[test]
+    fn init_force_overwrites_existing_files() {
+        let dir = env::temp_dir().join(format!("mainframer_init_force_{}", std::process::id()));
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+        fs::write(dir.join(".mainframer/config"), "remote_machine=old").unwrap();
+
+        let result = init(&dir, true);
+        let config_content = fs::read_to_string(dir.join(".mainframer/config")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(config_content, CONFIG_TEMPLATE);
+    }
+}
@@ -0,0 +1,453 @@
+pub mod args;
+pub mod clock;
+pub mod completions;
+pub mod compression;
+pub mod config;
+pub mod exit_code;
+pub mod hook;
+pub mod ignore;
+pub mod incremental;
+pub mod init;
+pub mod preflight;
+pub mod output;
+pub mod progress;
+pub mod remote_command;
+pub mod report;
+pub mod requirements;
+pub mod runner;
+pub mod signal;
+pub mod ssh;
+pub mod sync;
+pub mod time;
+pub mod watch;
+
+use args::Args;
+use args::Phase;
+use config::Config;
+use config::Transport;
+use ignore::Ignore;
+use output::Message;
+use output::Output;
+use output::Verbosity;
+use progress::ProgressEvent;
+use remote_command::CommandFile;
+use remote_command::RemoteCommandError;
+use runner::CommandRunner;
+use runner::SystemRunner;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use sync::SyncFailure;
+use sync::TransferStats;
+use time::*;
+
+// Outcome of a single upload → execute → download cycle.
+#[derive(Debug, PartialEq, Eq, Default, Clone)]
+pub struct RunResult {
+    // Exit code of remote command, 0 if it wasn't executed.
+    pub exit_code: i32,
+    pub timings: RunTimings,
+    pub upload_stats: TransferStats,
+    pub download_stats: TransferStats,
+}
+
+impl RunResult {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+// Failed remote command is not an error, it's reported by "RunResult::exit_code".
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunError {
+    WorkingDir,
+    NoCommand,
+    RemoteCommandOverRsyncd,
+    CommandFile(String),
+    Ignore(String),
+    BeforeUploadHook(String),
+    ChangedFilesList,
+    Upload(SyncFailure),
+    Download(SyncFailure),
+    AfterDownloadHook(String),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::WorkingDir => write!(formatter, "Could not resolve working directory, make sure it exists and user has enough permissions to work with it."),
+            RunError::NoCommand => write!(formatter, "No command provided to run on the remote machine."),
+            RunError::RemoteCommandOverRsyncd => write!(formatter, "Remote command can't be executed with 'rsyncd' transport, please pass '--upload-only' or '--download-only'."),
+            RunError::CommandFile(ref path) => write!(formatter, "Could not read command file '{}'.", path),
+            RunError::Ignore(ref message) => write!(formatter, "{}", message),
+            RunError::BeforeUploadHook(ref error) => write!(formatter, "Before upload hook failed: {}.", error),
+            RunError::ChangedFilesList => write!(formatter, "Could not write list of changed files."),
+            RunError::Upload(ref error) => write!(formatter, "Sync local → remote machine failed: {}.", error),
+            RunError::Download(ref error) => write!(formatter, "Sync remote → local machine failed: {}.", error),
+            RunError::AfterDownloadHook(ref error) => write!(formatter, "After download hook failed: {}.", error),
+        }
+    }
+}
+
+// Runs "command" in current working dir same as "mainframer 'command'" does, but without Mainframer's own messages.
+// Remote command output and timeouts are still printed, other errors are returned.
+pub fn run(mut config: Config, command: &str) -> Result<RunResult, RunError> {
+    if config.remote_machine_transport == Transport::Rsyncd {
+        return Err(RunError::RemoteCommandOverRsyncd);
+    }
+
+    let local_dir_absolute_path = env::current_dir().and_then(fs::canonicalize).map_err(|_| RunError::WorkingDir)?;
+    let ignore = Ignore::from_working_dir(&local_dir_absolute_path).map_err(RunError::Ignore)?;
+
+    let output = Output::new(Verbosity::Quiet, true);
+
+    compression::apply_auto_compression(&local_dir_absolute_path, &mut config, &output, &SystemRunner);
+
+    let args = Args {
+        commands: vec![String::from(command)],
+        ..Args::default()
+    };
+
+    let result = run_cycle(&local_dir_absolute_path, &args, None, &config, &ignore, &output, &SystemRunner);
+
+    ssh::close_master_connection(&config);
+
+    result
+}
+
+// Phases are the ones of "args.run_mode", reports are left to the caller.
+pub fn run_cycle(local_dir_absolute_path: &Path, args: &Args, command_file: Option<&CommandFile>, config: &Config, ignore: &Ignore, output: &Output, runner: &dyn CommandRunner) -> Result<RunResult, RunError> {
+    let mut result = RunResult::default();
+    let phases = args.run_mode.phases();
+
+    if phases.contains(&Phase::Execute) && !remote_command::has_command(&args.commands, command_file) {
+        return Err(RunError::NoCommand);
+    }
+
+    if phases.contains(&Phase::Upload) {
+        if let Some(ref hook_command) = config.before_upload_hook {
+            if let Err(error) = hook::run_local_hook(hook_command, local_dir_absolute_path, config, output) {
+                return Err(RunError::BeforeUploadHook(error));
+            }
+        }
+
+        // Taken before the snapshot so files changed while uploading are uploaded again next time.
+        let upload_start = SystemTime::now();
+        let snapshot = if args.since { Some(watch::snapshot(local_dir_absolute_path, &upload_patterns(config, ignore).map_err(RunError::Ignore)?)) } else { None };
+
+        // Without marker there is nothing to compare with, so the upload is a full one.
+        let destination = incremental::destination(local_dir_absolute_path, config);
+        let files_from = match (&snapshot, incremental::read_marker(local_dir_absolute_path, &destination)) {
+            (Some(snapshot), Some(marker)) if !args.full && !config.force_full_upload => {
+                let changed_files = incremental::changed_files(snapshot, &marker, config.sync_delete_extraneous);
+
+                match incremental::write_files_from(&changed_files) {
+                    Err(_) => return Err(RunError::ChangedFilesList),
+                    Ok(path) => Some(path)
+                }
+            }
+            _ => None
+        };
+
+        emit_progress(args, ProgressEvent::UploadStart);
+
+        let sync_result = sync_before_remote_command(local_dir_absolute_path, args, config, ignore, files_from.as_deref(), output, runner);
+
+        if let Some(ref files_from) = files_from {
+            let _ = fs::remove_file(files_from);
+        }
+
+        let (stats, upload_duration) = match sync_result {
+            Err(error) => return Err(RunError::Upload(error)),
+            Ok(value) => value
+        };
+
+        // Written only after successful upload, a failed one has to be repeated.
+        if let Some(snapshot) = snapshot.filter(|_| !config.dry_run) {
+            if incremental::write_marker(local_dir_absolute_path, &incremental::marker_for(&snapshot, upload_start, &destination)).is_err() && output.shows(Message::Warning) {
+                eprintln!("Could not write '.mainframer/last_sync', next upload will be a full one.");
+            }
+        }
+
+        result.upload_stats = stats;
+        result.timings.upload = upload_duration;
+        emit_progress(args, ProgressEvent::UploadDone);
+    }
+
+    let mut remote_command_result = Ok(());
+
+    if phases.contains(&Phase::Execute) {
+        emit_progress(args, ProgressEvent::ExecuteStart);
+        let (command_result, remote_execution_duration) = execute_remote_command(local_dir_absolute_path, args, command_file, config, output, runner);
+        remote_command_result = command_result;
+        result.timings.execution = remote_execution_duration;
+        emit_progress(args, ProgressEvent::ExecuteDone);
+    }
+
+    let download = phases.contains(&Phase::Download);
+
+    if download && sync::should_sync_remote_to_local(remote_command_result.is_ok(), config) {
+        emit_progress(args, ProgressEvent::DownloadStart);
+
+        let (stats, download_duration) = match sync_after_remote_command(local_dir_absolute_path, args, config, ignore, output, runner) {
+            Err(error) => return Err(RunError::Download(error)),
+            Ok(value) => value
+        };
+        result.download_stats = stats;
+        result.timings.download = download_duration;
+        emit_progress(args, ProgressEvent::DownloadDone);
+
+        if let Some(ref hook_command) = config.after_download_hook {
+            if let Err(error) = hook::run_local_hook(hook_command, local_dir_absolute_path, config, output) {
+                return Err(RunError::AfterDownloadHook(error));
+            }
+        }
+    } else if download && output.shows(Message::Notice) {
+        println!("Sync remote → local machine skipped: remote command failed and 'sync_back_on_failure' is disabled.");
+    }
+
+    if let Err(ref error) = remote_command_result {
+        result.exit_code = error.exit_code();
+    }
+
+    Ok(result)
+}
+
+// Hooks are tied to sync phases so they are skipped along with them.
+pub fn run_locally(local_dir_absolute_path: &Path, args: &Args, command_file: Option<&CommandFile>, config: &Config, output: &Output, runner: &dyn CommandRunner) -> i32 {
+    output.print_phase_start("Executing command on local machine");
+    output.print_line_break();
+
+    let start = Instant::now();
+
+    let result = remote_command::execute_local_command(
+        &args.commands,
+        command_file,
+        args.keep_going,
+        config,
+        &sync::execution_dir_on_local_machine(local_dir_absolute_path, config),
+        output,
+        runner,
+    );
+
+    let duration = start.elapsed();
+
+    if let Err(RemoteCommandError::TimedOut(timeout_seconds)) = result {
+        eprintln!("\nLocal command timed out after {} seconds.\n", timeout_seconds);
+    }
+
+    if output.shows(Message::Summary) {
+        match result {
+            Err(_) => eprintln!("\nFailure: took {}.", format_duration(duration)),
+            _ => println!("\nSuccess: took {}.", format_duration(duration))
+        }
+    }
+
+    match result {
+        Ok(_) => 0,
+        Err(ref error) => error.exit_code(),
+    }
+}
+
+// Files that are uploaded are the ones that are not excluded from upload.
+pub fn upload_patterns(config: &Config, ignore: &Ignore) -> Result<Vec<String>, String> {
+    let mut patterns = sync::built_in_patterns(config);
+    patterns.extend(ignore.patterns()?.upload);
+    Ok(patterns)
+}
+
+fn emit_progress(args: &Args, event: ProgressEvent) {
+    if args.machine_readable_progress {
+        println!("{}", progress::format_event(event));
+    }
+}
+
+fn progress_callback<'a>(args: &Args, on_progress: &'a dyn Fn(u8)) -> Option<&'a dyn Fn(u8)> {
+    if args.machine_readable_progress {
+        Some(on_progress)
+    } else {
+        None
+    }
+}
+
+fn sync_before_remote_command(local_dir_absolute_path: &Path, args: &Args, config: &Config, ignore: &Ignore, files_from: Option<&Path>, output: &Output, runner: &dyn CommandRunner) -> Result<(TransferStats, Duration), SyncFailure> {
+    let on_upload_progress = |percent| emit_progress(args, ProgressEvent::UploadProgress(percent));
+    let on_progress = progress_callback(args, &on_upload_progress);
+
+    let parallelism = sync::clamp_parallelism(config.sync_parallelism);
+
+    let start = Instant::now();
+
+    let result = match files_from {
+        // Progress of concurrent rsync processes doesn't add up to a single percentage.
+        None if parallelism > 1 && on_progress.is_none() => {
+            output.print_phase_start(&format!("Sync local → remote machine, {} rsync processes in parallel", parallelism));
+            let upload_patterns = upload_patterns(config, ignore).map_err(SyncFailure::Other)?;
+            sync::sync_local_to_remote_parallel(local_dir_absolute_path, config, ignore, &upload_patterns, parallelism, runner)
+        }
+        None => {
+            output.print_phase_start("Sync local → remote machine");
+            sync::sync_local_to_remote(local_dir_absolute_path, config, ignore, runner, on_progress).map(|rsync_stdout| vec![rsync_stdout])
+        }
+        Some(files_from) => {
+            output.print_phase_start("Sync changed files local → remote machine");
+            sync::sync_changed_local_to_remote(local_dir_absolute_path, config, ignore, files_from, runner, on_progress).map(|rsync_stdout| vec![rsync_stdout])
+        }
+    };
+
+    let duration = start.elapsed();
+
+    match result {
+        Err(error) => Err(error),
+        Ok(rsync_stdouts) => {
+            output.print_phase_done("Sync", duration);
+            let stats = print_transfer_stats(&rsync_stdouts, args.stats, output);
+            output.print_line_break();
+            Ok((stats, duration))
+        }
+    }
+}
+
+fn execute_remote_command(local_dir_absolute_path: &Path, args: &Args, command_file: Option<&CommandFile>, config: &Config, output: &Output, runner: &dyn CommandRunner) -> (Result<(), RemoteCommandError>, Duration) {
+    output.print_phase_start("Executing command on remote machine");
+    output.print_line_break();
+
+    let start = Instant::now();
+
+    let result = remote_command::execute_remote_command(
+        &args.commands,
+        command_file,
+        args.keep_going,
+        config,
+        sync::execution_dir_on_remote_machine(local_dir_absolute_path, config).as_ref(),
+        output,
+        runner,
+    );
+
+    let duration = start.elapsed();
+
+    match result {
+        Err(RemoteCommandError::TimedOut(timeout_seconds)) => eprintln!("\nRemote command timed out after {} seconds.\n", timeout_seconds),
+        Err(RemoteCommandError::Failed(_)) => {
+            output.print_line_break();
+            output.print_phase_failed("Execution", duration);
+            output.print_line_break();
+        }
+        Ok(_) => {
+            output.print_line_break();
+            output.print_phase_done("Execution", duration);
+            output.print_line_break();
+        }
+    }
+
+    (result, duration)
+}
+
+fn sync_after_remote_command(working_dir_name: &Path, args: &Args, config: &Config, ignore: &Ignore, output: &Output, runner: &dyn CommandRunner) -> Result<(TransferStats, Duration), SyncFailure> {
+    let on_download_progress = |percent| emit_progress(args, ProgressEvent::DownloadProgress(percent));
+    let on_progress = progress_callback(args, &on_download_progress);
+
+    output.print_phase_start("Sync remote → local machine");
+
+    let start = Instant::now();
+
+    let result = sync::sync_remote_to_local(
+        working_dir_name,
+        config,
+        ignore,
+        runner,
+        on_progress,
+    );
+
+    let duration = start.elapsed();
+
+    match result {
+        Err(error) => Err(error),
+        Ok(rsync_stdout) => {
+            output.print_phase_done("Sync", duration);
+            Ok((print_transfer_stats(&[rsync_stdout], args.stats, output), duration))
+        }
+    }
+}
+
+// Falls back to rsync output as is if it can't be parsed.
+fn print_transfer_stats(rsync_stdouts: &[String], print_stats: bool, output: &Output) -> TransferStats {
+    let stats = sync::sum_stats(rsync_stdouts);
+
+    if print_stats && output.shows(Message::TransferStats) {
+        match stats {
+            None => rsync_stdouts.iter().for_each(|rsync_stdout| print!("{}", rsync_stdout)),
+            Some(ref stats) => println!("{}", sync::format_transfer_stats(stats))
+        }
+    }
+
+    stats.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use args::RunMode;
+    use config::config_from_str;
+    use runner::RecordingRunner;
+
+    fn no_ignore() -> Ignore {
+        Ignore {
+            common_patterns: vec![],
+            local_ignore_file: None,
+            remote_ignore_file: None,
+            cli_patterns: vec![],
+            include_patterns: vec![],
+        }
+    }
+
+    #[test]
+    fn run_cycle_no_execute_syncs_both_ways_without_ssh() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let args = Args {
+            run_mode: RunMode::NoExecute,
+            ..Args::default()
+        };
+        let runner = RecordingRunner::new(0, "");
+
+        let result = run_cycle(Path::new("/project"), &args, None, &config, &no_ignore(), &Output::new(Verbosity::Quiet, true), &runner);
+
+        assert_eq!(result.map(|result| result.exit_code), Ok(0));
+        let invocations = runner.invocations();
+        let programs: Vec<&str> = invocations.iter().map(|(program, _)| program.as_str()).collect();
+        assert_eq!(programs, vec!["rsync", "rsync"]);
+        assert_eq!(invocations[0].1.last(), Some(&String::from("machine:~/mainframer/project")));
+        assert_eq!(invocations[1].1.last(), Some(&String::from("./")));
+    }
+
+    #[test]
+    fn run_cycle_blank_command_runs_nothing() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let args = Args {
+            commands: vec![String::from("  ")],
+            ..Args::default()
+        };
+        let runner = RecordingRunner::new(0, "");
+
+        let result = run_cycle(Path::new("/project"), &args, None, &config, &no_ignore(), &Output::new(Verbosity::Quiet, true), &runner);
+
+        assert_eq!(result, Err(RunError::NoCommand));
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn run_cycle_no_execute_without_command() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let args = Args {
+            commands: vec![String::from("")],
+            run_mode: RunMode::NoExecute,
+            ..Args::default()
+        };
+        let result = run_cycle(Path::new("/project"), &args, None, &config, &no_ignore(), &Output::new(Verbosity::Quiet, true), &RecordingRunner::new(0, ""));
+        assert_eq!(result.map(|result| result.exit_code), Ok(0));
+    }
+}
@@ -1,132 +1,312 @@
-mod args;
-mod config;
-mod ignore;
-mod remote_command;
-mod sync;
-mod time;
-
-use args::Args;
-use config::Config;
-use ignore::*;
+extern crate mainframer;
+
+use mainframer::args::Args;
+use mainframer::args::Listing;
+use mainframer::args::Phase;
+use mainframer::args::ReportFormat;
+use mainframer::config::Config;
+use mainframer::config::IntermediateConfig;
+use mainframer::config::Transport;
+use mainframer::exit_code::ExitCode;
+use mainframer::ignore::*;
+use mainframer::output::Message;
+use mainframer::output::Output;
+use mainframer::remote_command::CommandFile;
+use mainframer::runner::CommandRunner;
+use mainframer::runner::EchoingRunner;
+use mainframer::runner::SystemRunner;
+use mainframer::report::RunReport;
+use mainframer::sync::TotalBytes;
+use mainframer::time::*;
+use mainframer::clock;
+use mainframer::completions;
+use mainframer::compression;
+use mainframer::config;
+use mainframer::init;
+use mainframer::preflight;
+use mainframer::remote_command;
+use mainframer::report;
+use mainframer::requirements;
+use mainframer::run_cycle;
+use mainframer::run_locally;
+use mainframer::signal;
+use mainframer::ssh;
+use mainframer::sync;
+use mainframer::upload_patterns;
+use mainframer::watch;
+use mainframer::RunError;
 use std::env;
 use std::fs;
 use std::path::Path;
 use std::process;
+use std::time::Duration;
 use std::time::Instant;
-use time::*;
 
 fn main() {
-    println!(":: Mainframer v{}\n", env!("CARGO_PKG_VERSION"));
     let raw_args: Vec<String> = env::args().skip(1).collect();
 
     let args = match Args::parse(raw_args.as_ref()) {
-        Err(message) => exit_with_error(&message, 1),
+        Err(message) => exit_with_error(&message, ExitCode::Usage),
         Ok(value) => value,
     };
 
+    // Machine-readable report, progress and resolved config replace Mainframer messages on stdout, the ones on stderr stay.
+    let stdout_taken = args.report_format != ReportFormat::Human || args.machine_readable_progress || args.explain || args.list.is_some() || args.completions.is_some();
+    let output = Output { stdout_taken, ..Output::new(args.verbosity, args.no_color) };
+
+    if let Some(shell) = args.completions {
+        print!("{}", completions::script(shell));
+        process::exit(0);
+    }
+
+    if output.shows(Message::Banner) {
+        println!(":: Mainframer v{}\n", env!("CARGO_PKG_VERSION"));
+    }
+
     let local_dir_absolute_path = match env::current_dir() {
-        Err(_) => exit_with_error(&"Could not resolve working directory, make sure it exists and user has enough permissions to work with it.", 1),
+        Err(_) => exit_with_error("Could not resolve working directory, make sure it exists and user has enough permissions to work with it.", ExitCode::LocalSetup),
         Ok(value) => fs::canonicalize(value).unwrap()
     };
 
-    let mut config_file = local_dir_absolute_path.to_owned();
-    config_file.push(".mainframer/config");
+    if args.init {
+        match init::init(&local_dir_absolute_path, args.force) {
+            Err(message) => exit_with_error(&message, ExitCode::LocalSetup),
+            Ok(files) => for file in files {
+                println!("Created '{}'.", file.to_string_lossy());
+            }
+        }
+
+        process::exit(0);
+    }
+
+    let global_config_file = config::global_config_file();
 
-    let config = match Config::from_file(config_file.as_path()) {
-        Err(message) => exit_with_error(&message, 1),
+    let default_config_path = local_dir_absolute_path.join(".mainframer/config");
+    let config_paths: Vec<&Path> = if args.config_paths.is_empty() {
+        vec![&default_config_path]
+    } else {
+        args.config_paths.iter().map(Path::new).collect()
+    };
+
+    // Config doesn't have to be complete for it, i.e. named machines are listed without picking one.
+    if let Some(listing) = args.list {
+        let names = match IntermediateConfig::from_files(&config_paths, global_config_file.as_deref()) {
+            Err(error) => exit_with_error(&error.to_string(), ExitCode::Config),
+            Ok(ref config) if listing == Listing::Profiles => config.profile_names(),
+            Ok(ref config) => config.remote_machine_names()
+        };
+
+        for name in names {
+            println!("{}", name);
+        }
+
+        process::exit(0);
+    }
+
+    let mut config = match Config::from_files(&config_paths, global_config_file.as_deref(), args.remote_machine.as_deref(), args.profile.as_deref()) {
+        Err(error) => exit_with_error(&error.to_string(), ExitCode::Config),
         Ok(value) => value
     };
 
-    let ignore = Ignore::from_working_dir(&local_dir_absolute_path);
+    if args.config_check {
+        if output.shows(Message::Notice) {
+            println!("Config is valid.");
+        }
 
-    let start = Instant::now();
+        process::exit(0);
+    }
 
-    if let Err(error) = sync_before_remote_command(&local_dir_absolute_path, &config, &ignore) {
-        exit_with_error(&format!("Sync local → remote machine failed: {}.", error), 1)
+    if args.dry_run {
+        config.dry_run = true;
     }
 
-    let remote_command_result = execute_remote_command(&local_dir_absolute_path, &args, &config);
+    if args.force_full_upload {
+        config.force_full_upload = true;
+    }
 
-    if let Err(error) = sync_after_remote_command(&local_dir_absolute_path, &config, &ignore) {
-        exit_with_error(&format!("Sync remote → local machine failed: {}.", error), 1)
+    if args.delete {
+        config.sync_delete_extraneous = true;
     }
 
-    let duration = start.elapsed();
+    if args.tty {
+        config.execution_allocate_tty = true;
+    }
 
-    match remote_command_result {
-        Err(_) => exit_with_error(&format!("\nFailure: took {}.", format_duration(duration)), 1),
-        _ => println!("\nSuccess: took {}.", format_duration(duration))
+    if args.timeout_seconds.is_some() {
+        config.timeout_seconds = args.timeout_seconds;
     }
-}
 
-fn exit_with_error(message: &str, code: i32) -> ! {
-    if !message.is_empty() {
-        eprintln!("{}", message);
+    if let Some(level) = args.local_compression_level {
+        config.local_compression_level = level;
+        config.local_compression_level_auto = false;
+    }
+
+    if let Some(level) = args.remote_compression_level {
+        config.remote_compression_level = level;
+        config.remote_compression_level_auto = false;
     }
-    process::exit(code);
-}
 
-fn sync_before_remote_command(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore) -> Result<(), String> {
-    println!("Sync local → remote machine...");
+    // After command line overrides since they win over config values too.
+    if args.explain {
+        print!("{}", config.format_resolved());
+        process::exit(0);
+    }
 
-    let start = Instant::now();
+    // Command file is read on every run, so only presence of it is checked here, before anything connects to remote machine.
+    if args.run_mode.phases().contains(&Phase::Execute) && args.command_file.is_none() && !remote_command::has_command(&args.commands, None) {
+        let error = RunError::NoCommand;
+        exit_with_error(&error.to_string(), ExitCode::from(&error));
+    }
 
-    let result = sync::sync_local_to_remote(
-        &local_dir_absolute_path,
-        config,
-        ignore,
-    );
+    if config.remote_machine_transport == Transport::Rsyncd && !args.local && args.run_mode.phases().contains(&Phase::Execute) {
+        let error = RunError::RemoteCommandOverRsyncd;
+        exit_with_error(&error.to_string(), ExitCode::from(&error));
+    }
 
-    let duration = start.elapsed();
+    let control_socket = if config.remote_machine_multiplex && !args.local { Some(ssh::control_path()) } else { None };
+    signal::install_interrupt_handler(control_socket.as_deref());
+
+    // Neither ssh nor rsync are used by "--local" runs.
+    if !args.local {
+        let required_binaries: &[&str] = match config.remote_machine_transport {
+            Transport::Ssh => &[&config.sync_rsync_path, &config.remote_machine_ssh_path],
+            Transport::Rsyncd => &[&config.sync_rsync_path],
+        };
 
-    match result {
-        Err(error) => Err(error),
-        Ok(_) => {
-            println!("Sync done: took {}.\n", format_duration(duration));
-            Ok(())
+        if let Err(message) = requirements::check_required_binaries(required_binaries, &requirements::is_binary_in_path) {
+            exit_with_error(&message, ExitCode::LocalSetup);
         }
     }
+
+    // After requirements check since it's measured with rsync.
+    if !args.local {
+        compression::apply_auto_compression(&local_dir_absolute_path, &mut config, &output, &SystemRunner);
+    }
+
+    let mut ignore = match Ignore::from_working_dir(&local_dir_absolute_path) {
+        Err(message) => exit_with_error(&message, ExitCode::LocalSetup),
+        Ok(value) => value
+    };
+
+    ignore.cli_patterns = args.excludes.clone();
+
+    let echoing_runner = EchoingRunner(&SystemRunner);
+    let runner: &dyn CommandRunner = if output.shows(Message::CommandLine) { &echoing_runner } else { &SystemRunner };
+
+    // Checksums don't depend on modification times, so skew doesn't matter then.
+    if config.sync_check_clock_skew && !args.local && !config.dry_run && !config.sync_checksum && config.remote_machine_transport == Transport::Ssh {
+        clock::check_clock_skew(&config, &output, runner);
+    }
+
+    // Before the first upload, so it can't fail midway because of permissions or missing tools.
+    if !args.local && config.remote_machine_transport == Transport::Ssh {
+        let phases = args.run_mode.phases();
+
+        if let Err(error) = preflight::check_remote_machine(&local_dir_absolute_path, &config, phases.contains(&Phase::Upload), phases.contains(&Phase::Execute), runner) {
+            exit_with_error(&error.to_string(), ExitCode::from(&error));
+        }
+    }
+
+    if args.watch {
+        watch_and_run(&local_dir_absolute_path, &args, &mut config, &ignore, &output);
+    }
+
+    let exit_code = match run(&local_dir_absolute_path, &args, &config, &ignore, &output) {
+        Err(error) => exit_with_error(&error.to_string(), ExitCode::from(&error)),
+        Ok(value) => value
+    };
+
+    if !args.local && config.remote_machine_transport == Transport::Ssh {
+        ssh::close_master_connection(&config);
+    }
+
+    process::exit(exit_code);
 }
 
-fn execute_remote_command(local_dir_absolute_path: &Path, args: &Args, config: &Config) -> Result<(), ()> {
-    println!("Executing command on remote machine...\n");
+// Single upload → execute → download cycle, returns exit code of remote command.
+fn run(local_dir_absolute_path: &Path, args: &Args, config: &Config, ignore: &Ignore, output: &Output) -> Result<i32, RunError> {
+    // Read on every run so "--watch" picks up changes of it.
+    let command_file = match args.command_file {
+        None => None,
+        Some(ref path) => match fs::read_to_string(path) {
+            Err(_) => return Err(RunError::CommandFile(path.clone())),
+            Ok(content) => Some(CommandFile { path: path.clone(), content })
+        }
+    };
+
+    let echoing_runner = EchoingRunner(&SystemRunner);
+    let runner: &dyn CommandRunner = if output.shows(Message::CommandLine) { &echoing_runner } else { &SystemRunner };
+
+    if args.local {
+        return Ok(run_locally(local_dir_absolute_path, args, command_file.as_ref(), config, output, runner));
+    }
 
     let start = Instant::now();
 
-    let result = remote_command::execute_remote_command(
-        &args.command.clone(),
-        config,
-        sync::project_dir_on_remote_machine(local_dir_absolute_path).as_ref(),
-    );
+    let result = run_cycle(local_dir_absolute_path, args, command_file.as_ref(), config, ignore, output, runner)?;
 
     let duration = start.elapsed();
 
-    match result {
-        Err(_) => eprintln!("\nExecution failed: took {}.\n", format_duration(duration)),
-        Ok(_) => println!("\nExecution done: took {}.\n", format_duration(duration))
+    match args.report_format {
+        ReportFormat::Json => println!("{}", report::format_json(&RunReport {
+            upload_duration: result.timings.upload,
+            remote_execution_duration: result.timings.execution,
+            download_duration: result.timings.download,
+            bytes_uploaded: result.upload_stats.bytes_sent,
+            bytes_downloaded: result.download_stats.bytes_received,
+            exit_code: result.exit_code,
+        })),
+        ReportFormat::Human => {
+            if output.shows(Message::Summary) {
+                if result.succeeded() {
+                    println!("\nSuccess: took {}.", format_duration(duration));
+                } else {
+                    eprintln!("\nFailure: took {}.", format_duration(duration));
+                }
+            }
+
+            if output.shows(Message::Timings) {
+                let mut total_bytes = TotalBytes::default();
+                total_bytes.add(&result.upload_stats);
+                total_bytes.add(&result.download_stats);
+
+                println!("{}", format_timings(&result.timings, args.run_mode.phases()));
+                println!("{}", sync::format_total_bytes(&total_bytes));
+            }
+        }
     }
 
-    result
+    Ok(result.exit_code)
 }
 
-fn sync_after_remote_command(working_dir_name: &Path, config: &Config, ignore: &Ignore) -> Result<(), String> {
-    println!("Sync remote → local machine...");
+// Failed runs are reported and watching goes on, the next change might fix them.
+fn watch_and_run(local_dir_absolute_path: &Path, args: &Args, config: &mut Config, ignore: &Ignore, output: &Output) -> ! {
+    let patterns = match upload_patterns(config, ignore) {
+        Err(message) => exit_with_error(&message, ExitCode::LocalSetup),
+        Ok(value) => value
+    };
 
-    let start = Instant::now();
+    let run_and_report = |config: &Config| if let Err(error) = run(local_dir_absolute_path, args, config, ignore, output) {
+        eprintln!("{}", error);
+    };
 
-    let result = sync::sync_remote_to_local(
-        working_dir_name,
-        config,
-        ignore,
-    );
+    run_and_report(config);
 
-    let duration = start.elapsed();
+    // Forced only once, runs triggered by changes are regular ones.
+    config.force_full_upload = false;
+    let config: &Config = config;
 
-    match result {
-        Err(error) => Err(error),
-        Ok(_) => {
-            println!("Sync done: took {}.", format_duration(duration));
-            Ok(())
+    watch::watch(local_dir_absolute_path, &patterns, Duration::from_millis(u64::from(config.watch_debounce_millis)), &mut || {
+        if output.shows(Message::Notice) {
+            println!("\n{}\n:: Local changes detected, running again\n", "─".repeat(60));
         }
+        run_and_report(config);
+    })
+}
+
+fn exit_with_error(message: &str, exit_code: ExitCode) -> ! {
+    if !message.is_empty() {
+        eprintln!("{}", message);
     }
+    process::exit(exit_code.code());
 }
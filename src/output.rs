@@ -0,0 +1,229 @@
+use std::env;
+use std::io;
+use std::io::IsTerminal;
+use std::time::Duration;
+use time::format_duration;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// Errors and remote command output are shown regardless, verbosity only applies to Mainframer's own messages.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Message {
+    // Version line printed on start.
+    Banner,
+    // Start and end of upload, execution and download.
+    Phase,
+    // Success or failure of the whole run.
+    Summary,
+    // Timings of phases and transferred bytes.
+    Timings,
+    // Per-phase rsync summary requested with "--stats".
+    TransferStats,
+    // Skipped phases, dry run steps and reruns in "--watch" mode.
+    Notice,
+    // Non-fatal failures, i.e. a cache that could not be written.
+    Warning,
+    // rsync and ssh invocations.
+    CommandLine,
+}
+
+impl Message {
+    // The rest go to stdout, at least partly.
+    fn goes_to_stderr(self) -> bool {
+        matches!(self, Message::Warning | Message::CommandLine)
+    }
+}
+
+impl Verbosity {
+    pub fn shows(self, message: Message) -> bool {
+        match message {
+            Message::Banner | Message::Phase | Message::Summary | Message::Timings | Message::TransferStats | Message::Notice | Message::Warning => self >= Verbosity::Normal,
+            Message::CommandLine => self >= Verbosity::Verbose,
+        }
+    }
+}
+
+// Mainframer's own messages about run phases, interleaved with rsync and remote command output.
+pub struct Output {
+    pub verbosity: Verbosity,
+    pub color: bool,
+    // Machine-readable report, progress or listing owns stdout, only messages that go to stderr are printed then.
+    pub stdout_taken: bool,
+}
+
+impl Output {
+    pub fn new(verbosity: Verbosity, no_color: bool) -> Output {
+        Output {
+            verbosity,
+            color: use_color(no_color, env::var("NO_COLOR").ok(), io::stdout().is_terminal()),
+            stdout_taken: false,
+        }
+    }
+
+    pub fn shows(&self, message: Message) -> bool {
+        self.verbosity.shows(message) && (!self.stdout_taken || message.goes_to_stderr())
+    }
+
+    pub fn print_phase_start(&self, name: &str) {
+        if self.shows(Message::Phase) {
+            println!("{}", format_phase_start(name, self.color));
+        }
+    }
+
+    pub fn print_phase_done(&self, name: &str, duration: Duration) {
+        if self.shows(Message::Phase) {
+            println!("{}", format_phase_done(name, duration, self.color));
+        }
+    }
+
+    pub fn print_phase_failed(&self, name: &str, duration: Duration) {
+        if self.shows(Message::Phase) {
+            eprintln!("{}", format_phase_failed(name, duration, self.color));
+        }
+    }
+
+    pub fn print_line_break(&self) {
+        if self.shows(Message::Phase) {
+            println!();
+        }
+    }
+}
+
+// See https://no-color.org, any non-empty value disables colors.
+fn use_color(no_color_flag: bool, no_color_variable: Option<String>, is_terminal: bool) -> bool {
+    !no_color_flag && no_color_variable.is_none_or(|value| value.is_empty()) && is_terminal
+}
+
+fn format_phase_start(name: &str, color: bool) -> String {
+    if color {
+        format!("{}{}...{}", BOLD, name, RESET)
+    } else {
+        format!("{}...", name)
+    }
+}
+
+fn format_phase_done(name: &str, duration: Duration, color: bool) -> String {
+    if color {
+        format!("{}{} done:{} {}took {}.{}", BOLD, name, RESET, DIM, format_duration(duration), RESET)
+    } else {
+        format!("{} done: took {}.", name, format_duration(duration))
+    }
+}
+
+fn format_phase_failed(name: &str, duration: Duration, color: bool) -> String {
+    if color {
+        format!("{}{}{} failed:{} {}took {}.{}", BOLD, RED, name, RESET, DIM, format_duration(duration), RESET)
+    } else {
+        format!("{} failed: took {}.", name, format_duration(duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_phase_start_plain() {
+        assert_eq!(format_phase_start("Sync local → remote machine", false), "Sync local → remote machine...");
+    }
+
+    #[test]
+    fn format_phase_done_plain() {
+        assert_eq!(format_phase_done("Sync", Duration::from_secs(61), false), "Sync done: took 1 minute 1 second.");
+    }
+
+    #[test]
+    fn format_phase_failed_plain() {
+        assert_eq!(format_phase_failed("Execution", Duration::from_secs(2), false), "Execution failed: took 2 seconds.");
+    }
+
+    #[test]
+    fn format_phase_done_color() {
+        assert_eq!(format_phase_done("Sync", Duration::from_secs(1), true), "\x1b[1mSync done:\x1b[0m \x1b[2mtook 1 second.\x1b[0m");
+    }
+
+    #[test]
+    fn verbosity_quiet_shows_no_messages() {
+        for &message in &[Message::Banner, Message::Phase, Message::Summary, Message::Timings, Message::TransferStats, Message::Notice, Message::Warning, Message::CommandLine] {
+            assert!(!Verbosity::Quiet.shows(message));
+        }
+    }
+
+    #[test]
+    fn verbosity_normal_shows_all_but_command_lines() {
+        for &message in &[Message::Banner, Message::Phase, Message::Summary, Message::Timings, Message::TransferStats, Message::Notice, Message::Warning] {
+            assert!(Verbosity::Normal.shows(message));
+        }
+        assert!(!Verbosity::Normal.shows(Message::CommandLine));
+    }
+
+    #[test]
+    fn verbosity_verbose_shows_all_messages() {
+        for &message in &[Message::Banner, Message::Phase, Message::Summary, Message::Timings, Message::TransferStats, Message::Notice, Message::Warning, Message::CommandLine] {
+            assert!(Verbosity::Verbose.shows(message));
+        }
+    }
+
+    #[test]
+    fn verbosity_transfer_stats() {
+        assert!(!Verbosity::Quiet.shows(Message::TransferStats));
+        assert!(Verbosity::Normal.shows(Message::TransferStats));
+        assert!(Verbosity::Verbose.shows(Message::TransferStats));
+    }
+
+    #[test]
+    fn output_stdout_taken_shows_only_stderr_messages() {
+        let output = Output { stdout_taken: true, ..Output::new(Verbosity::Verbose, true) };
+        for &message in &[Message::Banner, Message::Phase, Message::Summary, Message::Timings, Message::TransferStats, Message::Notice] {
+            assert!(!output.shows(message));
+        }
+        assert!(output.shows(Message::Warning));
+        assert!(output.shows(Message::CommandLine));
+    }
+
+    #[test]
+    fn output_stdout_taken_keeps_verbosity() {
+        let output = Output { stdout_taken: true, ..Output::new(Verbosity::Normal, true) };
+        assert!(output.shows(Message::Warning));
+        assert!(!output.shows(Message::CommandLine));
+
+        let output = Output { stdout_taken: true, ..Output::new(Verbosity::Quiet, true) };
+        assert!(!output.shows(Message::Warning));
+    }
+
+    #[test]
+    fn use_color_terminal() {
+        assert!(use_color(false, None, true));
+    }
+
+    #[test]
+    fn use_color_not_terminal() {
+        assert!(!use_color(false, None, false));
+    }
+
+    #[test]
+    fn use_color_no_color_flag() {
+        assert!(!use_color(true, None, true));
+    }
+
+    #[test]
+    fn use_color_no_color_variable() {
+        assert!(!use_color(false, Some(String::from("1")), true));
+    }
+
+    #[test]
+    fn use_color_empty_no_color_variable() {
+        assert!(use_color(false, Some(String::new()), true));
+    }
+}
@@ -0,0 +1,308 @@
+use config::Config;
+use runner::CommandRunner;
+use ssh;
+use std::fmt;
+use std::path::Path;
+use sync;
+
+// What remote machine reported about its project dir.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PreflightResult {
+    Writable,
+    // Project dir or its closest existing parent, since rsync creates the missing ones.
+    Unwritable { path: String, user: String },
+    // Closest existing parent is not a dir, i.e. a file or a symlink loop, so project dir can't be created.
+    MissingParent { path: String },
+    // Output of a shell that doesn't behave like POSIX one, nothing is claimed then.
+    Unknown,
+}
+
+// Project dir one is a remote machine setup problem, missing tools are the ones config asks for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PreflightError {
+    ProjectDir(String),
+    RemoteTools(Vec<String>),
+}
+
+impl fmt::Display for PreflightError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PreflightError::ProjectDir(ref message) => write!(formatter, "{}", message),
+            PreflightError::RemoteTools(ref missing_tools) => write!(formatter, "Required tools are missing on remote machine: {}.", missing_tools.join(", ")),
+        }
+    }
+}
+
+// Both checks go over a single ssh round trip, so they fail the run before anything is transferred.
+// Project dir one fails only on permission misconfigurations, rsync would otherwise fail with a cryptic error midway through the transfer.
+// Remote tools one covers all "execution_require_remote_tools", instead of a "command not found" midway through the build.
+// Unreachable machine is reported by the sync that follows.
+pub fn check_remote_machine(local_dir_absolute_path: &Path, config: &Config, check_project_dir: bool, check_remote_tools: bool, runner: &dyn CommandRunner) -> Result<(), PreflightError> {
+    let check_remote_tools = check_remote_tools && !config.execution_require_remote_tools.is_empty();
+    let mut scripts = vec![];
+
+    if check_project_dir {
+        scripts.push(preflight_script(&sync::project_dir_on_remote_machine(local_dir_absolute_path, config)));
+    }
+
+    if check_remote_tools {
+        scripts.push(remote_tools_script(&config.execution_require_remote_tools, config.execution_source_profile.as_deref()));
+    }
+
+    if scripts.is_empty() {
+        return Ok(());
+    }
+
+    let ssh_stdout = match run_remote_script(config, scripts.join("; "), runner) {
+        None => return Ok(()),
+        Some(value) => value
+    };
+
+    if let Some(message) = preflight_error(&parse_preflight_output(&ssh_stdout)).filter(|_| check_project_dir) {
+        return Err(PreflightError::ProjectDir(message));
+    }
+
+    match parse_remote_tools_output(&ssh_stdout) {
+        Some(missing_tools) if check_remote_tools && !missing_tools.is_empty() => Err(PreflightError::RemoteTools(missing_tools)),
+        _ => Ok(())
+    }
+}
+
+// Returns none if remote machine couldn't run it, the sync that follows reports why.
+fn run_remote_script(config: &Config, script: String, runner: &dyn CommandRunner) -> Option<String> {
+    let mut args = ssh::ssh_options(config);
+    // Check is not worth a password prompt, it's skipped instead.
+    args.push(String::from("-o"));
+    args.push(String::from("BatchMode=yes"));
+    args.push(config.remote_machine_target());
+    args.push(script);
+
+    match runner.output(&config.remote_machine_ssh_path, &args, None) {
+        Ok(ref output) if output.status.success() => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => None
+    }
+}
+
+// Walks up to the closest existing path, "-L" stops it at symlinks that don't resolve.
+// Lines start with "project_dir" so they are not confused with the ones of remote tools script.
+fn preflight_script(project_dir: &str) -> String {
+    format!(
+        "dir={}; user=$(id -un); \
+        while [ ! -e \"$dir\" ] && [ ! -L \"$dir\" ]; do dir=$(dirname \"$dir\"); done; \
+        if [ ! -d \"$dir\" ]; then echo \"project_dir missing $user $dir\"; \
+        elif [ ! -w \"$dir\" ] || [ ! -x \"$dir\" ]; then echo \"project_dir unwritable $user $dir\"; \
+        else echo \"project_dir writable $user\"; fi",
+        ssh::shell_quote_path(project_dir)
+    )
+}
+
+// Tools are looked up the same way the command finds them, so the profile it sources is sourced here too.
+fn remote_tools_script(tools: &[String], source_profile: Option<&str>) -> String {
+    let mut script = String::new();
+
+    if let Some(source_profile) = source_profile {
+        script.push_str(&format!(". {} >/dev/null 2>&1; ", ssh::shell_quote_path(source_profile)));
+    }
+
+    let tools: Vec<String> = tools.iter().map(|tool| ssh::shell_quote(tool)).collect();
+    script.push_str(&format!("for tool in {}; do command -v \"$tool\" >/dev/null 2>&1 || echo \"missing $tool\"; done; echo checked", tools.join(" ")));
+    script
+}
+
+// Returns none if the script didn't get to the end, i.e. remote shell is not a POSIX one.
+pub fn parse_remote_tools_output(ssh_stdout: &str) -> Option<Vec<String>> {
+    let mut missing_tools = vec![];
+    let mut checked = false;
+
+    for line in ssh_stdout.lines() {
+        if let Some(tool) = line.strip_prefix("missing ") {
+            missing_tools.push(String::from(tool));
+        } else if line == "checked" {
+            checked = true;
+        }
+    }
+
+    if checked {
+        Some(missing_tools)
+    } else {
+        None
+    }
+}
+
+// Path goes last since it can contain spaces.
+pub fn parse_preflight_output(ssh_stdout: &str) -> PreflightResult {
+    let line = ssh_stdout.lines().filter_map(|line| line.strip_prefix("project_dir ")).next_back().unwrap_or("");
+    let mut parts = line.splitn(3, ' ');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("writable"), Some(_), None) => PreflightResult::Writable,
+        (Some("unwritable"), Some(user), Some(path)) => PreflightResult::Unwritable { path: String::from(path), user: String::from(user) },
+        (Some("missing"), Some(_), Some(path)) => PreflightResult::MissingParent { path: String::from(path) },
+        _ => PreflightResult::Unknown
+    }
+}
+
+pub fn preflight_error(result: &PreflightResult) -> Option<String> {
+    match result {
+        PreflightResult::Writable | PreflightResult::Unknown => None,
+        PreflightResult::Unwritable { path, user } => Some(format!("Remote path {} is not writable by {}.", path, user)),
+        PreflightResult::MissingParent { path } => Some(format!("Remote path {} is not a directory or is a symlink loop, project dir can't be created in it.", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use runner::RecordingRunner;
+
+    #[test]
+    fn parse_preflight_output_writable() {
+        assert_eq!(parse_preflight_output("project_dir writable builder\n"), PreflightResult::Writable);
+    }
+
+    #[test]
+    fn parse_preflight_output_unwritable() {
+        assert_eq!(parse_preflight_output("project_dir unwritable builder /opt/build cache\n"), PreflightResult::Unwritable {
+            path: String::from("/opt/build cache"),
+            user: String::from("builder"),
+        });
+    }
+
+    #[test]
+    fn parse_preflight_output_missing_parent() {
+        assert_eq!(parse_preflight_output("project_dir missing builder /home/builder/mainframer\n"), PreflightResult::MissingParent {
+            path: String::from("/home/builder/mainframer"),
+        });
+    }
+
+    #[test]
+    fn parse_preflight_output_skips_login_banner() {
+        assert_eq!(parse_preflight_output("Welcome to build machine!\nproject_dir writable builder\nchecked\n"), PreflightResult::Writable);
+    }
+
+    #[test]
+    fn parse_preflight_output_unknown() {
+        assert_eq!(parse_preflight_output(""), PreflightResult::Unknown);
+        assert_eq!(parse_preflight_output("fish: unknown command\n"), PreflightResult::Unknown);
+        assert_eq!(parse_preflight_output("writable builder\n"), PreflightResult::Unknown);
+    }
+
+    #[test]
+    fn preflight_error_unwritable() {
+        let result = PreflightResult::Unwritable { path: String::from("/opt/build"), user: String::from("builder") };
+        assert_eq!(preflight_error(&result), Some(String::from("Remote path /opt/build is not writable by builder.")));
+    }
+
+    #[test]
+    fn preflight_error_writable() {
+        assert_eq!(preflight_error(&PreflightResult::Writable), None);
+    }
+
+    #[test]
+    fn check_remote_machine_project_dir_runs_script_on_remote_machine() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "project_dir unwritable builder /home/builder/mainframer\n");
+
+        assert_eq!(
+            check_remote_machine(Path::new("/project"), &config, true, false, &runner),
+            Err(PreflightError::ProjectDir(String::from("Remote path /home/builder/mainframer is not writable by builder.")))
+        );
+
+        let (program, args) = runner.invocations().remove(0);
+        assert_eq!(program, "ssh");
+        assert_eq!(args[args.len() - 2], "machine");
+        assert!(args[args.len() - 1].starts_with("dir=~/mainframer/project; "));
+    }
+
+    #[test]
+    fn check_remote_machine_unreachable_machine() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(check_remote_machine(Path::new("/project"), &config, true, false, &RecordingRunner::new(255, "")), Ok(()));
+    }
+
+    #[test]
+    fn check_remote_machine_both_checks_in_one_round_trip() {
+        let config = config_from_str("remote_machine=machine\nexecution_require_remote_tools=gradle", None).unwrap();
+        let runner = RecordingRunner::new(0, "project_dir writable builder\nmissing gradle\nchecked\n");
+
+        assert_eq!(check_remote_machine(Path::new("/project"), &config, true, true, &runner), Err(PreflightError::RemoteTools(vec![String::from("gradle")])));
+
+        let invocations = runner.invocations();
+        assert_eq!(invocations.len(), 1);
+        let script = invocations[0].1.last().unwrap();
+        assert!(script.starts_with("dir=~/mainframer/project; "));
+        assert!(script.ends_with("; for tool in gradle; do command -v \"$tool\" >/dev/null 2>&1 || echo \"missing $tool\"; done; echo checked"));
+    }
+
+    #[test]
+    fn check_remote_machine_nothing_to_check() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        assert_eq!(check_remote_machine(Path::new("/project"), &config, false, true, &runner), Ok(()));
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn parse_remote_tools_output_all_present() {
+        assert_eq!(parse_remote_tools_output("checked\n"), Some(vec![]));
+    }
+
+    #[test]
+    fn parse_remote_tools_output_missing() {
+        assert_eq!(
+            parse_remote_tools_output("Welcome to build machine!\nmissing gradle\nmissing cargo\nchecked\n"),
+            Some(vec![String::from("gradle"), String::from("cargo")])
+        );
+    }
+
+    #[test]
+    fn parse_remote_tools_output_not_checked() {
+        assert_eq!(parse_remote_tools_output("fish: Unsupported use of '||'\n"), None);
+    }
+
+    #[test]
+    fn check_remote_machine_remote_tools_missing() {
+        let config = config_from_str("remote_machine=machine\nexecution_require_remote_tools=gradle cargo", None).unwrap();
+        let runner = RecordingRunner::new(0, "missing cargo\nchecked\n");
+
+        let result = check_remote_machine(Path::new("/project"), &config, false, true, &runner);
+        assert_eq!(result, Err(PreflightError::RemoteTools(vec![String::from("cargo")])));
+        assert_eq!(result.unwrap_err().to_string(), "Required tools are missing on remote machine: cargo.");
+        assert_eq!(
+            runner.invocations()[0].1.last(),
+            Some(&String::from("for tool in gradle cargo; do command -v \"$tool\" >/dev/null 2>&1 || echo \"missing $tool\"; done; echo checked"))
+        );
+    }
+
+    #[test]
+    fn remote_tools_script_sources_profile() {
+        assert!(remote_tools_script(&[String::from("node")], Some("~/.nvm/nvm.sh")).starts_with(". ~/.nvm/nvm.sh >/dev/null 2>&1; for tool in node; "));
+    }
+
+    #[test]
+    fn remote_tools_script_in_shell() {
+        let script = remote_tools_script(&[String::from("sh"), String::from("mainframer-unknown-binary")], None);
+        let output = ::std::process::Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert_eq!(parse_remote_tools_output(&String::from_utf8_lossy(&output.stdout)), Some(vec![String::from("mainframer-unknown-binary")]));
+    }
+
+    #[test]
+    fn preflight_script_in_shell() {
+        let dir = ::std::env::temp_dir().join(format!("mainframer_preflight_{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+        ::std::fs::write(dir.join("file"), "").unwrap();
+
+        let run = |project_dir: &Path| {
+            let output = ::std::process::Command::new("sh").arg("-c").arg(preflight_script(&project_dir.to_string_lossy())).output().unwrap();
+            parse_preflight_output(&String::from_utf8_lossy(&output.stdout))
+        };
+
+        let missing_dirs = run(&dir.join("a/b/project"));
+        let file_in_the_way = run(&dir.join("file/project"));
+        ::std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(missing_dirs, PreflightResult::Writable);
+        assert_eq!(file_in_the_way, PreflightResult::MissingParent { path: dir.join("file").to_string_lossy().into_owned() });
+    }
+}
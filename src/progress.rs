@@ -0,0 +1,66 @@
+// Newline-delimited JSON events for IDEs and other tools rendering progress of a run.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProgressEvent {
+    UploadStart,
+    UploadProgress(u8),
+    UploadDone,
+    ExecuteStart,
+    ExecuteDone,
+    DownloadStart,
+    DownloadProgress(u8),
+    DownloadDone,
+}
+
+pub fn format_event(event: ProgressEvent) -> String {
+    match event {
+        ProgressEvent::UploadStart => String::from("{\"event\":\"upload_start\"}"),
+        ProgressEvent::UploadProgress(percent) => format!("{{\"event\":\"upload_progress\",\"percent\":{}}}", percent),
+        ProgressEvent::UploadDone => String::from("{\"event\":\"upload_done\"}"),
+        ProgressEvent::ExecuteStart => String::from("{\"event\":\"execute_start\"}"),
+        ProgressEvent::ExecuteDone => String::from("{\"event\":\"execute_done\"}"),
+        ProgressEvent::DownloadStart => String::from("{\"event\":\"download_start\"}"),
+        ProgressEvent::DownloadProgress(percent) => format!("{{\"event\":\"download_progress\",\"percent\":{}}}", percent),
+        ProgressEvent::DownloadDone => String::from("{\"event\":\"download_done\"}"),
+    }
+}
+
+// rsync "--info=progress2" line looks like "    1,234,567  42%   10.00MB/s    0:00:01 (xfr#3, to-chk=0/5)".
+pub fn parse_progress_percent(line: &str) -> Option<u8> {
+    line.split_whitespace()
+        .find_map(|part| part.strip_suffix('%'))
+        .and_then(|percent| percent.parse().ok())
+        .filter(|percent| *percent <= 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_event_without_percent() {
+        assert_eq!(format_event(ProgressEvent::UploadStart), "{\"event\":\"upload_start\"}");
+        assert_eq!(format_event(ProgressEvent::ExecuteDone), "{\"event\":\"execute_done\"}");
+    }
+
+    #[test]
+    fn format_event_with_percent() {
+        assert_eq!(format_event(ProgressEvent::UploadProgress(42)), "{\"event\":\"upload_progress\",\"percent\":42}");
+        assert_eq!(format_event(ProgressEvent::DownloadProgress(100)), "{\"event\":\"download_progress\",\"percent\":100}");
+    }
+
+    #[test]
+    fn parse_progress_percent_progress2_line() {
+        assert_eq!(parse_progress_percent("      1,234,567  42%   10.00MB/s    0:00:01 (xfr#3, to-chk=0/5)"), Some(42));
+    }
+
+    #[test]
+    fn parse_progress_percent_done() {
+        assert_eq!(parse_progress_percent("     12,345,678 100%   11.77MB/s    0:00:01 (xfr#5, to-chk=0/5)"), Some(100));
+    }
+
+    #[test]
+    fn parse_progress_percent_not_progress() {
+        assert_eq!(parse_progress_percent("Number of files: 5 (reg: 3, dir: 2)"), None);
+        assert_eq!(parse_progress_percent(""), None);
+    }
+}
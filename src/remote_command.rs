@@ -1,31 +1,597 @@
 use config::Config;
-use std::process::Command;
-use std::process::Stdio;
-
-pub fn execute_remote_command(remote_command: &str, config: &Config, project_dir_on_remote_machine: &str) -> Result<(), ()> {
-    let mut command = Command::new("ssh");
-
-    command
-        .arg(config.remote_machine_name.clone())
-        .arg(format!(
-            "echo 'set -e && cd {project_dir_on_remote_machine} && echo \"{remote_command}\" && echo \"\" && {remote_command}' | bash",
-            project_dir_on_remote_machine = project_dir_on_remote_machine,
-            remote_command = remote_command)
-        );
-
-    let mut process = command
-        // Interactively pipe ssh output to Mainframer output.
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .unwrap();
-
-    match process.wait() {
-        Err(_) => Err(()), // No need to get error description as we've already piped command output to Mainframer output.
-        Ok(exit_status) => if exit_status.success() {
-            Ok(())
-        } else {
-            Err(())
+use output::Message;
+use output::Output;
+use runner::CommandRunner;
+use ssh;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemoteCommandError {
+    // Exit code of remote command, none if it was terminated by a signal.
+    Failed(Option<i32>),
+    TimedOut(u32),
+}
+
+// Local script run after the commands, it doesn't have to exist on remote machine.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandFile {
+    pub path: String,
+    pub content: String,
+}
+
+impl RemoteCommandError {
+    // Mainframer exits with remote command exit code so it can be chained in shell.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            RemoteCommandError::Failed(Some(exit_code)) => exit_code,
+            RemoteCommandError::Failed(None) => 1,
+            // Same exit code as coreutils "timeout" uses.
+            RemoteCommandError::TimedOut(_) => 124,
+        }
+    }
+}
+
+// How the script is started on remote machine, the default one is plain "bash" without a profile.
+#[derive(Debug, Default)]
+struct ScriptShell<'a> {
+    source_profile: Option<&'a str>,
+    shell: Option<&'a str>,
+    nice: Option<i64>,
+    ionice: bool,
+}
+
+impl<'a> ScriptShell<'a> {
+    fn remote(config: &'a Config) -> ScriptShell<'a> {
+        ScriptShell {
+            source_profile: config.execution_source_profile.as_deref(),
+            shell: config.remote_machine_shell.as_deref(),
+            nice: config.execution_nice,
+            ionice: config.execution_ionice,
+        }
+    }
+
+    // Wraps the shell instead of every command, so builtins and compound commands keep working and children inherit the priority.
+    fn program(&self, shell: &str) -> String {
+        let mut program = String::new();
+
+        if let Some(nice) = self.nice {
+            program.push_str(&format!("nice -n {} ", nice));
+        }
+
+        if self.ionice {
+            program.push_str("ionice -c3 ");
+        }
+
+        program.push_str(shell);
+        program
+    }
+}
+
+// Blank command would leave a dangling "&&" in the script, so it's rejected before anything is synced.
+pub fn has_command(commands: &[String], command_file: Option<&CommandFile>) -> bool {
+    command_file.is_some() || commands.iter().any(|command| !command.trim().is_empty())
+}
+
+pub fn execute_remote_command(remote_commands: &[String], command_file: Option<&CommandFile>, keep_going: bool, config: &Config, project_dir_on_remote_machine: &str, output: &Output, runner: &dyn CommandRunner) -> Result<(), RemoteCommandError> {
+    if config.dry_run {
+        if output.shows(Message::Notice) {
+            let steps: Vec<&str> = remote_commands.iter().map(String::as_str).chain(command_file.map(|file| file.path.as_str())).collect();
+            println!("Dry run, would execute '{}' in '{}'.", steps.join("' and '"), project_dir_on_remote_machine);
+        }
+
+        return Ok(());
+    }
+
+    let mut args = execution_ssh_options(config);
+    args.push(config.remote_machine_target());
+    args.push(remote_shell_command(remote_commands, command_file, keep_going, &config.remote_env, project_dir_on_remote_machine, &ScriptShell::remote(config)));
+
+    // Interactively pipe ssh output to Mainframer output.
+    run_command(runner, &config.remote_machine_ssh_path, &args, config.timeout_seconds)
+}
+
+// Same script as on remote machine so "--local" behaves the same, just without ssh and syncing.
+pub fn execute_local_command(commands: &[String], command_file: Option<&CommandFile>, keep_going: bool, config: &Config, execution_dir: &Path, output: &Output, runner: &dyn CommandRunner) -> Result<(), RemoteCommandError> {
+    if config.dry_run {
+        if output.shows(Message::Notice) {
+            let steps: Vec<&str> = commands.iter().map(String::as_str).chain(command_file.map(|file| file.path.as_str())).collect();
+            println!("Dry run, would execute '{}' locally in '{}'.", steps.join("' and '"), execution_dir.to_string_lossy());
         }
+
+        return Ok(());
+    }
+
+    run_command(runner, "bash", &local_command_args(commands, command_file, keep_going, config, execution_dir), config.timeout_seconds)
+}
+
+// "remote_machine_shell" and the rest of "ScriptShell" are about remote machine, local script is given to "bash" right away.
+fn local_command_args(commands: &[String], command_file: Option<&CommandFile>, keep_going: bool, config: &Config, execution_dir: &Path) -> Vec<String> {
+    vec![
+        String::from("-c"),
+        command_script(commands, command_file, keep_going, &config.remote_env, &execution_dir.to_string_lossy(), None),
+    ]
+}
+
+// Options that only make sense for command execution, rsync transport must not get a terminal.
+fn execution_ssh_options(config: &Config) -> Vec<String> {
+    let mut options = ssh::ssh_options(config);
+
+    if config.execution_allocate_tty {
+        options.push(String::from("-t"));
+    }
+
+    if config.remote_machine_forward_agent {
+        options.push(String::from("-A"));
+    }
+
+    options
+}
+
+// Script is passed to "shell" if set, hosts with non-POSIX login shell can't pipe it to "bash" otherwise.
+fn remote_shell_command(remote_commands: &[String], command_file: Option<&CommandFile>, keep_going: bool, remote_env: &BTreeMap<String, String>, project_dir_on_remote_machine: &str, script_shell: &ScriptShell) -> String {
+    let script = command_script(remote_commands, command_file, keep_going, remote_env, project_dir_on_remote_machine, script_shell.source_profile);
+
+    match script_shell.shell {
+        // "printf" instead of "echo", the latter interprets backslashes in dash and zsh.
+        None => format!("printf '%s\\n' {} | {}", ssh::shell_quote(&script), script_shell.program("bash")),
+        Some(shell) => format!("{} -c {}", script_shell.program(shell), ssh::shell_quote(&script))
+    }
+}
+
+// Commands are echoed before being run, the first failure stops the chain unless "keep_going",
+// in which case the exit code of the last failed command is reported.
+// Command file goes inline as quoted heredoc, so its content is not expanded and stdin is left alone.
+fn command_script(remote_commands: &[String], command_file: Option<&CommandFile>, keep_going: bool, remote_env: &BTreeMap<String, String>, project_dir_on_remote_machine: &str, source_profile: Option<&str>) -> String {
+    let mut script = String::from("set -e");
+
+    // Non-interactive ssh doesn't read login files, tools installed by "nvm" or "sdkman" are missing without it.
+    // "." instead of "source" works in any POSIX shell.
+    if let Some(source_profile) = source_profile {
+        script.push_str(&format!(" && . {}", ssh::shell_quote_path(source_profile)));
+    }
+
+    script.push_str(&format!(" && cd {}", ssh::shell_quote_path(project_dir_on_remote_machine)));
+
+    for (name, value) in remote_env {
+        script.push_str(&format!(" && export {}=\"{}\"", name, quote_env_value(value)));
+    }
+
+    if keep_going {
+        script.push_str(" && exit_code=0");
+    }
+
+    for remote_command in remote_commands {
+        push_step(&mut script, remote_command, remote_command, keep_going);
+    }
+
+    let heredoc_delimiter = command_file.map(|command_file| heredoc_delimiter(&command_file.content));
+
+    if let (Some(command_file), Some(ref delimiter)) = (command_file, &heredoc_delimiter) {
+        push_step(&mut script, &command_file.path, &format!("bash -s <<'{}'", delimiter), keep_going);
+    }
+
+    if keep_going {
+        script.push_str(" && exit $exit_code");
+    }
+
+    // Heredoc body starts on the line after the one it's referenced from.
+    if let (Some(command_file), Some(ref delimiter)) = (command_file, &heredoc_delimiter) {
+        script.push_str(&format!("\n{}\n{}", command_file.content.trim_end_matches('\n'), delimiter));
+    }
+
+    script
+}
+
+// Title is single-quoted so announcing the command doesn't already expand "$(...)", backticks or variables of it.
+fn push_step(script: &mut String, title: &str, remote_command: &str, keep_going: bool) {
+    let title = ssh::shell_quote(title);
+
+    if keep_going {
+        script.push_str(&format!(" && echo {title} && echo \"\" && {{ {remote_command} || exit_code=$?; }}", title = title, remote_command = remote_command));
+    } else {
+        script.push_str(&format!(" && echo {title} && echo \"\" && {remote_command}", title = title, remote_command = remote_command));
+    }
+}
+
+// Heredoc would end early on a line equal to the delimiter, so one that is not present in the content is picked.
+fn heredoc_delimiter(content: &str) -> String {
+    let mut delimiter = String::from("MAINFRAMER_COMMAND_FILE");
+
+    while content.lines().any(|line| line == delimiter) {
+        delimiter.push('_');
+    }
+
+    delimiter
+}
+
+// Value is put in double quotes of the script, "$" references are still expanded.
+fn quote_env_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+fn command_result(exit_status: ExitStatus) -> Result<(), RemoteCommandError> {
+    if exit_status.success() {
+        Ok(())
+    } else {
+        Err(RemoteCommandError::Failed(exit_status.code()))
+    }
+}
+
+// Killing local ssh process closes the connection which terminates the command on remote machine too.
+fn run_command(runner: &dyn CommandRunner, program: &str, args: &[String], timeout_seconds: Option<u32>) -> Result<(), RemoteCommandError> {
+    match (runner.run_with_timeout(program, args, timeout_seconds.map(|value| Duration::from_secs(u64::from(value)))), timeout_seconds) {
+        (Err(ref error), Some(timeout_seconds)) if error.kind() == io::ErrorKind::TimedOut => Err(RemoteCommandError::TimedOut(timeout_seconds)),
+        // No need to get error description as we've already piped command output to Mainframer output.
+        (Err(_), _) => Err(RemoteCommandError::Failed(None)),
+        (Ok(exit_status), _) => command_result(exit_status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use output::Verbosity;
+    use runner::RecordingRunner;
+    use runner::SystemRunner;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+    use std::process::Command;
+    use std::time::Instant;
+    use sync;
+
+    #[test]
+    fn has_command_empty() {
+        assert!(!has_command(&[], None));
+        assert!(!has_command(&[String::from("")], None));
+    }
+
+    #[test]
+    fn has_command_whitespace() {
+        assert!(!has_command(&[String::from("  \t\n")], None));
+    }
+
+    #[test]
+    fn has_command_valid() {
+        assert!(has_command(&[String::from("./gradlew build")], None));
+        assert!(has_command(&[], Some(&command_file("./gradlew build"))));
+    }
+
+    #[test]
+    fn execution_ssh_options_default() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222", None).unwrap();
+        assert_eq!(execution_ssh_options(&config), vec![String::from("-p"), String::from("2222"), String::from("-o"), String::from("StrictHostKeyChecking=accept-new")]);
+    }
+
+    #[test]
+    fn execution_ssh_options_allocate_tty() {
+        let config = config_from_str("remote_machine=machine\nexecution_allocate_tty=true", None).unwrap();
+        assert_eq!(execution_ssh_options(&config), vec![String::from("-o"), String::from("StrictHostKeyChecking=accept-new"), String::from("-t")]);
+        assert_eq!(ssh::rsync_shell(&config), "ssh -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn execution_ssh_options_forward_agent() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_forward_agent=true", None).unwrap();
+        assert_eq!(execution_ssh_options(&config), vec![String::from("-o"), String::from("StrictHostKeyChecking=accept-new"), String::from("-A")]);
+        assert_eq!(ssh::rsync_shell(&config), "ssh -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn remote_shell_command_source_profile() {
+        assert_eq!(
+            remote_shell_command(&[String::from("./gradlew build")], None, false, &BTreeMap::new(), "~/mainframer/project", &ScriptShell { source_profile: Some("~/.sdkman/bin/sdkman-init.sh"), ..ScriptShell::default() }),
+            "printf '%s\\n' 'set -e && . ~/.sdkman/bin/sdkman-init.sh && cd ~/mainframer/project && echo '\\''./gradlew build'\\'' && echo \"\" && ./gradlew build' | bash"
+        );
+    }
+
+    #[test]
+    fn execute_remote_command_sources_profile() {
+        let config = config_from_str("remote_machine=machine\nexecution_source_profile=~/.nvm/nvm.sh", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        execute_remote_command(&[String::from("npm test")], None, false, &config, "~/mainframer/project", &Output::new(Verbosity::Quiet, true), &runner).unwrap();
+        assert!(runner.invocations()[0].1.last().unwrap().starts_with("printf '%s\\n' 'set -e && . ~/.nvm/nvm.sh && cd ~/mainframer/project && "));
+    }
+
+    #[test]
+    fn remote_shell_command_single_command() {
+        assert_eq!(
+            remote_shell_command(&[String::from("./gradlew build")], None, false, &BTreeMap::new(), "~/mainframer/project", &ScriptShell::default()),
+            "printf '%s\\n' 'set -e && cd ~/mainframer/project && echo '\\''./gradlew build'\\'' && echo \"\" && ./gradlew build' | bash"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_several_commands() {
+        assert_eq!(
+            remote_shell_command(&[String::from("./gradlew assemble"), String::from("./gradlew test")], None, false, &BTreeMap::new(), "~/mainframer/project", &ScriptShell::default()),
+            "printf '%s\\n' 'set -e && cd ~/mainframer/project && echo '\\''./gradlew assemble'\\'' && echo \"\" && ./gradlew assemble && echo '\\''./gradlew test'\\'' && echo \"\" && ./gradlew test' | bash"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_keep_going() {
+        assert_eq!(
+            remote_shell_command(&[String::from("./gradlew assemble"), String::from("./gradlew test")], None, true, &BTreeMap::new(), "~/mainframer/project", &ScriptShell::default()),
+            "printf '%s\\n' 'set -e && cd ~/mainframer/project && exit_code=0 && echo '\\''./gradlew assemble'\\'' && echo \"\" && { ./gradlew assemble || exit_code=$?; } && echo '\\''./gradlew test'\\'' && echo \"\" && { ./gradlew test || exit_code=$?; } && exit $exit_code' | bash"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_keep_going_reports_failure() {
+        let script = remote_shell_command(&[String::from("(exit 3)"), String::from("echo second")], None, true, &BTreeMap::new(), "/", &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line == "second"));
+    }
+
+    #[test]
+    fn remote_shell_command_remote_env() {
+        let mut remote_env = BTreeMap::new();
+        remote_env.insert(String::from("CI"), String::from("true"));
+        remote_env.insert(String::from("JAVA_HOME"), String::from("$HOME/jdk"));
+        assert_eq!(
+            remote_shell_command(&[String::from("./gradlew build")], None, false, &remote_env, "~/mainframer/project", &ScriptShell::default()),
+            "printf '%s\\n' 'set -e && cd ~/mainframer/project && export CI=\"true\" && export JAVA_HOME=\"$HOME/jdk\" && echo '\\''./gradlew build'\\'' && echo \"\" && ./gradlew build' | bash"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_remote_env_quotes() {
+        let mut remote_env = BTreeMap::new();
+        remote_env.insert(String::from("MESSAGE"), String::from("it's \"quoted\""));
+        let script = remote_shell_command(&[String::from("printf %s \"$MESSAGE\"")], None, false, &remote_env, "/", &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().last(), Some("it's \"quoted\""));
+    }
+
+    #[test]
+    fn remote_shell_command_dir_with_special_chars() {
+        let dir = env::temp_dir().join(format!("mainframer-it's $dir {}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = remote_shell_command(&[String::from("pwd")], None, false, &BTreeMap::new(), &dir.to_string_lossy(), &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().last(), Some(dir.to_string_lossy().as_ref()));
+    }
+
+    #[test]
+    fn remote_shell_command_title_is_not_expanded() {
+        let dir = env::temp_dir().join(format!("mainframer_title_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = remote_shell_command(&[String::from("true || $(touch x)")], None, false, &BTreeMap::new(), &dir.to_string_lossy(), &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        let expanded = dir.join("x").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().next(), Some("true || $(touch x)"));
+        assert!(!expanded);
+    }
+
+    #[test]
+    fn remote_shell_command_execution_working_dir() {
+        let config = config_from_str("remote_machine=machine\nexecution_working_dir=services/api", None).unwrap();
+        let dir = sync::execution_dir_on_remote_machine(Path::new("/home/user/project"), &config);
+        assert_eq!(
+            remote_shell_command(&[String::from("make")], None, false, &BTreeMap::new(), &dir, &ScriptShell::default()),
+            "printf '%s\\n' 'set -e && cd ~/mainframer/home/user/project/services/api && echo make && echo \"\" && make' | bash"
+        );
+    }
+
+    fn command_file(content: &str) -> CommandFile {
+        CommandFile {
+            path: String::from("ci/build.sh"),
+            content: String::from(content),
+        }
+    }
+
+    #[test]
+    fn remote_shell_command_command_file() {
+        assert_eq!(
+            remote_shell_command(&[], Some(&command_file("./gradlew assemble\n./gradlew test\n")), false, &BTreeMap::new(), "~/mainframer/project", &ScriptShell::default()),
+            "printf '%s\\n' 'set -e && cd ~/mainframer/project && echo ci/build.sh && echo \"\" && bash -s <<'\\''MAINFRAMER_COMMAND_FILE'\\''\n./gradlew assemble\n./gradlew test\nMAINFRAMER_COMMAND_FILE' | bash"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_command_file_runs_multi_line_script() {
+        let content = "message=\"it's \\\"quoted\\\" \\$HOME\"\nprintf '%s\\n' \"$message\"\nprintf '%s\\n' 'MAINFRAMER_COMMAND_FILE'\n";
+        let script = remote_shell_command(&[String::from("echo first")], Some(&command_file(content)), false, &BTreeMap::new(), "/", &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success());
+        assert_eq!(stdout.lines().rev().take(2).collect::<Vec<&str>>(), vec!["MAINFRAMER_COMMAND_FILE", "it's \"quoted\" $HOME"]);
+        assert!(stdout.lines().any(|line| line == "first"));
+    }
+
+    #[test]
+    fn remote_shell_command_command_file_keeps_backslashes() {
+        let content = "printf '%s|' 'back\\\\slash' 'new\\nline' 'stop\\cnot'\n";
+        let script = remote_shell_command(&[], Some(&command_file(content)), false, &BTreeMap::new(), "/", &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().last(), Some("back\\\\slash|new\\nline|stop\\cnot|"));
+    }
+
+    #[test]
+    fn remote_shell_command_command_file_keep_going() {
+        let script = remote_shell_command(&[String::from("(exit 3)")], Some(&command_file("echo from file")), true, &BTreeMap::new(), "/", &ScriptShell::default());
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line == "from file"));
+    }
+
+    #[test]
+    fn heredoc_delimiter_not_in_content() {
+        assert_eq!(heredoc_delimiter("echo hi\nMAINFRAMER_COMMAND_FILE\nMAINFRAMER_COMMAND_FILE_"), "MAINFRAMER_COMMAND_FILE__");
+    }
+
+    #[test]
+    fn remote_shell_command_shell() {
+        assert_eq!(
+            remote_shell_command(&[String::from("./gradlew build")], None, false, &BTreeMap::new(), "~/mainframer/project", &ScriptShell { shell: Some("/bin/bash"), ..ScriptShell::default() }),
+            "/bin/bash -c 'set -e && cd ~/mainframer/project && echo '\\''./gradlew build'\\'' && echo \"\" && ./gradlew build'"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_shell_quotes_command() {
+        let script = remote_shell_command(&[String::from("printf %s 'it is quoted'")], None, false, &BTreeMap::new(), "/", &ScriptShell { shell: Some("sh"), ..ScriptShell::default() });
+        assert_eq!(script, r#"sh -c 'set -e && cd / && echo '\''printf %s '\''\'\'''\''it is quoted'\''\'\'''\'''\'' && echo "" && printf %s '\''it is quoted'\'''"#);
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().last(), Some("it is quoted"));
+    }
+
+    #[test]
+    fn remote_shell_command_nice() {
+        let script_shell = ScriptShell { nice: Some(10), ..ScriptShell::default() };
+        assert_eq!(
+            remote_shell_command(&[String::from("make")], None, false, &BTreeMap::new(), "/", &script_shell),
+            "printf '%s\\n' 'set -e && cd / && echo make && echo \"\" && make' | nice -n 10 bash"
+        );
+    }
+
+    #[test]
+    fn remote_shell_command_nice_and_ionice_with_shell() {
+        let script_shell = ScriptShell { shell: Some("/bin/bash"), nice: Some(-5), ionice: true, ..ScriptShell::default() };
+        assert_eq!(
+            remote_shell_command(&[String::from("make")], None, false, &BTreeMap::new(), "/", &script_shell),
+            "nice -n -5 ionice -c3 /bin/bash -c 'set -e && cd / && echo make && echo \"\" && make'"
+        );
+    }
+
+    #[test]
+    fn execute_remote_command_ionice_only() {
+        let config = config_from_str("remote_machine=machine\nexecution_ionice=true", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        execute_remote_command(&[String::from("make")], None, false, &config, "~/mainframer/project", &Output::new(Verbosity::Quiet, true), &runner).unwrap();
+        assert!(runner.invocations()[0].1.last().unwrap().ends_with("' | ionice -c3 bash"));
+    }
+
+    #[test]
+    fn local_command_ignores_nice() {
+        let config = config_from_str("remote_machine=machine\nexecution_nice=19", None).unwrap();
+        let args = local_command_args(&[String::from("make")], None, false, &config, Path::new("/project"));
+        assert!(!args.iter().any(|arg| arg.contains("nice")));
+    }
+
+    #[test]
+    fn run_command_finishes_in_time() {
+        assert_eq!(run_command(&SystemRunner, "true", &[], Some(10)), Ok(()));
+    }
+
+    #[test]
+    fn run_command_exceeds_timeout() {
+        let start = Instant::now();
+        assert_eq!(run_command(&SystemRunner, "sleep", &[String::from("30")], Some(1)), Err(RemoteCommandError::TimedOut(1)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn execute_remote_command_runs_ssh() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        let result = execute_remote_command(&[String::from("./gradlew build")], None, false, &config, "~/mainframer/project", &Output::new(Verbosity::Quiet, true), &runner);
+        assert_eq!(result, Ok(()));
+        assert_eq!(runner.invocations(), vec![(String::from("ssh"), vec![
+            String::from("-p"),
+            String::from("2222"),
+            String::from("-o"),
+            String::from("StrictHostKeyChecking=accept-new"),
+            String::from("machine"),
+            String::from("printf '%s\\n' 'set -e && cd ~/mainframer/project && echo '\\''./gradlew build'\\'' && echo \"\" && ./gradlew build' | bash"),
+        ])]);
+    }
+
+    #[test]
+    fn execute_remote_command_failure() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(3, "");
+        assert_eq!(execute_remote_command(&[String::from("false")], None, false, &config, "~/mainframer/project", &Output::new(Verbosity::Quiet, true), &runner), Err(RemoteCommandError::Failed(Some(3))));
+    }
+
+    #[test]
+    fn execute_remote_command_dry_run() {
+        let config = config_from_str("remote_machine=machine\ndry_run=true", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        assert_eq!(execute_remote_command(&[String::from("make")], None, false, &config, "~/mainframer/project", &Output::new(Verbosity::Quiet, true), &runner), Ok(()));
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn local_command_runs_script_through_bash() {
+        let config = config_from_str("remote_machine=machine\nremote_env.BUILD_TYPE=release", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        let result = execute_local_command(&[String::from("./gradlew build")], None, false, &config, Path::new("/home/user/project/app"), &Output::new(Verbosity::Quiet, true), &runner);
+        assert_eq!(result, Ok(()));
+        assert_eq!(runner.invocations(), vec![(String::from("bash"), vec![
+            String::from("-c"),
+            String::from("set -e && cd /home/user/project/app && export BUILD_TYPE=\"release\" && echo './gradlew build' && echo \"\" && ./gradlew build"),
+        ])]);
+    }
+
+    #[test]
+    fn execute_local_command_without_ssh_and_rsync() {
+        let dir = env::temp_dir().join(format!("mainframer_local_command_{}", process::id()));
+        fs::create_dir_all(dir.join("app")).unwrap();
+        let dir = fs::canonicalize(dir).unwrap();
+
+        // Would fail to spawn if any of them were invoked.
+        let config = config_from_str("remote_machine=machine\nremote_machine_ssh_path=/nonexistent/ssh\nsync_rsync_path=/nonexistent/rsync\nexecution_working_dir=app", None).unwrap();
+        let execution_dir = sync::execution_dir_on_local_machine(&dir, &config);
+        let result = execute_local_command(&[String::from("pwd > pwd.txt")], None, false, &config, &execution_dir, &Output::new(Verbosity::Quiet, true), &SystemRunner);
+        let pwd = fs::read_to_string(dir.join("app/pwd.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(pwd.ok(), Some(format!("{}\n", dir.join("app").to_string_lossy())));
+    }
+
+    #[test]
+    fn execute_local_command_keeps_backslashes() {
+        let dir = env::temp_dir().join(format!("mainframer_local_backslashes_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let result = execute_local_command(&[String::from("printf '%s' 'back\\\\slash stop\\cnot' > out.txt")], Some(&command_file("printf '%s' 'new\\nline' >> out.txt")), false, &config, &dir, &Output::new(Verbosity::Quiet, true), &SystemRunner);
+        let out = fs::read_to_string(dir.join("out.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(out.ok(), Some(String::from("back\\\\slash stop\\cnotnew\\nline")));
+    }
+
+    #[test]
+    fn run_command_no_timeout() {
+        assert_eq!(run_command(&SystemRunner, "false", &[], None), Err(RemoteCommandError::Failed(Some(1))));
+    }
+
+    #[test]
+    fn run_command_propagates_exit_code() {
+        let result = run_command(&SystemRunner, "sh", &[String::from("-c"), String::from("exit 42")], None);
+        assert_eq!(result, Err(RemoteCommandError::Failed(Some(42))));
+        assert_eq!(result.unwrap_err().exit_code(), 42);
+    }
+
+    #[test]
+    fn run_command_success() {
+        assert_eq!(run_command(&SystemRunner, "true", &[], None), Ok(()));
+    }
+
+    #[test]
+    fn exit_code_terminated() {
+        assert_eq!(RemoteCommandError::Failed(None).exit_code(), 1);
+    }
+
+    #[test]
+    fn exit_code_timed_out() {
+        assert_eq!(RemoteCommandError::TimedOut(60).exit_code(), 124);
     }
 }
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+// Machine-readable summary of a run.
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct RunReport {
+    pub upload_duration: Duration,
+    pub remote_execution_duration: Duration,
+    pub download_duration: Duration,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+    pub exit_code: i32,
+}
+
+// Only numbers are serialized so there is nothing to escape.
+pub fn format_json(report: &RunReport) -> String {
+    format!(
+        "{{\"uploadDurationMs\":{upload},\"remoteExecutionDurationMs\":{execution},\"downloadDurationMs\":{download},\"bytesUploaded\":{bytes_uploaded},\"bytesDownloaded\":{bytes_downloaded},\"exitCode\":{exit_code}}}",
+        upload = report.upload_duration.as_millis(),
+        execution = report.remote_execution_duration.as_millis(),
+        download = report.download_duration.as_millis(),
+        bytes_uploaded = report.bytes_uploaded,
+        bytes_downloaded = report.bytes_downloaded,
+        exit_code = report.exit_code
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_json_all_fields() {
+        let report = RunReport {
+            upload_duration: Duration::from_millis(1200),
+            remote_execution_duration: Duration::from_millis(45300),
+            download_duration: Duration::from_millis(800),
+            bytes_uploaded: 12345,
+            bytes_downloaded: 678,
+            exit_code: 1,
+        };
+        assert_eq!(
+            format_json(&report),
+            "{\"uploadDurationMs\":1200,\"remoteExecutionDurationMs\":45300,\"downloadDurationMs\":800,\"bytesUploaded\":12345,\"bytesDownloaded\":678,\"exitCode\":1}"
+        );
+    }
+
+    #[test]
+    fn format_json_empty_report() {
+        assert_eq!(
+            format_json(&RunReport::default()),
+            "{\"uploadDurationMs\":0,\"remoteExecutionDurationMs\":0,\"downloadDurationMs\":0,\"bytesUploaded\":0,\"bytesDownloaded\":0,\"exitCode\":0}"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::env;
+use std::path::Path;
+
+// Checked before anything runs, otherwise missing binary shows up as a confusing spawn error midway.
+pub fn check_required_binaries(binaries: &[&str], is_available: &dyn Fn(&str) -> bool) -> Result<(), String> {
+    let messages: Vec<String> = binaries.iter()
+        .filter(|binary| !is_available(binary))
+        .map(|binary| format!("{} is required but was not found on PATH; install it and retry.", binary))
+        .collect();
+
+    match messages.len() {
+        0 => Ok(()),
+        _ => Err(messages.join("\n"))
+    }
+}
+
+// Same lookup shell does: binary with a path is used as is, otherwise it's searched in PATH dirs.
+pub fn is_binary_in_path(binary: &str) -> bool {
+    if binary.contains('/') {
+        return Path::new(binary).is_file();
+    }
+
+    match env::var_os("PATH") {
+        None => false,
+        Some(path) => env::split_paths(&path).any(|dir| dir.join(binary).is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_required_binaries_present() {
+        assert_eq!(check_required_binaries(&["rsync", "ssh"], &|_| true), Ok(()));
+    }
+
+    #[test]
+    fn check_required_binaries_missing() {
+        assert_eq!(
+            check_required_binaries(&["rsync", "ssh"], &|binary| binary == "ssh"),
+            Err(String::from("rsync is required but was not found on PATH; install it and retry."))
+        );
+    }
+
+    #[test]
+    fn check_required_binaries_several_missing() {
+        assert_eq!(
+            check_required_binaries(&["rsync", "ssh"], &|_| false),
+            Err(String::from("rsync is required but was not found on PATH; install it and retry.\nssh is required but was not found on PATH; install it and retry."))
+        );
+    }
+
+    #[test]
+    fn is_binary_in_path_shell() {
+        assert!(is_binary_in_path("sh"));
+    }
+
+    #[test]
+    fn is_binary_in_path_unknown() {
+        assert!(!is_binary_in_path("mainframer-unknown-binary"));
+    }
+}
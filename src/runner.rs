@@ -0,0 +1,243 @@
+use signal;
+use ssh;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::process::Child;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Output;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(test)]
+use std::os::unix::process::ExitStatusExt;
+#[cfg(test)]
+use std::sync::Mutex;
+
+// Seam between assembled command lines and processes, so tests can check the former without spawning the latter.
+// "Sync" lets parallel uploads share one runner between threads.
+pub trait CommandRunner: Sync {
+    // Output goes to Mainframer output as is.
+    fn run(&self, program: &str, args: &[String]) -> io::Result<ExitStatus> {
+        self.run_with_timeout(program, args, None)
+    }
+
+    // Process killed after "timeout" is reported as "io::ErrorKind::TimedOut".
+    fn run_with_timeout(&self, program: &str, args: &[String], timeout: Option<Duration>) -> io::Result<ExitStatus>;
+
+    // Output is captured, "on_line" gets stdout lines as they come.
+    fn output(&self, program: &str, args: &[String], on_line: Option<&dyn Fn(&str)>) -> io::Result<Output>;
+}
+
+// Spawns processes for real, they are terminated on Ctrl-C.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run_with_timeout(&self, program: &str, args: &[String], timeout: Option<Duration>) -> io::Result<ExitStatus> {
+        let mut process = Command::new(program)
+            .args(args)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        signal::register_child(process.id());
+        let result = wait_with_timeout(&mut process, timeout);
+        signal::unregister_child(process.id());
+
+        result
+    }
+
+    // rsync redraws progress line with "\r", so both "\r" and "\n" end a line.
+    fn output(&self, program: &str, args: &[String], on_line: Option<&dyn Fn(&str)>) -> io::Result<Output> {
+        let mut process = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        signal::register_child(process.id());
+
+        // Read separately so full stderr pipe doesn't block the process while stdout is being read.
+        let mut stderr_pipe = process.stderr.take().unwrap();
+        let stderr_reader = thread::spawn(move || {
+            let mut stderr = Vec::new();
+            let _ = stderr_pipe.read_to_end(&mut stderr);
+            stderr
+        });
+
+        let mut stdout = Vec::new();
+        let mut line = Vec::new();
+
+        for byte in BufReader::new(process.stdout.take().unwrap()).bytes() {
+            let byte = byte?;
+            stdout.push(byte);
+
+            if byte == b'\r' || byte == b'\n' {
+                if let Some(on_line) = on_line {
+                    on_line(&String::from_utf8_lossy(&line));
+                }
+                line.clear();
+            } else {
+                line.push(byte);
+            }
+        }
+
+        let status = process.wait();
+        signal::unregister_child(process.id());
+
+        Ok(Output {
+            status: status?,
+            stdout,
+            stderr: stderr_reader.join().unwrap_or_default(),
+        })
+    }
+}
+
+// Prints every command line to stderr before running it, so it doesn't mix with command output on stdout.
+pub struct EchoingRunner<'a>(pub &'a dyn CommandRunner);
+
+impl<'a> CommandRunner for EchoingRunner<'a> {
+    fn run_with_timeout(&self, program: &str, args: &[String], timeout: Option<Duration>) -> io::Result<ExitStatus> {
+        eprintln!("{}", format_command_line(program, args));
+        self.0.run_with_timeout(program, args, timeout)
+    }
+
+    fn output(&self, program: &str, args: &[String], on_line: Option<&dyn Fn(&str)>) -> io::Result<Output> {
+        eprintln!("{}", format_command_line(program, args));
+        self.0.output(program, args, on_line)
+    }
+}
+
+// Quoted so it can be copied and run as is.
+pub fn format_command_line(program: &str, args: &[String]) -> String {
+    let mut words = vec![ssh::shell_quote(program)];
+    words.extend(args.iter().map(|arg| ssh::shell_quote(arg)));
+    format!("$ {}", words.join(" "))
+}
+
+fn wait_with_timeout(process: &mut Child, timeout: Option<Duration>) -> io::Result<ExitStatus> {
+    let deadline = match timeout {
+        None => return process.wait(),
+        Some(timeout) => Instant::now() + timeout
+    };
+
+    loop {
+        if let Some(exit_status) = process.try_wait()? {
+            return Ok(exit_status);
+        }
+
+        if Instant::now() >= deadline {
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "process timed out"));
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// Records invocations instead of running them, every one "exits" with the same code and prints the same stdout.
+#[cfg(test)]
+pub struct RecordingRunner {
+    invocations: Mutex<Vec<(String, Vec<String>)>>,
+    exit_code: i32,
+    stdout: String,
+}
+
+#[cfg(test)]
+impl RecordingRunner {
+    pub fn new(exit_code: i32, stdout: &str) -> RecordingRunner {
+        RecordingRunner {
+            invocations: Mutex::new(vec![]),
+            exit_code,
+            stdout: String::from(stdout),
+        }
+    }
+
+    pub fn invocations(&self) -> Vec<(String, Vec<String>)> {
+        self.invocations.lock().unwrap().clone()
+    }
+
+    fn record(&self, program: &str, args: &[String]) -> ExitStatus {
+        self.invocations.lock().unwrap().push((String::from(program), args.to_vec()));
+        // Raw value is a wait status, exit code is in its second byte.
+        ExitStatus::from_raw(self.exit_code << 8)
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for RecordingRunner {
+    fn run_with_timeout(&self, program: &str, args: &[String], _timeout: Option<Duration>) -> io::Result<ExitStatus> {
+        Ok(self.record(program, args))
+    }
+
+    fn output(&self, program: &str, args: &[String], on_line: Option<&dyn Fn(&str)>) -> io::Result<Output> {
+        let status = self.record(program, args);
+
+        if let Some(on_line) = on_line {
+            self.stdout.split(['\r', '\n']).filter(|line| !line.is_empty()).for_each(on_line);
+        }
+
+        Ok(Output {
+            status,
+            stdout: self.stdout.clone().into_bytes(),
+            stderr: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn system_runner_run_exit_code() {
+        let status = SystemRunner.run("sh", &[String::from("-c"), String::from("exit 3")]).unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn system_runner_run_with_timeout_kills_process() {
+        let result = SystemRunner.run_with_timeout("sleep", &[String::from("5")], Some(Duration::from_millis(100)));
+        assert_eq!(result.map_err(|error| error.kind()), Err(io::ErrorKind::TimedOut));
+    }
+
+    #[test]
+    fn system_runner_output_reports_lines() {
+        let lines = RefCell::new(vec![]);
+        let output = SystemRunner.output(
+            "printf",
+            &[String::from("10%%\\r100%%\\ndone\\n")],
+            Some(&|line| lines.borrow_mut().push(String::from(line)))
+        ).unwrap();
+        assert!(output.status.success());
+        assert_eq!(*lines.borrow(), vec!["10%", "100%", "done"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "10%\r100%\ndone\n");
+    }
+
+    #[test]
+    fn format_command_line_quotes_args() {
+        let args = vec![String::from("--archive"), String::from("--rsh=ssh -p 2222"), String::from("machine:~/mainframer/project")];
+        assert_eq!(format_command_line("rsync", &args), "$ rsync --archive '--rsh=ssh -p 2222' 'machine:~/mainframer/project'");
+    }
+
+    #[test]
+    fn echoing_runner_delegates() {
+        let runner = RecordingRunner::new(0, "");
+        EchoingRunner(&runner).run("ssh", &[String::from("machine")]).unwrap();
+        assert_eq!(runner.invocations(), vec![(String::from("ssh"), vec![String::from("machine")])]);
+    }
+
+    #[test]
+    fn recording_runner_records_invocations() {
+        let runner = RecordingRunner::new(2, "");
+        let status = runner.run("ssh", &[String::from("machine"), String::from("true")]).unwrap();
+        assert_eq!(status.code(), Some(2));
+        assert_eq!(runner.invocations(), vec![(String::from("ssh"), vec![String::from("machine"), String::from("true")])]);
+    }
+}
@@ -0,0 +1,149 @@
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::os::raw::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+use sync;
+
+const SIGINT: c_int = 2;
+const SIGTERM: c_int = 15;
+
+// Same exit code shells use for a command interrupted with Ctrl-C.
+const INTERRUPTED_EXIT_CODE: c_int = 130;
+
+// Parallel upload runs up to "sync::MAX_PARALLELISM" rsync processes at once, the rest is for hooks,
+// ssh master connection and whatever runs next to them.
+const MAX_CHILDREN: usize = sync::MAX_PARALLELISM + 8;
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    fn kill(pid: i32, signum: c_int) -> c_int;
+    fn unlink(path: *const c_char) -> c_int;
+    fn _exit(status: c_int) -> !;
+}
+
+// Lock-free so signal handler can go through it without allocating or blocking.
+struct Children {
+    pids: [AtomicU32; MAX_CHILDREN],
+}
+
+impl Children {
+    const fn new() -> Children {
+        Children {
+            pids: [const { AtomicU32::new(0) }; MAX_CHILDREN],
+        }
+    }
+
+    fn register(&self, pid: u32) {
+        for slot in &self.pids {
+            if slot.compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                return;
+            }
+        }
+    }
+
+    fn unregister(&self, pid: u32) {
+        for slot in &self.pids {
+            let _ = slot.compare_exchange(pid, 0, Ordering::SeqCst, Ordering::SeqCst);
+        }
+    }
+
+    // Terminated ssh closes its connection which stops command on remote machine too.
+    fn terminate_all(&self) {
+        for slot in &self.pids {
+            let pid = slot.swap(0, Ordering::SeqCst);
+
+            if pid != 0 {
+                unsafe {
+                    kill(pid as i32, SIGTERM);
+                }
+            }
+        }
+    }
+}
+
+static CHILDREN: Children = Children::new();
+static CONTROL_SOCKET: OnceLock<CString> = OnceLock::new();
+
+pub fn register_child(pid: u32) {
+    CHILDREN.register(pid);
+}
+
+pub fn unregister_child(pid: u32) {
+    CHILDREN.unregister(pid);
+}
+
+// On SIGINT and SIGTERM running children are terminated, master connection socket is removed
+// so it's not reused, and Mainframer exits with 130.
+pub fn install_interrupt_handler(control_socket: Option<&Path>) {
+    if let Some(control_socket) = control_socket.and_then(|path| CString::new(path.as_os_str().as_bytes()).ok()) {
+        let _ = CONTROL_SOCKET.set(control_socket);
+    }
+
+    unsafe {
+        signal(SIGINT, handle_interrupt);
+        signal(SIGTERM, handle_interrupt);
+    }
+}
+
+// Only async-signal-safe calls are allowed here.
+extern "C" fn handle_interrupt(_signum: c_int) {
+    CHILDREN.terminate_all();
+
+    if let Some(control_socket) = CONTROL_SOCKET.get() {
+        unsafe {
+            unlink(control_socket.as_ptr());
+        }
+    }
+
+    unsafe {
+        _exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn terminate_all_kills_registered_child() {
+        let children = Children::new();
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        children.register(child.id());
+
+        children.terminate_all();
+
+        assert_eq!(child.wait().unwrap().code(), None);
+    }
+
+    #[test]
+    fn terminate_all_kills_max_parallelism_children() {
+        let children = Children::new();
+        let mut child_processes: Vec<_> = (0..sync::MAX_PARALLELISM).map(|_| Command::new("sleep").arg("30").spawn().unwrap()).collect();
+        for child in &child_processes {
+            children.register(child.id());
+        }
+
+        children.terminate_all();
+
+        for child in &mut child_processes {
+            assert_eq!(child.wait().unwrap().code(), None);
+        }
+    }
+
+    #[test]
+    fn terminate_all_skips_unregistered_child() {
+        let children = Children::new();
+        let mut child = Command::new("sh").arg("-c").arg("sleep 1").spawn().unwrap();
+        children.register(child.id());
+        children.unregister(child.id());
+
+        children.terminate_all();
+
+        assert_eq!(child.wait().unwrap().code(), Some(0));
+    }
+}
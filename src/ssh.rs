@@ -0,0 +1,311 @@
+use config::Config;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::process::Command;
+use std::process::Stdio;
+
+// Master connection outlives the run for this long if it's not closed explicitly (i.e. on errors).
+const CONTROL_PERSIST_SECONDS: u32 = 60;
+
+// Options shared by every ssh connection: rsync transport and remote command execution.
+pub fn ssh_options(config: &Config) -> Vec<String> {
+    let mut options = Vec::new();
+
+    if let Some(port) = config.remote_machine_port {
+        options.push(String::from("-p"));
+        options.push(port.to_string());
+    }
+
+    if let Some(ref identity_file) = config.remote_machine_identity_file {
+        options.push(String::from("-i"));
+        options.push(identity_file.clone());
+    }
+
+    if let Some(ref proxy_jump) = config.remote_machine_proxy_jump {
+        options.push(String::from("-J"));
+        options.push(proxy_jump.clone());
+    }
+
+    // ssh uses the first value of an option, so these win over the ones derived from other keys.
+    for option in &config.remote_machine_ssh_options {
+        options.push(String::from("-o"));
+        options.push(option.clone());
+    }
+
+    if let Some(interval) = config.remote_machine_keep_alive_interval_seconds {
+        options.push(String::from("-o"));
+        options.push(format!("ServerAliveInterval={}", interval));
+    }
+
+    if let Some(count_max) = config.remote_machine_keep_alive_count_max {
+        options.push(String::from("-o"));
+        options.push(format!("ServerAliveCountMax={}", count_max));
+    }
+
+    // Unreachable machine fails within it instead of hanging for the TCP timeout of the OS.
+    if let Some(connect_timeout) = config.remote_machine_connect_timeout_seconds {
+        options.push(String::from("-o"));
+        options.push(format!("ConnectTimeout={}", connect_timeout));
+    }
+
+    if config.remote_machine_multiplex {
+        options.push(String::from("-o"));
+        options.push(String::from("ControlMaster=auto"));
+        options.push(String::from("-o"));
+        options.push(format!("ControlPath={}", control_path().to_string_lossy()));
+        options.push(String::from("-o"));
+        options.push(format!("ControlPersist={}", CONTROL_PERSIST_SECONDS));
+    }
+
+    if let Some(ref known_hosts_file) = config.remote_machine_known_hosts_file {
+        options.push(String::from("-o"));
+        // ssh treats whitespace separated value as several files.
+        if known_hosts_file.contains(char::is_whitespace) {
+            options.push(format!("UserKnownHostsFile=\"{}\"", known_hosts_file));
+        } else {
+            options.push(format!("UserKnownHostsFile={}", known_hosts_file));
+        }
+    }
+
+    options.push(String::from("-o"));
+    options.push(format!("StrictHostKeyChecking={}", if config.remote_machine_strict_host_key_checking { "yes" } else { "accept-new" }));
+
+    options
+}
+
+// Unique per run so concurrent runs don't share (and close) each other's master connection.
+pub fn control_path() -> PathBuf {
+    env::temp_dir().join(format!("mainframer-{}.sock", process::id()))
+}
+
+// Tears down master connection opened by the first ssh invocation of the run, failures are not important.
+pub fn close_master_connection(config: &Config) {
+    if !config.remote_machine_multiplex || config.dry_run {
+        return;
+    }
+
+    let _ = Command::new(&config.remote_machine_ssh_path)
+        .args(ssh_options(config))
+        .arg("-O")
+        .arg("exit")
+        .arg(config.remote_machine_target())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+// Value for rsync "--rsh" option, rsync splits it on spaces by itself.
+pub fn rsync_shell(config: &Config) -> String {
+    let mut shell = vec![quote_rsync_shell_arg(&config.remote_machine_ssh_path)];
+    shell.extend(ssh_options(config).iter().map(|option| quote_rsync_shell_arg(option)));
+    shell.join(" ")
+}
+
+// rsync keeps spaces inside of single quotes and treats doubled single quote as a literal one.
+fn quote_rsync_shell_arg(arg: &str) -> String {
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"') {
+        format!("'{}'", arg.replace('\'', "''"))
+    } else {
+        String::from(arg)
+    }
+}
+
+// Quotes a word for POSIX shell of remote user, only safe words are left as is.
+pub fn shell_quote(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c)) {
+        String::from(s)
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+// Leading "~/" stays unquoted so remote shell still expands it to home dir.
+pub fn shell_quote_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(relative_path) => format!("~/{}", shell_quote(relative_path)),
+        None => shell_quote(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+
+    #[test]
+    fn ssh_options_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![String::from("-o"), String::from("StrictHostKeyChecking=accept-new")]);
+    }
+
+    #[test]
+    fn ssh_options_port() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![String::from("-p"), String::from("2222"), String::from("-o"), String::from("StrictHostKeyChecking=accept-new")]);
+    }
+
+    #[test]
+    fn rsync_shell_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(rsync_shell(&config), "ssh -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn rsync_shell_port() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222", None).unwrap();
+        assert_eq!(rsync_shell(&config), "ssh -p 2222 -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn ssh_options_identity_file() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_identity_file=/keys/id_rsa", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![String::from("-i"), String::from("/keys/id_rsa"), String::from("-o"), String::from("StrictHostKeyChecking=accept-new")]);
+    }
+
+    #[test]
+    fn rsync_shell_identity_file_with_space() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222\nremote_machine_identity_file=/my keys/id_rsa", None).unwrap();
+        assert_eq!(rsync_shell(&config), "ssh -p 2222 -i '/my keys/id_rsa' -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn rsync_shell_custom_ssh_path() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222\nremote_machine_ssh_path=/opt/ssh wrapper/ssh", None).unwrap();
+        assert_eq!(rsync_shell(&config), "'/opt/ssh wrapper/ssh' -p 2222 -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn quote_rsync_shell_arg_plain() {
+        assert_eq!(quote_rsync_shell_arg("/keys/id_rsa"), "/keys/id_rsa");
+    }
+
+    #[test]
+    fn quote_rsync_shell_arg_single_quote() {
+        assert_eq!(quote_rsync_shell_arg("/keys/it's"), "'/keys/it''s'");
+    }
+
+    #[test]
+    fn ssh_options_proxy_jump() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_proxy_jump=bastion,inner", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![String::from("-J"), String::from("bastion,inner"), String::from("-o"), String::from("StrictHostKeyChecking=accept-new")]);
+    }
+
+    #[test]
+    fn rsync_shell_proxy_jump() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_port=2222\nremote_machine_proxy_jump=bastion", None).unwrap();
+        assert_eq!(rsync_shell(&config), "ssh -p 2222 -J bastion -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn ssh_options_keep_alive() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_keep_alive_interval_seconds=30\nremote_machine_keep_alive_count_max=4", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![
+            String::from("-o"), String::from("ServerAliveInterval=30"),
+            String::from("-o"), String::from("ServerAliveCountMax=4"),
+            String::from("-o"), String::from("StrictHostKeyChecking=accept-new"),
+        ]);
+    }
+
+    #[test]
+    fn ssh_options_connect_timeout() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_connect_timeout_seconds=5", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![
+            String::from("-o"), String::from("ConnectTimeout=5"),
+            String::from("-o"), String::from("StrictHostKeyChecking=accept-new"),
+        ]);
+        assert_eq!(rsync_shell(&config), "ssh -o ConnectTimeout=5 -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn ssh_options_passthrough() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_ssh_options=Compression=yes StrictHostKeyChecking=no\nremote_machine_keep_alive_interval_seconds=30", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![
+            String::from("-o"), String::from("Compression=yes"),
+            String::from("-o"), String::from("StrictHostKeyChecking=no"),
+            String::from("-o"), String::from("ServerAliveInterval=30"),
+            String::from("-o"), String::from("StrictHostKeyChecking=accept-new"),
+        ]);
+    }
+
+    #[test]
+    fn ssh_options_multiplex() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_multiplex=true", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![
+            String::from("-o"), String::from("ControlMaster=auto"),
+            String::from("-o"), format!("ControlPath={}", control_path().to_string_lossy()),
+            String::from("-o"), String::from("ControlPersist=60"),
+            String::from("-o"), String::from("StrictHostKeyChecking=accept-new"),
+        ]);
+    }
+
+    #[test]
+    fn ssh_options_strict_host_key_checking() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_strict_host_key_checking=true", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![String::from("-o"), String::from("StrictHostKeyChecking=yes")]);
+    }
+
+    #[test]
+    fn ssh_options_no_strict_host_key_checking() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_strict_host_key_checking=false", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![String::from("-o"), String::from("StrictHostKeyChecking=accept-new")]);
+    }
+
+    #[test]
+    fn ssh_options_known_hosts_file() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_known_hosts_file=/project/.mainframer/known_hosts", None).unwrap();
+        assert_eq!(ssh_options(&config), vec![
+            String::from("-o"), String::from("UserKnownHostsFile=/project/.mainframer/known_hosts"),
+            String::from("-o"), String::from("StrictHostKeyChecking=accept-new"),
+        ]);
+    }
+
+    #[test]
+    fn rsync_shell_known_hosts_file_with_space() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_known_hosts_file=/my project/known_hosts", None).unwrap();
+        assert_eq!(rsync_shell(&config), "ssh -o 'UserKnownHostsFile=\"/my project/known_hosts\"' -o StrictHostKeyChecking=accept-new");
+    }
+
+    #[test]
+    fn control_path_is_unique_per_process() {
+        assert!(control_path().to_string_lossy().ends_with(&format!("mainframer-{}.sock", process::id())));
+    }
+
+    #[test]
+    fn shell_quote_plain() {
+        assert_eq!(shell_quote("/home/user/project"), "/home/user/project");
+    }
+
+    #[test]
+    fn shell_quote_empty() {
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn shell_quote_space() {
+        assert_eq!(shell_quote("/home/user/my project"), "'/home/user/my project'");
+    }
+
+    #[test]
+    fn shell_quote_single_quote() {
+        assert_eq!(shell_quote("/home/user/it's"), "'/home/user/it'\\''s'");
+    }
+
+    #[test]
+    fn shell_quote_dollar_sign() {
+        assert_eq!(shell_quote("/home/user/$project"), "'/home/user/$project'");
+    }
+
+    #[test]
+    fn shell_quote_path_keeps_tilde() {
+        assert_eq!(shell_quote_path("~/mainframer/my project"), "~/'mainframer/my project'");
+    }
+
+    #[test]
+    fn shell_quote_reaches_shell_intact() {
+        for value in &["/home/user/my project", "/home/user/it's", "/home/user/$project \"quoted\""] {
+            let output = Command::new("sh").arg("-c").arg(format!("printf %s {}", shell_quote(value))).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), *value);
+        }
+    }
+}
@@ -1,90 +1,1614 @@
 use config::Config;
+use config::Transport;
 use ignore::Ignore;
+use ssh;
+use progress;
+use runner::CommandRunner;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+use watch;
 
-// TODO add internal version of sync functions with closures as parameters to unit test properly.
-pub fn sync_local_to_remote(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore) -> Result<(), String> {
-    let mut command = Command::new("rsync");
+// More rsync processes than that mostly compete for the same link and disk.
+pub const MAX_PARALLELISM: usize = 16;
 
-    command
-        .arg("--archive")
-        .arg("--delete")
+// Statistics block rsync prints with "--stats".
+#[derive(Debug, PartialEq, Eq, Default, Clone)]
+pub struct TransferStats {
+    pub files_transferred: u64,
+    pub total_file_size: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    // Kept as printed, decimal separator depends on locale.
+    pub speedup: String,
+}
+
+// Bytes rsync sent and received over all syncs of the run.
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub struct TotalBytes {
+    pub up: u64,
+    pub down: u64,
+}
+
+impl TotalBytes {
+    pub fn add(&mut self, stats: &TransferStats) {
+        self.up += stats.bytes_sent;
+        self.down += stats.bytes_received;
+    }
+}
+
+// Both return rsync output which ends with "--stats" block, see "parse_stats()".
+// Progress is reported in percents of the whole transfer if "on_progress" is passed.
+pub fn sync_local_to_remote(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore, runner: &dyn CommandRunner, on_progress: Option<&dyn Fn(u8)>) -> Result<String, SyncFailure> {
+    execute_rsync(runner, local_to_remote_rsync_args(local_dir_absolute_path, config, ignore), config, on_progress)
+}
+
+pub fn sync_remote_to_local(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore, runner: &dyn CommandRunner, on_progress: Option<&dyn Fn(u8)>) -> Result<String, SyncFailure> {
+    execute_rsync(runner, remote_to_local_rsync_args(local_dir_absolute_path, config, ignore), config, on_progress)
+}
+
+// Uploads only files listed in "files_from", the ones missing locally are deleted on remote machine if deletion is enabled.
+pub fn sync_changed_local_to_remote(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore, files_from: &Path, runner: &dyn CommandRunner, on_progress: Option<&dyn Fn(u8)>) -> Result<String, SyncFailure> {
+    let args = incremental_rsync_args(local_to_remote_rsync_args(local_dir_absolute_path, config, ignore), files_from, config);
+    execute_rsync(runner, args, config, on_progress)
+}
+
+// Uploads "payload" into ".mainframer" of remote project dir uncompressed and as a whole, for measuring link speed.
+pub fn upload_probe(local_dir_absolute_path: &Path, config: &Config, payload: &Path, runner: &dyn CommandRunner) -> Result<String, SyncFailure> {
+    let mut args = vec![String::from("--whole-file"), String::from("--ignore-times")];
+
+    match config.remote_machine_transport {
+        Transport::Ssh => args.push(format!("--rsync-path=mkdir -p {} && rsync", ssh::shell_quote_path(&format!("{}/.mainframer", project_dir_on_remote_machine(local_dir_absolute_path, config))))),
+        Transport::Rsyncd => args.push(String::from("--mkpath")),
+    }
+
+    apply_bandwidth_limit(&mut args, config);
+    apply_io_timeout(&mut args, config);
+    apply_rsh(&mut args, config);
+    args.push(payload.to_string_lossy().into_owned());
+    args.push(format!("{}/.mainframer/", remote_project_location(local_dir_absolute_path, config)));
+
+    execute_rsync(runner, args, config, None)
+}
+
+// Part of the project uploaded by one rsync process of a parallel upload.
+#[derive(Debug, PartialEq, Eq)]
+enum UploadJob {
+    // Top-level dir with everything in it.
+    Dir(String),
+    // Everything except dirs uploaded by their own jobs, i.e. top-level files.
+    Rest(Vec<String>),
+}
+
+pub fn clamp_parallelism(parallelism: Option<i64>) -> usize {
+    parallelism.unwrap_or(1).clamp(1, MAX_PARALLELISM as i64) as usize
+}
+
+// Uploads each top-level dir with its own rsync process, at most "parallelism" of them at a time.
+// Every process gets the whole project dir as source so anchored excludes keep their meaning, filters just narrow it
+// down to the job's part. Parts excluded by filters are never deleted, so "--delete" stays within each part.
+// Returns rsync output of every job, errors of all failed jobs are reported together.
+pub fn sync_local_to_remote_parallel(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore, upload_patterns: &[String], parallelism: usize, runner: &dyn CommandRunner) -> Result<Vec<String>, SyncFailure> {
+    let top_level_dirs = match list_top_level_dirs(local_dir_absolute_path, upload_patterns, &ignore.include_patterns) {
+        Err(_) => return Err(SyncFailure::Other(format!("Could not list project dir '{}'.", local_dir_absolute_path.to_string_lossy()))),
+        Ok(value) => value
+    };
+
+    let jobs = partition_upload(top_level_dirs);
+    let next_job = AtomicUsize::new(0);
+
+    // Shared instead of returned by workers, so results of finished jobs survive a worker that panics later.
+    let finished_jobs: Mutex<Vec<(usize, Result<String, SyncFailure>)>> = Mutex::new(vec![]);
+
+    let panicked_workers = thread::scope(|scope| {
+        let workers: Vec<_> = (0..parallelism.min(jobs.len())).map(|_| scope.spawn(|| {
+            loop {
+                let index = next_job.fetch_add(1, Ordering::SeqCst);
+
+                match jobs.get(index) {
+                    None => return,
+                    Some(job) => {
+                        let result = execute_rsync(runner, upload_rsync_args(local_dir_absolute_path, config, ignore, Some(job)), config, None);
+                        finished_jobs.lock().unwrap_or_else(|error| error.into_inner()).push((index, result));
+                    }
+                }
+            }
+        })).collect();
+
+        workers.into_iter().map(|worker| worker.join()).filter(Result::is_err).count()
+    });
+
+    let mut results = finished_jobs.into_inner().unwrap_or_else(|error| error.into_inner());
+
+    // Jobs without result were interrupted by a worker that panicked or never started after all of them did.
+    if panicked_workers > 0 {
+        for index in 0..jobs.len() {
+            if !results.iter().any(|&(finished_index, _)| finished_index == index) {
+                results.push((index, Err(SyncFailure::Other(String::from("upload thread panicked before it finished")))));
+            }
+        }
+    }
+
+    results.sort_by_key(|&(index, _)| index);
+
+    let failures: Vec<(usize, &SyncFailure)> = results.iter()
+        .filter_map(|(index, result)| result.as_ref().err().map(|failure| (*index, failure)))
+        .collect();
+
+    if failures.is_empty() {
+        return Ok(results.into_iter().filter_map(|(_, result)| result.ok()).collect());
+    }
+
+    let message = failures.iter()
+        .map(|(index, failure)| format!("{}: {}", describe_upload_job(&jobs[*index]), failure))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    // Failed connection is likely the reason other jobs failed too.
+    if failures.iter().any(|(_, failure)| matches!(failure, SyncFailure::Connection(_))) {
+        Err(SyncFailure::Connection(message))
+    } else {
+        Err(SyncFailure::Other(message))
+    }
+}
+
+// Excluded dirs and ones outside of include patterns get no job of their own, the rest job skips them anyway.
+fn list_top_level_dirs(local_dir_absolute_path: &Path, upload_patterns: &[String], include_patterns: &[String]) -> io::Result<Vec<String>> {
+    let included_top_level_dirs: Vec<String> = include_patterns.iter()
+        .filter_map(|pattern| pattern.trim_start_matches('/').split('/').next())
+        .map(|first_component| format!("/{}", first_component))
+        .collect();
+
+    let mut dirs = vec![];
+
+    for entry in fs::read_dir(local_dir_absolute_path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        // Symlinks are uploaded as links, so they belong to the rest job.
+        let is_included = include_patterns.is_empty() || watch::is_ignored(&name, true, &included_top_level_dirs);
+
+        if entry.file_type()?.is_dir() && is_included && !watch::is_ignored(&name, true, upload_patterns) {
+            dirs.push(name);
+        }
+    }
+
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn partition_upload(top_level_dirs: Vec<String>) -> Vec<UploadJob> {
+    let mut jobs = vec![UploadJob::Rest(top_level_dirs.clone())];
+    jobs.extend(top_level_dirs.into_iter().map(UploadJob::Dir));
+    jobs
+}
+
+// Go after excludes so excluded files stay excluded and before include patterns so they don't pull in other parts,
+// rsync applies the first matching rule.
+fn upload_job_filters(job: &UploadJob) -> Vec<String> {
+    match job {
+        UploadJob::Dir(dir) => vec![format!("--include=/{}/", dir), String::from("--exclude=/*")],
+        UploadJob::Rest(dirs) => dirs.iter().map(|dir| format!("--exclude=/{}/", dir)).collect()
+    }
+}
+
+// Patterns are relative to project dir. Parent dirs of a nested pattern are included one by one since rsync
+// doesn't descend into excluded dirs, "***" matches a dir along with everything in it, "*" then excludes the rest.
+pub fn include_filters(include_patterns: &[String]) -> Vec<String> {
+    if include_patterns.is_empty() {
+        return vec![];
+    }
+
+    let mut filters: Vec<String> = vec![];
+    let mut push_filter = |filter: String| if !filters.contains(&filter) {
+        filters.push(filter);
+    };
+
+    for pattern in include_patterns {
+        let path = pattern.trim_start_matches('/');
+        let (path, dir_only) = match path.strip_suffix('/') {
+            Some(dir_path) => (dir_path, true),
+            None => (path, false)
+        };
+
+        for (index, _) in path.match_indices('/') {
+            push_filter(format!("--include=/{}/", &path[..index]));
+        }
+
+        if !dir_only {
+            push_filter(format!("--include=/{}", path));
+        }
+
+        push_filter(format!("--include=/{}/***", path));
+    }
+
+    filters.push(String::from("--exclude=*"));
+    filters
+}
+
+fn describe_upload_job(job: &UploadJob) -> String {
+    match job {
+        UploadJob::Dir(dir) => format!("Sync of '{}/'", dir),
+        UploadJob::Rest(_) => String::from("Sync of top-level files")
+    }
+}
+
+// "--files-from" turns off recursion so "--delete" would do nothing, deleted files are listed instead.
+fn incremental_rsync_args(mut args: Vec<String>, files_from: &Path, config: &Config) -> Vec<String> {
+    args.retain(|arg| arg != "--delete");
+
+    // Source and destination stay the last ones.
+    let mut options = vec![format!("--files-from={}", files_from.to_string_lossy())];
+
+    if config.sync_delete_extraneous {
+        options.push(String::from("--delete-missing-args"));
+    }
+
+    let paths_index = args.len() - 2;
+    args.splice(paths_index..paths_index, options);
+    args
+}
+
+fn local_to_remote_rsync_args(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore) -> Vec<String> {
+    upload_rsync_args(local_dir_absolute_path, config, ignore, None)
+}
+
+// "job" narrows upload down to a part of the project, see "sync_local_to_remote_parallel()".
+fn upload_rsync_args(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore, job: Option<&UploadJob>) -> Vec<String> {
+    let mut args = vec![String::from("--archive")];
+
+    // Files removed locally would otherwise linger on remote machine and break builds.
+    if config.sync_delete_extraneous {
+        args.push(String::from("--delete"));
+    }
+
+    args.push(String::from("--stats"));
+    match config.remote_machine_transport {
         // Create (if not exists) project dir on remote machine.
-        .arg(format!("--rsync-path=mkdir -p {} && rsync", project_dir_on_remote_machine(local_dir_absolute_path)))
-        .arg(format!("--compress-level={}", config.local_compression_level));
+        Transport::Ssh => args.push(format!("--rsync-path=mkdir -p {} && rsync", ssh::shell_quote_path(&project_dir_on_remote_machine(local_dir_absolute_path, config)))),
+        // Daemon doesn't run shell commands, rsync 3.2.3+ creates missing dirs itself.
+        Transport::Rsyncd => args.push(String::from("--mkpath")),
+    }
+
+    apply_preserve_flags(&mut args, config);
+    apply_dry_run(&mut args, config);
+    apply_compression_level(&mut args, config.local_compression_level, config);
+    apply_max_file_size(&mut args, config.sync_max_file_size_upload);
+    apply_bandwidth_limit(&mut args, config);
+    apply_io_timeout(&mut args, config);
+    apply_whole_file(&mut args, config);
+    apply_checksum(&mut args, config);
+    apply_ignore_times(&mut args, config);
+    apply_partial_dir(&mut args, config);
+
+    apply_excludes(&mut args, &built_in_patterns(config));
+    apply_excludes(&mut args, &ignore.common_patterns);
+    apply_exclude_from(&mut args, &ignore.local_ignore_file);
+    apply_excludes(&mut args, &ignore.cli_patterns);
+
+    if let Some(job) = job {
+        args.extend(upload_job_filters(job));
+    }
+
+    args.extend(include_filters(&ignore.include_patterns));
+
+    args.extend(config.rsync_args.iter().cloned());
 
-    apply_exclude_from(&mut command, &ignore.common_ignore_file);
-    apply_exclude_from(&mut command, &ignore.local_ignore_file);
+    apply_rsh(&mut args, config);
+    args.push(String::from("./"));
+    args.push(remote_project_location(local_dir_absolute_path, config));
 
-    command
-        .arg("--rsh=ssh")
-        .arg("./");
+    args
+}
+
+fn remote_to_local_rsync_args(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore) -> Vec<String> {
+    let mut args = vec![
+        String::from("--archive"),
+        String::from("--delete"),
+        String::from("--stats"),
+    ];
+
+    apply_preserve_flags(&mut args, config);
+    apply_dry_run(&mut args, config);
+    apply_compression_level(&mut args, config.remote_compression_level, config);
+    apply_max_file_size(&mut args, config.sync_max_file_size_download);
+    apply_bandwidth_limit(&mut args, config);
+    apply_io_timeout(&mut args, config);
+    apply_whole_file(&mut args, config);
+    apply_partial_dir(&mut args, config);
+    apply_result_paths_flags(&mut args, config);
+
+    apply_excludes(&mut args, &built_in_patterns(config));
+    apply_excludes(&mut args, &ignore.common_patterns);
+    apply_exclude_from(&mut args, &ignore.remote_ignore_file);
+    apply_excludes(&mut args, &ignore.cli_patterns);
 
-    command.arg(format!(
-        "{remote_machine_name}:{project_dir_on_remote_machine}",
-        remote_machine_name = config.remote_machine_name,
-        project_dir_on_remote_machine = project_dir_on_remote_machine(local_dir_absolute_path))
-    );
+    args.extend(config.rsync_args.iter().cloned());
 
-    execute_rsync(&mut command)
+    apply_rsh(&mut args, config);
+    args.extend(remote_to_local_sources(&remote_project_location(local_dir_absolute_path, config), config));
+    args.push(String::from("./"));
+
+    args
 }
 
-pub fn sync_remote_to_local(local_dir_absolute_path: &Path, config: &Config, ignore: &Ignore) -> Result<(), String> {
-    let mut command = Command::new("rsync");
+// Result paths are passed as separate sources, "--relative" keeps them at the same place inside of project dir.
+// Path listed but not produced by the command is not an error, i.e. no "target/release" after a debug build.
+fn apply_result_paths_flags(args: &mut Vec<String>, config: &Config) {
+    if !config.execution_result_paths.is_empty() {
+        args.push(String::from("--relative"));
+        args.push(String::from("--ignore-missing-args"));
+    }
+}
 
-    command
-        .arg("--archive")
-        .arg("--delete")
-        .arg(format!("--compress-level={}", config.remote_compression_level));
+// "/./" marks where the relative part starts, so "project/./build/outputs" lands into "./build/outputs".
+fn remote_to_local_sources(remote_project_location: &str, config: &Config) -> Vec<String> {
+    if config.execution_result_paths.is_empty() {
+        vec![format!("{}/", remote_project_location)]
+    } else {
+        config.execution_result_paths.iter().map(|path| format!("{}/./{}", remote_project_location, path)).collect()
+    }
+}
+
+// Results of failed command (logs, reports) are usually needed the most, so sync back unless disabled.
+pub fn should_sync_remote_to_local(remote_command_succeeded: bool, config: &Config) -> bool {
+    remote_command_succeeded || config.sync_back_on_failure
+}
 
-    apply_exclude_from(&mut command, &ignore.common_ignore_file);
-    apply_exclude_from(&mut command, &ignore.remote_ignore_file);
+#[derive(Debug, PartialEq, Eq)]
+enum RemoteFolderPrefix<'a> {
+    // "/scratch", empty one is what "/" turns into after trailing slash is trimmed.
+    Absolute(&'a str),
+    // "~/mainframer" or "~user/mainframer", expanded by remote shell.
+    Tilde(&'a str),
+    // "mainframer", relative to remote user's home.
+    Relative(&'a str),
+}
 
-    command
-        .arg("--rsh=ssh")
-        .arg(format!(
-            "{remote_machine_name}:{project_dir_on_remote_machine}/",
-            remote_machine_name = config.remote_machine_name,
-            project_dir_on_remote_machine = project_dir_on_remote_machine(local_dir_absolute_path))
+fn classify_remote_folder_prefix(prefix: &str) -> RemoteFolderPrefix<'_> {
+    if prefix.is_empty() || prefix.starts_with('/') {
+        RemoteFolderPrefix::Absolute(prefix)
+    } else if prefix.starts_with('~') {
+        RemoteFolderPrefix::Tilde(prefix)
+    } else {
+        RemoteFolderPrefix::Relative(prefix.trim_start_matches("./"))
+    }
+}
+
+// Relative prefix is anchored at home explicitly, otherwise it'd depend on the dir rsync and ssh happen to start in.
+fn render_remote_folder_prefix(prefix: &RemoteFolderPrefix) -> String {
+    match *prefix {
+        RemoteFolderPrefix::Absolute(prefix) | RemoteFolderPrefix::Tilde(prefix) => String::from(prefix),
+        RemoteFolderPrefix::Relative(prefix) => format!("~/{}", prefix),
+    }
+}
+
+// Used both as rsync destination and "cd" target of remote command so they always agree.
+pub fn project_dir_on_remote_machine(local_dir_absolute_path: &Path, config: &Config) -> String {
+    format!(
+        "{}{}",
+        render_remote_folder_prefix(&classify_remote_folder_prefix(&config.remote_folder_prefix)),
+        local_dir_absolute_path.to_string_lossy()
+    )
+}
+
+// Project dir as rsync source or destination, daemon module stands for "remote_folder_prefix" there.
+fn remote_project_location(local_dir_absolute_path: &Path, config: &Config) -> String {
+    match (config.remote_machine_transport, &config.remote_machine_module) {
+        (Transport::Rsyncd, Some(module)) => format!(
+            "rsync://{remote_machine}/{module}{local_dir}",
+            remote_machine = config.remote_machine_rsync_target(),
+            module = module,
+            local_dir = local_dir_absolute_path.to_string_lossy()
+        ),
+        _ => format!(
+            "{remote_machine}:{project_dir_on_remote_machine}",
+            remote_machine = config.remote_machine_rsync_target(),
+            project_dir_on_remote_machine = project_dir_on_remote_machine(local_dir_absolute_path, config)
         )
-        .arg("./");
+    }
+}
+
+fn apply_rsh(rsync_args: &mut Vec<String>, config: &Config) {
+    if config.remote_machine_transport == Transport::Ssh {
+        rsync_args.push(format!("--rsh={}", ssh::rsync_shell(config)));
+    }
+}
+
+// Remote command is run in "execution_working_dir" of the project if it's set.
+pub fn execution_dir_on_remote_machine(local_dir_absolute_path: &Path, config: &Config) -> String {
+    let project_dir = project_dir_on_remote_machine(local_dir_absolute_path, config);
+
+    match config.execution_working_dir {
+        None => project_dir,
+        Some(ref working_dir) => format!("{}/{}", project_dir, working_dir)
+    }
+}
+
+// Same as "execution_dir_on_remote_machine" but for "--local" runs.
+pub fn execution_dir_on_local_machine(local_dir_absolute_path: &Path, config: &Config) -> PathBuf {
+    match config.execution_working_dir {
+        None => local_dir_absolute_path.to_path_buf(),
+        Some(ref working_dir) => local_dir_absolute_path.join(working_dir)
+    }
+}
+
+// "--archive" preserves everything, some remote filesystems reject chmod or symlinks though.
+fn apply_preserve_flags(rsync_args: &mut Vec<String>, config: &Config) {
+    if !config.sync_preserve_permissions {
+        rsync_args.push(String::from("--no-perms"));
+    }
+
+    if !config.sync_preserve_times {
+        rsync_args.push(String::from("--no-times"));
+    }
+
+    // "--copy-links" wins over "--links" implied by "--archive", so "--no-links" is not needed along with it.
+    if config.sync_copy_links {
+        rsync_args.push(String::from("--copy-links"));
+    } else if !config.sync_preserve_links {
+        rsync_args.push(String::from("--no-links"));
+    }
+}
+
+fn apply_dry_run(rsync_args: &mut Vec<String>, config: &Config) {
+    if config.dry_run {
+        rsync_args.push(String::from("--dry-run"));
+    }
+}
+
+fn apply_compression_level(rsync_args: &mut Vec<String>, compression_level: u8, config: &Config) {
+    // Level 0 means no compression at all, rsync doesn't need to know about it.
+    // Older rsync versions ignore the level unless compression is enabled explicitly.
+    if compression_level > 0 {
+        rsync_args.push(String::from("--compress"));
+        rsync_args.push(format!("--compress-level={}", compression_level));
+
+        if !config.compression_skip_compress.is_empty() {
+            rsync_args.push(format!("--skip-compress={}", config.compression_skip_compress.join("/")));
+        }
+    }
+}
+
+fn apply_bandwidth_limit(rsync_args: &mut Vec<String>, config: &Config) {
+    if let Some(bandwidth_limit) = config.bandwidth_limit_kbps {
+        rsync_args.push(format!("--bwlimit={}", bandwidth_limit));
+    }
+}
+
+// Catches half-open connections on which rsync would otherwise wait forever.
+fn apply_io_timeout(rsync_args: &mut Vec<String>, config: &Config) {
+    if let Some(io_timeout) = config.sync_io_timeout_seconds {
+        rsync_args.push(format!("--timeout={}", io_timeout));
+    }
+}
+
+fn apply_max_file_size(rsync_args: &mut Vec<String>, max_file_size: Option<u64>) {
+    if let Some(max_file_size) = max_file_size {
+        rsync_args.push(format!("--max-size={}", max_file_size));
+    }
+}
+
+fn apply_whole_file(rsync_args: &mut Vec<String>, config: &Config) {
+    match config.sync_whole_file {
+        None => (),
+        Some(true) => rsync_args.push(String::from("--whole-file")),
+        Some(false) => rsync_args.push(String::from("--no-whole-file")),
+    }
+}
+
+// Detects changes by content instead of mtime and size, only matters for upload that triggers rebuilds.
+fn apply_checksum(rsync_args: &mut Vec<String>, config: &Config) {
+    if config.sync_checksum {
+        rsync_args.push(String::from("--checksum"));
+    }
+}
+
+// Unchanged size and mtime don't skip a file then, so remote copy is rewritten even where it matches by them.
+fn apply_ignore_times(rsync_args: &mut Vec<String>, config: &Config) {
+    if config.force_full_upload {
+        rsync_args.push(String::from("--ignore-times"));
+    }
+}
 
-    execute_rsync(&mut command)
+// rsync excludes relative partial dir itself, so it's neither synced nor deleted.
+fn apply_partial_dir(rsync_args: &mut Vec<String>, config: &Config) {
+    if let Some(ref partial_dir) = config.sync_partial_dir {
+        rsync_args.push(String::from("--partial"));
+        rsync_args.push(format!("--partial-dir={}", partial_dir));
+    }
+}
+
+// Mainframer config is local, syncing it would overwrite the one remote machine might have.
+// VCS metadata is synced unless disabled since some builds rely on it (i.e. for versioning).
+pub fn built_in_patterns(config: &Config) -> Vec<String> {
+    let mut patterns = vec![String::from(".mainframer/")];
+
+    if config.sync_ignore_git {
+        patterns.push(String::from(".git/"));
+    }
+
+    patterns
+}
+
+fn apply_excludes(rsync_args: &mut Vec<String>, patterns: &[String]) {
+    for pattern in patterns {
+        rsync_args.push(format!("--exclude={}", pattern));
+    }
+}
+
+fn apply_exclude_from(rsync_args: &mut Vec<String>, exclude_file: &Option<PathBuf>) {
+    if let Some(ref value) = exclude_file {
+        rsync_args.push(format!("--exclude-from={}", value.to_string_lossy()));
+    }
+}
+
+// Stats of a parallel upload, none if any of the outputs has no statistics block.
+pub fn sum_stats(rsync_stdouts: &[String]) -> Option<TransferStats> {
+    let all_stats: Option<Vec<TransferStats>> = rsync_stdouts.iter().map(|rsync_stdout| parse_stats(rsync_stdout)).collect();
+
+    match all_stats?.as_slice() {
+        [] => None,
+        [stats] => Some(stats.clone()),
+        all_stats => {
+            let mut sum = TransferStats::default();
+
+            for stats in all_stats {
+                sum.files_transferred += stats.files_transferred;
+                sum.total_file_size += stats.total_file_size;
+                sum.bytes_sent += stats.bytes_sent;
+                sum.bytes_received += stats.bytes_received;
+            }
+
+            // Same as rsync computes it.
+            sum.speedup = format!("{:.2}", sum.total_file_size as f64 / (sum.bytes_sent + sum.bytes_received).max(1) as f64);
+            Some(sum)
+        }
+    }
+}
+
+// Returns none if output has no statistics block, i.e. rsync of unexpected version.
+pub fn parse_stats(rsync_stdout: &str) -> Option<TransferStats> {
+    let mut stats = TransferStats::default();
+    let mut bytes_found = false;
+
+    for line in rsync_stdout.lines() {
+        // rsync 3.1+ counts only regular files.
+        if let Some(value) = line.strip_prefix("Number of regular files transferred:").or_else(|| line.strip_prefix("Number of files transferred:")) {
+            stats.files_transferred = parse_stats_number(value);
+        } else if let Some(value) = line.strip_prefix("Total file size:") {
+            stats.total_file_size = parse_stats_number(value.trim_end().trim_end_matches("bytes"));
+        } else if let Some(value) = line.strip_prefix("Total bytes sent:") {
+            stats.bytes_sent = parse_stats_number(value);
+            bytes_found = true;
+        } else if let Some(value) = line.strip_prefix("Total bytes received:") {
+            stats.bytes_received = parse_stats_number(value);
+        } else if let Some((sent, received)) = parse_summary_bytes(line) {
+            // Summary line goes after "Total bytes" ones, it only fills them in if they're missing.
+            if !bytes_found {
+                stats.bytes_sent = sent;
+                stats.bytes_received = received;
+                bytes_found = true;
+            }
+        } else if let Some(index) = line.find("speedup is ") {
+            stats.speedup = String::from(line[index + "speedup is ".len()..].trim());
+        }
+    }
+
+    if bytes_found {
+        Some(stats)
+    } else {
+        None
+    }
+}
+
+pub fn format_transfer_stats(stats: &TransferStats) -> String {
+    format!(
+        "Transferred {files} of {total_file_size} bytes: sent {sent} bytes, received {received} bytes, speedup {speedup}.",
+        files = match stats.files_transferred {
+            1 => String::from("1 file"),
+            files => format!("{} files", files)
+        },
+        total_file_size = stats.total_file_size,
+        sent = stats.bytes_sent,
+        received = stats.bytes_received,
+        speedup = stats.speedup
+    )
+}
+
+// rsync 3.1+ separates thousands with "," or "." depending on locale.
+// "sent 1,234 bytes  received 56 bytes  2,580.00 bytes/sec"
+fn parse_summary_bytes(line: &str) -> Option<(u64, u64)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["sent", sent, "bytes", "received", received, "bytes", ..] => Some((parse_stats_number(sent), parse_stats_number(received))),
+        _ => None
+    }
+}
+
+pub fn format_total_bytes(total_bytes: &TotalBytes) -> String {
+    format!("Transferred {} up, {} down", format_binary_size(total_bytes.up), format_binary_size(total_bytes.down))
+}
+
+// Binary units with one decimal, i.e. "12.4 MiB", bytes are printed as is.
+pub fn format_binary_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn parse_stats_number(value: &str) -> u64 {
+    value.trim()
+        .chars()
+        .filter(|c| *c != ',' && *c != '.')
+        .collect::<String>()
+        .parse()
+        .unwrap_or(0)
 }
 
-pub fn project_dir_on_remote_machine(local_dir_absolute_path: &Path) -> String {
-    format!("~/mainframer{}", local_dir_absolute_path.to_string_lossy())
+// Failed sync with its message, connection failures are told apart since retrying them with a fixed config won't help.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyncFailure {
+    Connection(String),
+    Other(String),
 }
 
-fn apply_exclude_from(rsync_command: &mut Command, exclude_file: &Option<PathBuf>) {
-    match exclude_file {
-        Some(ref value) => {
-            rsync_command.arg(format!("--exclude-from={}", value.to_string_lossy()));
+impl fmt::Display for SyncFailure {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyncFailure::Connection(ref message) | SyncFailure::Other(ref message) => write!(formatter, "{}", message),
         }
-        None => ()
+    }
+}
+
+// Failure categories of rsync exit codes, see "EXIT VALUES" of rsync man page.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyncError {
+    // rsync reports ssh failures (unknown host, refused connection, denied key) as 255.
+    Connection,
+    Interrupted,
+    PartialTransfer,
+    Timeout,
+    Other(i32),
+}
+
+pub fn classify_rsync_exit(exit_code: i32) -> SyncError {
+    match exit_code {
+        255 => SyncError::Connection,
+        20 => SyncError::Interrupted,
+        23 | 24 => SyncError::PartialTransfer,
+        30 | 35 => SyncError::Timeout,
+        _ => SyncError::Other(exit_code)
+    }
+}
+
+fn connection_error_message(config: &Config) -> String {
+    let destination = match config.remote_machine_port {
+        None => config.remote_machine_target(),
+        // Bracketed so IPv6 host can be told apart from port.
+        Some(port) => format!("{}:{}", config.remote_machine_rsync_target(), port)
     };
+
+    format!("Could not connect to {}. Check the host is reachable and your SSH key is authorized", destination)
 }
 
-fn execute_rsync(rsync: &mut Command) -> Result<(), String> {
-    let result = rsync.output();
+// ssh rejects changed host key (or unknown one with strict checking) with the same exit code as any connection failure.
+fn is_host_key_error(ssh_stderr: &str) -> bool {
+    ssh_stderr.contains("REMOTE HOST IDENTIFICATION HAS CHANGED") || ssh_stderr.contains("Host key verification failed")
+}
+
+fn host_key_error_message(config: &Config) -> String {
+    format!("Host key of {} could not be verified, it might have changed since the last connection. Check 'known_hosts' before trusting the new key", config.remote_machine_target())
+}
+
+fn execute_rsync(runner: &dyn CommandRunner, mut args: Vec<String>, config: &Config, on_progress: Option<&dyn Fn(u8)>) -> Result<String, SyncFailure> {
+    let result = match on_progress {
+        None => runner.output(&config.sync_rsync_path, &args, None),
+        Some(on_progress) => {
+            args.push(String::from("--info=progress2"));
+            runner.output(&config.sync_rsync_path, &args, Some(&|line| if let Some(percent) = progress::parse_progress_percent(line) {
+                on_progress(percent);
+            }))
+        }
+    };
 
     match result {
-        Err(_) => Err(String::from("Generic sync error.")), // Rust doc doesn't really say when can an error occur.
+        Err(_) => Err(SyncFailure::Other(String::from("Generic sync error."))), // Rust doc doesn't really say when can an error occur.
         Ok(output) => match output.status.code() {
-            None => Err(String::from("Sync was terminated.")),
-            Some(status_code) => match status_code {
-                0 => Ok(()),
-                _ => Err(
+            None => Err(SyncFailure::Other(String::from("Sync was terminated."))),
+            Some(0) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            Some(status_code) => match classify_rsync_exit(status_code) {
+                SyncError::Connection if is_host_key_error(&String::from_utf8_lossy(&output.stderr)) => Err(SyncFailure::Connection(host_key_error_message(config))),
+                SyncError::Connection => Err(SyncFailure::Connection(connection_error_message(config))),
+                _ => Err(SyncFailure::Other(
                     format!(
                         "rsync exit code '{exit_code}',\nrsync stdout '{stdout}',\nrsync stderr '{stderr}'.",
                         exit_code = status_code,
                         stdout = String::from_utf8_lossy(&output.stdout),
                         stderr = String::from_utf8_lossy(&output.stderr)
                     )
-                )
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::config_from_str;
+    use runner::format_command_line;
+    use runner::RecordingRunner;
+
+    fn no_ignore() -> Ignore {
+        Ignore {
+            common_patterns: vec![],
+            local_ignore_file: None,
+            remote_ignore_file: None,
+            cli_patterns: vec![],
+            include_patterns: vec![],
+        }
+    }
+
+    #[test]
+    fn project_dir_on_remote_machine_default_prefix() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(project_dir_on_remote_machine(Path::new("/home/user/project"), &config), "~/mainframer/home/user/project");
+    }
+
+    #[test]
+    fn execution_dir_on_remote_machine_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(execution_dir_on_remote_machine(Path::new("/home/user/project"), &config), "~/mainframer/home/user/project");
+    }
+
+    #[test]
+    fn execution_dir_on_remote_machine_working_dir() {
+        let config = config_from_str("remote_machine=machine\nexecution_working_dir=services/api", None).unwrap();
+        assert_eq!(execution_dir_on_remote_machine(Path::new("/home/user/project"), &config), "~/mainframer/home/user/project/services/api");
+    }
+
+    #[test]
+    fn project_dir_on_remote_machine_custom_prefix() {
+        let config = config_from_str("remote_machine=machine\nremote_folder_prefix=/scratch", None).unwrap();
+        assert_eq!(project_dir_on_remote_machine(Path::new("/home/user/project"), &config), "/scratch/home/user/project");
+    }
+
+    #[test]
+    fn classify_remote_folder_prefix_forms() {
+        assert_eq!(classify_remote_folder_prefix("/scratch"), RemoteFolderPrefix::Absolute("/scratch"));
+        assert_eq!(classify_remote_folder_prefix(""), RemoteFolderPrefix::Absolute(""));
+        assert_eq!(classify_remote_folder_prefix("~/mainframer"), RemoteFolderPrefix::Tilde("~/mainframer"));
+        assert_eq!(classify_remote_folder_prefix("~builder/mainframer"), RemoteFolderPrefix::Tilde("~builder/mainframer"));
+        assert_eq!(classify_remote_folder_prefix("builds"), RemoteFolderPrefix::Relative("builds"));
+        assert_eq!(classify_remote_folder_prefix("./builds"), RemoteFolderPrefix::Relative("builds"));
+    }
+
+    #[test]
+    fn remote_paths_absolute_prefix() {
+        let config = config_from_str("remote_machine=machine\nremote_folder_prefix=/scratch", None).unwrap();
+        assert_eq!(remote_project_location(Path::new("/home/user/project"), &config), "machine:/scratch/home/user/project");
+        assert_eq!(execution_dir_on_remote_machine(Path::new("/home/user/project"), &config), "/scratch/home/user/project");
+    }
+
+    #[test]
+    fn remote_paths_root_prefix() {
+        let config = config_from_str("remote_machine=machine\nremote_folder_prefix=/", None).unwrap();
+        assert_eq!(remote_project_location(Path::new("/home/user/project"), &config), "machine:/home/user/project");
+        assert_eq!(execution_dir_on_remote_machine(Path::new("/home/user/project"), &config), "/home/user/project");
+    }
+
+    #[test]
+    fn remote_paths_tilde_prefix() {
+        let config = config_from_str("remote_machine=machine\nremote_folder_prefix=~/builds", None).unwrap();
+        assert_eq!(remote_project_location(Path::new("/home/user/project"), &config), "machine:~/builds/home/user/project");
+        assert_eq!(execution_dir_on_remote_machine(Path::new("/home/user/project"), &config), "~/builds/home/user/project");
+    }
+
+    #[test]
+    fn remote_paths_relative_prefix() {
+        let config = config_from_str("remote_machine=machine\nremote_folder_prefix=builds", None).unwrap();
+        assert_eq!(remote_project_location(Path::new("/home/user/project"), &config), "machine:~/builds/home/user/project");
+        assert_eq!(execution_dir_on_remote_machine(Path::new("/home/user/project"), &config), "~/builds/home/user/project");
+    }
+
+    #[test]
+    fn should_sync_remote_to_local_after_success() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert!(should_sync_remote_to_local(true, &config));
+    }
+
+    #[test]
+    fn should_sync_remote_to_local_after_failure() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert!(should_sync_remote_to_local(false, &config));
+    }
+
+    #[test]
+    fn should_sync_remote_to_local_after_failure_disabled() {
+        let config = config_from_str("remote_machine=machine\nsync_back_on_failure=false", None).unwrap();
+        assert!(!should_sync_remote_to_local(false, &config));
+        assert!(should_sync_remote_to_local(true, &config));
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()), vec![
+            "--archive",
+            "--delete",
+            "--stats",
+            "--rsync-path=mkdir -p ~/mainframer/project && rsync",
+            "--compress",
+            "--compress-level=1",
+            "--skip-compress=7z/aar/apk/avi/bz2/deb/gz/iso/jar/jpeg/jpg/mkv/mov/mp3/mp4/png/rar/rpm/tgz/webm/webp/xz/zip/zst",
+            "--exclude=.mainframer/",
+            "--rsh=ssh -o StrictHostKeyChecking=accept-new",
+            "./",
+            "machine:~/mainframer/project",
+        ]);
+    }
+
+    #[test]
+    fn local_to_remote_rsync_command_line() {
+        let config = config_from_str("remote_machine=build@machine\nremote_machine_port=2222\nlocal_compression_level=3\ncompression_skip_compress=jpg zip", None).unwrap();
+        let args = local_to_remote_rsync_args(Path::new("/my project"), &config, &no_ignore());
+        let command_line = format_command_line("rsync", &args);
+
+        assert_eq!(command_line, "$ rsync --archive --delete --stats '--rsync-path=mkdir -p ~/'\\''mainframer/my project'\\'' && rsync' --compress --compress-level=3 --skip-compress=jpg/zip --exclude=.mainframer/ '--rsh=ssh -p 2222 -o StrictHostKeyChecking=accept-new' ./ 'build@machine:~/mainframer/my project'");
+
+        // Copied command line is split by shell into the same args.
+        let output = ::std::process::Command::new("sh").arg("-c").arg(format!("printf '%s\\n' {}", command_line.trim_start_matches("$ rsync "))).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).lines().collect::<Vec<&str>>(), args);
+    }
+
+    #[test]
+    fn remote_to_local_rsync_args_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()), vec![
+            "--archive",
+            "--delete",
+            "--stats",
+            "--compress",
+            "--compress-level=1",
+            "--skip-compress=7z/aar/apk/avi/bz2/deb/gz/iso/jar/jpeg/jpg/mkv/mov/mp3/mp4/png/rar/rpm/tgz/webm/webp/xz/zip/zst",
+            "--exclude=.mainframer/",
+            "--rsh=ssh -o StrictHostKeyChecking=accept-new",
+            "machine:~/mainframer/project/",
+            "./",
+        ]);
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_rsyncd() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_user=build\nremote_machine_transport=rsyncd\nremote_machine_module=projects", None).unwrap();
+        assert_eq!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()), vec![
+            "--archive",
+            "--delete",
+            "--stats",
+            "--mkpath",
+            "--compress",
+            "--compress-level=1",
+            "--skip-compress=7z/aar/apk/avi/bz2/deb/gz/iso/jar/jpeg/jpg/mkv/mov/mp3/mp4/png/rar/rpm/tgz/webm/webp/xz/zip/zst",
+            "--exclude=.mainframer/",
+            "./",
+            "rsync://build@machine/projects/project",
+        ]);
+    }
+
+    #[test]
+    fn remote_to_local_rsync_args_result_paths() {
+        let config = config_from_str("remote_machine=machine\nexecution_result_paths=build/outputs target/release app/build/reports", None).unwrap();
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(args.contains(&String::from("--relative")));
+        assert!(args.contains(&String::from("--ignore-missing-args")));
+        assert_eq!(&args[args.len() - 4..], &[
+            String::from("machine:~/mainframer/project/./build/outputs"),
+            String::from("machine:~/mainframer/project/./target/release"),
+            String::from("machine:~/mainframer/project/./app/build/reports"),
+            String::from("./"),
+        ]);
+    }
+
+    #[test]
+    fn remote_to_local_rsync_args_result_paths_rsyncd() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_transport=rsyncd\nremote_machine_module=projects\nexecution_result_paths=build/outputs", None).unwrap();
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert_eq!(&args[args.len() - 2..], &[String::from("rsync://machine/projects/project/./build/outputs"), String::from("./")]);
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_ignore_result_paths() {
+        let config = config_from_str("remote_machine=machine\nexecution_result_paths=build/outputs", None).unwrap();
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(!args.contains(&String::from("--relative")));
+        assert_eq!(&args[args.len() - 2..], &[String::from("./"), String::from("machine:~/mainframer/project")]);
+    }
+
+    #[test]
+    fn remote_to_local_rsync_args_rsyncd() {
+        let config = config_from_str("remote_machine=::1\nremote_machine_transport=rsyncd\nremote_machine_module=projects", None).unwrap();
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(!args.iter().any(|arg| arg.starts_with("--rsh=")));
+        assert_eq!(&args[args.len() - 2..], &[String::from("rsync://[::1]/projects/project/"), String::from("./")]);
+    }
+
+    #[test]
+    fn incremental_rsync_args_lists_files() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let args = incremental_rsync_args(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()), Path::new("/tmp/mainframer.files"), &config);
+        assert!(!args.contains(&String::from("--delete")));
+        assert_eq!(&args[args.len() - 4..], &[
+            String::from("--files-from=/tmp/mainframer.files"),
+            String::from("--delete-missing-args"),
+            String::from("./"),
+            String::from("machine:~/mainframer/project"),
+        ]);
+    }
+
+    #[test]
+    fn incremental_rsync_args_without_delete() {
+        let config = config_from_str("remote_machine=machine\nsync_delete_extraneous=false", None).unwrap();
+        let args = incremental_rsync_args(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()), Path::new("/tmp/mainframer.files"), &config);
+        assert!(args.contains(&String::from("--files-from=/tmp/mainframer.files")));
+        assert!(!args.contains(&String::from("--delete-missing-args")));
+    }
+
+    #[test]
+    fn rsync_args_skip_compress() {
+        let config = config_from_str("remote_machine=machine\ncompression_skip_compress=jpg zip", None).unwrap();
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--skip-compress=jpg/zip")));
+        assert!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--skip-compress=jpg/zip")));
+    }
+
+    #[test]
+    fn rsync_args_skip_compress_without_compression() {
+        let config = config_from_str("remote_machine=machine\nlocal_compression_level=0\ncompression_skip_compress=jpg", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).iter().any(|arg| arg.starts_with("--skip-compress")));
+        assert!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--skip-compress=jpg")));
+    }
+
+    #[test]
+    fn rsync_args_max_file_size() {
+        let config = config_from_str("remote_machine=machine\nsync_max_file_size_upload=100M", None).unwrap();
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--max-size=104857600")));
+        assert!(!remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).iter().any(|arg| arg.starts_with("--max-size")));
+    }
+
+    #[test]
+    fn rsync_args_whole_file() {
+        for (content, expected_flag) in &[
+            ("remote_machine=machine\nsync_whole_file=true", Some("--whole-file")),
+            ("remote_machine=machine\nsync_whole_file=false", Some("--no-whole-file")),
+            ("remote_machine=machine", None),
+        ] {
+            let config = config_from_str(content, None).unwrap();
+
+            for args in &[
+                local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()),
+                remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()),
+            ] {
+                let flags: Vec<&str> = args.iter().map(String::as_str).filter(|arg| arg.ends_with("whole-file")).collect();
+                assert_eq!(flags, expected_flag.iter().cloned().collect::<Vec<&str>>());
             }
         }
     }
+
+    #[test]
+    fn rsync_args_compression_level() {
+        let config = config_from_str("remote_machine=machine\nlocal_compression_level=5\nremote_compression_level=0", None).unwrap();
+
+        let upload_args = local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(upload_args.contains(&String::from("--compress")));
+        assert!(upload_args.contains(&String::from("--compress-level=5")));
+
+        let download_args = remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(!download_args.iter().any(|arg| arg.starts_with("--compress")));
+    }
+
+    #[test]
+    fn rsync_args_bandwidth_limit() {
+        let config = config_from_str("remote_machine=machine\nbandwidth_limit_kbps=512", None).unwrap();
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--bwlimit=512")));
+        assert!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--bwlimit=512")));
+    }
+
+    #[test]
+    fn rsync_args_io_timeout() {
+        let config = config_from_str("remote_machine=machine\nsync_io_timeout_seconds=30", None).unwrap();
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--timeout=30")));
+        assert!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--timeout=30")));
+    }
+
+    #[test]
+    fn rsync_args_no_io_timeout() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).iter().any(|arg| arg.starts_with("--timeout")));
+    }
+
+    #[test]
+    fn rsync_args_no_bandwidth_limit() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).iter().any(|arg| arg.starts_with("--bwlimit")));
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_checksum() {
+        let config = config_from_str("remote_machine=machine\nsync_checksum=true", None).unwrap();
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--checksum")));
+        assert!(!remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--checksum")));
+    }
+
+    #[test]
+    fn rsync_args_partial_dir_default() {
+        let config = config_from_str("remote_machine=machine\nsync_partial_dir=true", None).unwrap();
+        let partial_args = vec![String::from("--partial"), String::from("--partial-dir=.mainframer-partial")];
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).windows(2).any(|args| args == partial_args.as_slice()));
+        assert!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).windows(2).any(|args| args == partial_args.as_slice()));
+    }
+
+    #[test]
+    fn rsync_args_partial_dir_path() {
+        let config = config_from_str("remote_machine=machine\nsync_partial_dir=/tmp/partial", None).unwrap();
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(args.windows(2).any(|args| args == [String::from("--partial"), String::from("--partial-dir=/tmp/partial")]));
+    }
+
+    #[test]
+    fn rsync_args_no_partial_dir() {
+        let config = config_from_str("remote_machine=machine\nsync_partial_dir=false", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--partial")));
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_force_full_upload() {
+        let mut config = config_from_str("remote_machine=machine", None).unwrap();
+        config.force_full_upload = true;
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--ignore-times")));
+        assert!(!remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--ignore-times")));
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_no_ignore_times() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--ignore-times")));
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_no_checksum() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--checksum")));
+    }
+
+    #[test]
+    fn rsync_args_preserve_flags_default() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(args.contains(&String::from("--archive")));
+        assert!(!args.iter().any(|arg| arg.starts_with("--no-")));
+    }
+
+    #[test]
+    fn rsync_args_preserve_flags_all_disabled() {
+        let config = config_from_str("remote_machine=machine\nsync_preserve_permissions=false\nsync_preserve_times=false\nsync_preserve_links=false", None).unwrap();
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert_eq!(&args[3..6], &[String::from("--no-perms"), String::from("--no-times"), String::from("--no-links")]);
+    }
+
+    #[test]
+    fn rsync_args_preserve_flags_only_times_disabled() {
+        let config = config_from_str("remote_machine=machine\nsync_preserve_times=false", None).unwrap();
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert!(args.contains(&String::from("--no-times")));
+        assert!(!args.contains(&String::from("--no-perms")));
+        assert!(!args.contains(&String::from("--no-links")));
+    }
+
+    #[test]
+    fn rsync_args_copy_links() {
+        let config = config_from_str("remote_machine=machine\nsync_copy_links=true", None).unwrap();
+        for args in &[local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()), remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore())] {
+            assert!(args.contains(&String::from("--copy-links")));
+            assert!(!args.contains(&String::from("--no-links")));
+        }
+    }
+
+    #[test]
+    fn rsync_args_built_in_patterns_go_before_common_patterns() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            common_patterns: vec![String::from("build/")],
+            ..no_ignore()
+        };
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &ignore);
+        assert_eq!(&args[6..8], &[String::from("--exclude=.mainframer/"), String::from("--exclude=build/")]);
+        assert!(!args.contains(&String::from("--exclude=.git/")));
+    }
+
+    #[test]
+    fn rsync_args_ignore_git() {
+        let config = config_from_str("remote_machine=machine\nsync_ignore_git=true", None).unwrap();
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore());
+        assert_eq!(&args[7..9], &[String::from("--exclude=.mainframer/"), String::from("--exclude=.git/")]);
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_without_delete() {
+        let config = config_from_str("remote_machine=machine\nsync_delete_extraneous=false", None).unwrap();
+        assert!(!local_to_remote_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--delete")));
+        assert!(remote_to_local_rsync_args(Path::new("/project"), &config, &no_ignore()).contains(&String::from("--delete")));
+    }
+
+    #[test]
+    fn rsync_args_common_patterns() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            common_patterns: vec![String::from(".idea"), String::from("build/")],
+            ..no_ignore()
+        };
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &ignore);
+        assert_eq!(&args[8..10], &[String::from("--exclude=.idea"), String::from("--exclude=build/")]);
+    }
+
+    #[test]
+    fn local_to_remote_rsync_args_use_only_local_ignore_file() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            local_ignore_file: Some(PathBuf::from("/project/.mainframer/localignore")),
+            remote_ignore_file: Some(PathBuf::from("/project/.mainframer/remoteignore")),
+            ..no_ignore()
+        };
+        let args = local_to_remote_rsync_args(Path::new("/project"), &config, &ignore);
+        assert!(args.contains(&String::from("--exclude-from=/project/.mainframer/localignore")));
+        assert!(!args.contains(&String::from("--exclude-from=/project/.mainframer/remoteignore")));
+    }
+
+    #[test]
+    fn remote_to_local_rsync_args_use_only_remote_ignore_file() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            local_ignore_file: Some(PathBuf::from("/project/.mainframer/localignore")),
+            remote_ignore_file: Some(PathBuf::from("/project/.mainframer/remoteignore")),
+            ..no_ignore()
+        };
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &ignore);
+        assert!(args.contains(&String::from("--exclude-from=/project/.mainframer/remoteignore")));
+        assert!(!args.contains(&String::from("--exclude-from=/project/.mainframer/localignore")));
+    }
+
+    #[test]
+    fn rsync_args_common_patterns_go_before_direction_ignore_file() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            common_patterns: vec![String::from("build/")],
+            remote_ignore_file: Some(PathBuf::from("/project/.mainframer/remoteignore")),
+            ..no_ignore()
+        };
+        let args = remote_to_local_rsync_args(Path::new("/project"), &config, &ignore);
+        let common_index = args.iter().position(|arg| arg == "--exclude=build/").unwrap();
+        let remote_index = args.iter().position(|arg| arg == "--exclude-from=/project/.mainframer/remoteignore").unwrap();
+        assert!(common_index < remote_index);
+    }
+
+    #[test]
+    fn parse_stats_rsync_output() {
+        let stdout = "
+Number of files: 5 (reg: 3, dir: 2)
+Number of created files: 0
+Number of deleted files: 0
+Number of regular files transferred: 2
+Total file size: 12,345 bytes
+Total transferred file size: 1,024 bytes
+Literal data: 1,024 bytes
+Matched data: 0 bytes
+File list size: 0
+Total bytes sent: 1,234,567
+Total bytes received: 89
+
+sent 1,234,567 bytes  received 89 bytes  2,469,312.00 bytes/sec
+total size is 12,345  speedup is 0.01
+";
+        assert_eq!(parse_stats(stdout), Some(TransferStats {
+            files_transferred: 2,
+            total_file_size: 12_345,
+            bytes_sent: 1_234_567,
+            bytes_received: 89,
+            speedup: String::from("0.01"),
+        }));
+    }
+
+    #[test]
+    fn parse_stats_old_rsync_output() {
+        let stdout = "
+Number of files: 5
+Number of files transferred: 1
+Total file size: 12345 bytes
+Total bytes sent: 1234567
+Total bytes received: 89
+
+sent 1234567 bytes  received 89 bytes  2469312.00 bytes/sec
+total size is 12345  speedup is 0.01
+";
+        assert_eq!(parse_stats(stdout), Some(TransferStats {
+            files_transferred: 1,
+            total_file_size: 12_345,
+            bytes_sent: 1_234_567,
+            bytes_received: 89,
+            speedup: String::from("0.01"),
+        }));
+    }
+
+    #[test]
+    fn parse_stats_no_stats() {
+        assert_eq!(parse_stats("something else"), None);
+    }
+
+    #[test]
+    fn format_transfer_stats_several_files() {
+        let stats = TransferStats {
+            files_transferred: 2,
+            total_file_size: 12_345,
+            bytes_sent: 1_234_567,
+            bytes_received: 89,
+            speedup: String::from("0.01"),
+        };
+        assert_eq!(format_transfer_stats(&stats), "Transferred 2 files of 12345 bytes: sent 1234567 bytes, received 89 bytes, speedup 0.01.");
+    }
+
+    #[test]
+    fn format_transfer_stats_single_file() {
+        let stats = TransferStats {
+            files_transferred: 1,
+            speedup: String::from("1.00"),
+            ..TransferStats::default()
+        };
+        assert_eq!(format_transfer_stats(&stats), "Transferred 1 file of 0 bytes: sent 0 bytes, received 0 bytes, speedup 1.00.");
+    }
+
+    #[test]
+    fn parse_stats_summary_line_only() {
+        let stats = parse_stats("sending incremental file list\n\nsent 1,234 bytes  received 56 bytes  2,580.00 bytes/sec\ntotal size is 10,000  speedup is 7.75\n");
+        assert_eq!(stats.map(|stats| (stats.bytes_sent, stats.bytes_received)), Some((1_234, 56)));
+    }
+
+    #[test]
+    fn parse_stats_totals_take_precedence_over_summary_line() {
+        let stats = parse_stats("Total bytes sent: 1,300\nTotal bytes received: 60\n\nsent 1,234 bytes  received 56 bytes  2,580.00 bytes/sec\n");
+        assert_eq!(stats.map(|stats| (stats.bytes_sent, stats.bytes_received)), Some((1_300, 60)));
+    }
+
+    #[test]
+    fn parse_summary_bytes_not_summary_line() {
+        assert_eq!(parse_summary_bytes("sent: nothing"), None);
+    }
+
+    #[test]
+    fn total_bytes_add() {
+        let mut total_bytes = TotalBytes::default();
+        total_bytes.add(&TransferStats { bytes_sent: 1000, bytes_received: 20, ..TransferStats::default() });
+        total_bytes.add(&TransferStats { bytes_sent: 30, bytes_received: 4000, ..TransferStats::default() });
+        assert_eq!(total_bytes, TotalBytes { up: 1030, down: 4020 });
+    }
+
+    #[test]
+    fn format_total_bytes_mebibytes() {
+        assert_eq!(format_total_bytes(&TotalBytes { up: 13_002_342, down: 3_250_586 }), "Transferred 12.4 MiB up, 3.1 MiB down");
+    }
+
+    #[test]
+    fn format_binary_size_magnitudes() {
+        assert_eq!(format_binary_size(0), "0 B");
+        assert_eq!(format_binary_size(1023), "1023 B");
+        assert_eq!(format_binary_size(1024), "1.0 KiB");
+        assert_eq!(format_binary_size(1536), "1.5 KiB");
+        assert_eq!(format_binary_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+        assert_eq!(format_binary_size(3 * 1024 * 1024 * 1024 * 1024 * 1024), "3072.0 TiB");
+    }
+
+    #[test]
+    fn classify_rsync_exit_codes() {
+        assert_eq!(classify_rsync_exit(255), SyncError::Connection);
+        assert_eq!(classify_rsync_exit(20), SyncError::Interrupted);
+        assert_eq!(classify_rsync_exit(23), SyncError::PartialTransfer);
+        assert_eq!(classify_rsync_exit(24), SyncError::PartialTransfer);
+        assert_eq!(classify_rsync_exit(30), SyncError::Timeout);
+        assert_eq!(classify_rsync_exit(35), SyncError::Timeout);
+        assert_eq!(classify_rsync_exit(12), SyncError::Other(12));
+    }
+
+    #[test]
+    fn connection_error_message_with_user_and_port() {
+        let config = config_from_str("remote_machine=machine\nremote_machine_user=test\nremote_machine_port=2222", None).unwrap();
+        assert_eq!(connection_error_message(&config), "Could not connect to test@machine:2222. Check the host is reachable and your SSH key is authorized");
+    }
+
+    #[test]
+    fn connection_error_message_host_only() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        assert_eq!(connection_error_message(&config), "Could not connect to machine. Check the host is reachable and your SSH key is authorized");
+    }
+
+    #[test]
+    fn is_host_key_error_changed_key() {
+        assert!(is_host_key_error("@@@@@@@@@@@\n@    WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!     @\n@@@@@@@@@@@\nHost key verification failed.\n"));
+    }
+
+    #[test]
+    fn is_host_key_error_other_connection_error() {
+        assert!(!is_host_key_error("ssh: connect to host machine port 22: Connection refused\n"));
+    }
+
+    #[test]
+    fn sync_local_to_remote_runs_rsync() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "Total bytes sent: 10,240\n");
+        let result = sync_local_to_remote(Path::new("/project"), &config, &no_ignore(), &runner, None);
+        assert_eq!(result, Ok(String::from("Total bytes sent: 10,240\n")));
+        assert_eq!(runner.invocations(), vec![(String::from("rsync"), vec![
+            String::from("--archive"),
+            String::from("--delete"),
+            String::from("--stats"),
+            String::from("--rsync-path=mkdir -p ~/mainframer/project && rsync"),
+            String::from("--compress"),
+            String::from("--compress-level=1"),
+            String::from("--skip-compress=7z/aar/apk/avi/bz2/deb/gz/iso/jar/jpeg/jpg/mkv/mov/mp3/mp4/png/rar/rpm/tgz/webm/webp/xz/zip/zst"),
+            String::from("--exclude=.mainframer/"),
+            String::from("--rsh=ssh -o StrictHostKeyChecking=accept-new"),
+            String::from("./"),
+            String::from("machine:~/mainframer/project"),
+        ])]);
+    }
+
+    #[test]
+    fn sync_remote_to_local_reports_progress() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "  1,024  10%  1.00MB/s  0:00:01\r  10,240 100%  1.00MB/s  0:00:01 (xfr#1, to-chk=0/1)\nTotal bytes sent: 10,240\n");
+        let percents = ::std::cell::RefCell::new(vec![]);
+        let result = sync_remote_to_local(Path::new("/project"), &config, &no_ignore(), &runner, Some(&|percent| percents.borrow_mut().push(percent)));
+        assert_eq!(result.ok().and_then(|stdout| parse_stats(&stdout)).map(|stats| stats.bytes_sent), Some(10_240));
+        assert_eq!(*percents.borrow(), vec![10, 100]);
+        assert_eq!(runner.invocations()[0].1.last(), Some(&String::from("--info=progress2")));
+    }
+
+    #[test]
+    fn upload_probe_runs_rsync_without_compression() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "");
+        assert_eq!(upload_probe(Path::new("/project"), &config, Path::new("/tmp/probe"), &runner), Ok(String::new()));
+        assert_eq!(runner.invocations(), vec![(String::from("rsync"), vec![
+            String::from("--whole-file"),
+            String::from("--ignore-times"),
+            String::from("--rsync-path=mkdir -p ~/mainframer/project/.mainframer && rsync"),
+            String::from("--rsh=ssh -o StrictHostKeyChecking=accept-new"),
+            String::from("/tmp/probe"),
+            String::from("machine:~/mainframer/project/.mainframer/"),
+        ])]);
+    }
+
+    #[test]
+    fn sync_local_to_remote_connection_error() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(255, "");
+        assert_eq!(sync_local_to_remote(Path::new("/project"), &config, &no_ignore(), &runner, None), Err(SyncFailure::Connection(connection_error_message(&config))));
+    }
+
+    fn project_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("mainframer_sync_{}_{}", name, ::std::process::id()));
+        fs::create_dir_all(dir.join("app/src")).unwrap();
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::create_dir_all(dir.join("build")).unwrap();
+        fs::create_dir_all(dir.join(".mainframer")).unwrap();
+        fs::write(dir.join("build.gradle"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn clamp_parallelism_default() {
+        assert_eq!(clamp_parallelism(None), 1);
+    }
+
+    #[test]
+    fn clamp_parallelism_bounds() {
+        assert_eq!(clamp_parallelism(Some(-3)), 1);
+        assert_eq!(clamp_parallelism(Some(0)), 1);
+        assert_eq!(clamp_parallelism(Some(4)), 4);
+        assert_eq!(clamp_parallelism(Some(16)), 16);
+        assert_eq!(clamp_parallelism(Some(1000)), 16);
+    }
+
+    #[test]
+    fn partition_upload_job_per_dir() {
+        assert_eq!(partition_upload(vec![String::from("app"), String::from("lib")]), vec![
+            UploadJob::Rest(vec![String::from("app"), String::from("lib")]),
+            UploadJob::Dir(String::from("app")),
+            UploadJob::Dir(String::from("lib")),
+        ]);
+    }
+
+    #[test]
+    fn partition_upload_no_dirs() {
+        assert_eq!(partition_upload(vec![]), vec![UploadJob::Rest(vec![])]);
+    }
+
+    #[test]
+    fn list_top_level_dirs_skips_files_and_excluded_dirs() {
+        let dir = project_dir("list");
+        let dirs = list_top_level_dirs(&dir, &[String::from(".mainframer/"), String::from("/build")], &[]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dirs.unwrap(), vec![String::from("app"), String::from("lib")]);
+    }
+
+    #[test]
+    fn list_top_level_dirs_skips_dirs_outside_of_include_patterns() {
+        let dir = project_dir("list_include");
+        let dirs = list_top_level_dirs(&dir, &[String::from(".mainframer/")], &[String::from("app/src/"), String::from("build.gradle")]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dirs.unwrap(), vec![String::from("app")]);
+    }
+
+    #[test]
+    fn upload_job_filters_dir() {
+        assert_eq!(upload_job_filters(&UploadJob::Dir(String::from("app"))), vec![String::from("--include=/app/"), String::from("--exclude=/*")]);
+    }
+
+    #[test]
+    fn upload_job_filters_rest() {
+        assert_eq!(
+            upload_job_filters(&UploadJob::Rest(vec![String::from("app"), String::from("lib")])),
+            vec![String::from("--exclude=/app/"), String::from("--exclude=/lib/")]
+        );
+    }
+
+    #[test]
+    fn upload_rsync_args_job_filters_go_between_excludes_and_includes() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            common_patterns: vec![String::from("build/")],
+            include_patterns: vec![String::from("app/src/")],
+            ..no_ignore()
+        };
+        let args = upload_rsync_args(Path::new("/project"), &config, &ignore, Some(&UploadJob::Dir(String::from("app"))));
+        let filters: Vec<&str> = args.iter().map(String::as_str).filter(|arg| arg.starts_with("--include=") || arg.starts_with("--exclude=")).collect();
+        assert_eq!(filters, vec![
+            "--exclude=.mainframer/",
+            "--exclude=build/",
+            "--include=/app/",
+            "--exclude=/*",
+            "--include=/app/",
+            "--include=/app/src/***",
+            "--exclude=*",
+        ]);
+    }
+
+    #[test]
+    fn include_filters_allowlist() {
+        let include_patterns = vec![
+            String::from("src/"),
+            String::from("Cargo.toml"),
+            String::from("/app/src/main/"),
+            String::from("app/build.gradle"),
+        ];
+        assert_eq!(include_filters(&include_patterns), vec![
+            String::from("--include=/src/***"),
+            String::from("--include=/Cargo.toml"),
+            String::from("--include=/Cargo.toml/***"),
+            String::from("--include=/app/"),
+            String::from("--include=/app/src/"),
+            String::from("--include=/app/src/main/***"),
+            String::from("--include=/app/build.gradle"),
+            String::from("--include=/app/build.gradle/***"),
+            String::from("--exclude=*"),
+        ]);
+    }
+
+    #[test]
+    fn include_filters_no_patterns() {
+        assert_eq!(include_filters(&[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rsync_args_include_patterns_only_upload() {
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let ignore = Ignore {
+            include_patterns: vec![String::from("src/")],
+            ..no_ignore()
+        };
+        assert!(local_to_remote_rsync_args(Path::new("/project"), &config, &ignore).contains(&String::from("--exclude=*")));
+        assert!(!remote_to_local_rsync_args(Path::new("/project"), &config, &ignore).contains(&String::from("--exclude=*")));
+    }
+
+    #[test]
+    fn sync_local_to_remote_parallel_runs_every_job() {
+        let dir = project_dir("parallel");
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let runner = RecordingRunner::new(0, "Total bytes sent: 1,024\nTotal bytes received: 10\n");
+        let result = sync_local_to_remote_parallel(&dir, &config, &no_ignore(), &[String::from(".mainframer/"), String::from("/build")], 2, &runner);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.map(|rsync_stdouts| sum_stats(&rsync_stdouts).map(|stats| stats.bytes_sent)), Ok(Some(3 * 1024)));
+
+        let mut filters: Vec<String> = runner.invocations().iter()
+            .map(|(_, args)| args.iter().filter(|arg| arg.starts_with("--include=/") || arg.starts_with("--exclude=/")).cloned().collect::<Vec<String>>().join(" "))
+            .collect();
+        filters.sort();
+        assert_eq!(filters, vec![
+            "--exclude=/app/ --exclude=/lib/",
+            "--include=/app/ --exclude=/*",
+            "--include=/lib/ --exclude=/*",
+        ]);
+    }
+
+    #[test]
+    fn sync_local_to_remote_parallel_aggregates_errors() {
+        let dir = project_dir("parallel_errors");
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let result = sync_local_to_remote_parallel(&dir, &config, &no_ignore(), &[String::from(".mainframer/"), String::from("/build")], 4, &RecordingRunner::new(255, ""));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let message = connection_error_message(&config);
+        assert_eq!(result, Err(SyncFailure::Connection(format!("Sync of top-level files: {0}\nSync of 'app/': {0}\nSync of 'lib/': {0}", message))));
+    }
+
+    // Panics on every rsync, as a bug in a worker would.
+    struct PanickingRunner;
+
+    impl CommandRunner for PanickingRunner {
+        fn run_with_timeout(&self, _program: &str, _args: &[String], _timeout: Option<::std::time::Duration>) -> io::Result<::std::process::ExitStatus> {
+            panic!("rsync worker bug");
+        }
+
+        fn output(&self, _program: &str, _args: &[String], _on_line: Option<&dyn Fn(&str)>) -> io::Result<::std::process::Output> {
+            panic!("rsync worker bug");
+        }
+    }
+
+    #[test]
+    fn sync_local_to_remote_parallel_reports_panicked_worker() {
+        let dir = project_dir("parallel_panic");
+        let config = config_from_str("remote_machine=machine", None).unwrap();
+        let result = sync_local_to_remote_parallel(&dir, &config, &no_ignore(), &[String::from(".mainframer/"), String::from("/build")], 2, &PanickingRunner);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let message = "upload thread panicked before it finished";
+        assert_eq!(result, Err(SyncFailure::Other(format!("Sync of top-level files: {0}\nSync of 'app/': {0}\nSync of 'lib/': {0}", message))));
+    }
+
+    #[test]
+    fn sum_stats_adds_up_jobs() {
+        let stdouts = vec![
+            String::from("Number of regular files transferred: 2\nTotal file size: 1,000 bytes\nTotal bytes sent: 100\nTotal bytes received: 10\n"),
+            String::from("Number of regular files transferred: 1\nTotal file size: 100 bytes\nTotal bytes sent: 290\nTotal bytes received: 40\n"),
+        ];
+        assert_eq!(sum_stats(&stdouts), Some(TransferStats {
+            files_transferred: 3,
+            total_file_size: 1_100,
+            bytes_sent: 390,
+            bytes_received: 50,
+            speedup: String::from("2.50"),
+        }));
+    }
+
+    #[test]
+    fn sum_stats_missing_stats() {
+        assert_eq!(sum_stats(&[String::from("Total bytes sent: 100\n"), String::from("something else")]), None);
+    }
 }
@@ -1,6 +1,39 @@
+use args::Phase;
 use std::fmt::Write;
 use std::time::Duration;
 
+// Durations of the run phases, each one is filled in as the phase completes.
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub struct RunTimings {
+    pub upload: Duration,
+    pub execution: Duration,
+    pub download: Duration,
+}
+
+impl RunTimings {
+    pub fn total(&self) -> Duration {
+        self.upload + self.execution + self.download
+    }
+}
+
+// Phases that were not run are left out.
+pub fn format_timings(timings: &RunTimings, phases: &[Phase]) -> String {
+    let mut text = String::new();
+
+    for &(phase, label, duration) in &[(Phase::Upload, "Upload", timings.upload), (Phase::Execute, "Execution", timings.execution), (Phase::Download, "Download", timings.download)] {
+        if phases.contains(&phase) {
+            let _ = write!(text, "{}: {}  ", label, format_seconds(duration));
+        }
+    }
+
+    let _ = write!(text, "Total: {}", format_seconds(timings.total()));
+    text
+}
+
+fn format_seconds(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
+
 pub fn format_duration(duration: Duration) -> String {
     let total_seconds = duration.as_secs();
 
@@ -143,4 +176,29 @@ mod tests {
     fn format_duration_1_hour_2_minutes_2_seconds() {
         assert_eq!(format_duration(Duration::from_secs(3722)), "1 hour 2 minutes 2 seconds");
     }
+
+    #[test]
+    fn format_timings_all_phases() {
+        let timings = RunTimings {
+            upload: Duration::from_millis(1200),
+            execution: Duration::from_millis(45300),
+            download: Duration::from_millis(800),
+        };
+        assert_eq!(format_timings(&timings, &[Phase::Upload, Phase::Execute, Phase::Download]), "Upload: 1.2s  Execution: 45.3s  Download: 0.8s  Total: 47.3s");
+    }
+
+    #[test]
+    fn format_timings_zero() {
+        assert_eq!(format_timings(&RunTimings::default(), &[Phase::Upload, Phase::Execute, Phase::Download]), "Upload: 0.0s  Execution: 0.0s  Download: 0.0s  Total: 0.0s");
+    }
+
+    #[test]
+    fn format_timings_without_execution() {
+        let timings = RunTimings {
+            upload: Duration::from_millis(1200),
+            execution: Duration::default(),
+            download: Duration::from_millis(800),
+        };
+        assert_eq!(format_timings(&timings, &[Phase::Upload, Phase::Download]), "Upload: 1.2s  Download: 0.8s  Total: 2.0s");
+    }
 }
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+const POLL_INTERVAL_MILLIS: u64 = 200;
+
+// Modification time and size of every file that would be synced, keyed by path relative to project dir.
+pub type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+// Fires once per burst of changes, when there were none during the whole debounce period.
+pub struct Debouncer {
+    debounce: Duration,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(debounce: Duration) -> Debouncer {
+        Debouncer {
+            debounce,
+            last_change: None,
+        }
+    }
+
+    pub fn on_change(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    pub fn should_run(&mut self, now: Instant) -> bool {
+        match self.last_change {
+            Some(last_change) if now.duration_since(last_change) >= self.debounce => {
+                self.last_change = None;
+                true
+            }
+            _ => false
+        }
+    }
+}
+
+// Polls project dir and runs "on_change" once changes settle, never returns so it's stopped with Ctrl-C.
+pub fn watch(dir: &Path, patterns: &[String], debounce: Duration, on_change: &mut dyn FnMut()) -> ! {
+    let mut last_snapshot = snapshot(dir, patterns);
+    let mut debouncer = Debouncer::new(debounce);
+
+    loop {
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MILLIS));
+
+        let current_snapshot = snapshot(dir, patterns);
+
+        if current_snapshot != last_snapshot {
+            debouncer.on_change(Instant::now());
+            last_snapshot = current_snapshot;
+        }
+
+        if debouncer.should_run(Instant::now()) {
+            on_change();
+            // Files pulled from remote machine must not trigger another run.
+            last_snapshot = snapshot(dir, patterns);
+        }
+    }
+}
+
+pub fn snapshot(dir: &Path, patterns: &[String]) -> Snapshot {
+    let mut snapshot = BTreeMap::new();
+    collect_files(dir, "", patterns, &mut snapshot);
+    snapshot
+}
+
+// Symlinks are not followed, same as rsync "--archive" copies them as links.
+fn collect_files(dir: &Path, relative_dir: &str, patterns: &[String], snapshot: &mut Snapshot) {
+    let entries = match fs::read_dir(dir) {
+        Err(_) => return,
+        Ok(value) => value
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        let relative_path = if relative_dir.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative_dir, name)
+        };
+
+        let metadata = match entry.metadata() {
+            Err(_) => continue,
+            Ok(value) => value
+        };
+
+        if is_ignored(&relative_path, metadata.is_dir(), patterns) {
+            continue;
+        }
+
+        if metadata.is_dir() {
+            collect_files(&entry.path(), &relative_path, patterns, snapshot);
+        } else {
+            snapshot.insert(PathBuf::from(relative_path), (metadata.modified().ok(), metadata.len()));
+        }
+    }
+}
+
+pub fn is_ignored(relative_path: &str, is_dir: bool, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| matches_pattern(pattern, relative_path, is_dir))
+}
+
+// Subset of rsync exclude rules: trailing "/" matches only dirs, leading "/" anchors to project dir,
+// pattern with "/" matches end of the path, otherwise the name. Include rules are not supported.
+fn matches_pattern(pattern: &str, relative_path: &str, is_dir: bool) -> bool {
+    let pattern = pattern.strip_prefix("- ").unwrap_or(pattern);
+
+    if pattern.starts_with("+ ") || pattern.starts_with('!') {
+        return false;
+    }
+
+    let pattern = match pattern.strip_suffix('/') {
+        Some(_) if !is_dir => return false,
+        Some(dir_pattern) => dir_pattern,
+        None => pattern
+    };
+
+    if let Some(anchored_pattern) = pattern.strip_prefix('/') {
+        return wildcard_match(anchored_pattern.as_bytes(), relative_path.as_bytes());
+    }
+
+    if pattern.contains('/') {
+        return Some(relative_path).into_iter()
+            .chain(relative_path.match_indices('/').map(|(index, _)| &relative_path[index + 1..]))
+            .any(|suffix| wildcard_match(pattern.as_bytes(), suffix.as_bytes()));
+    }
+
+    let name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+    wildcard_match(pattern.as_bytes(), name.as_bytes())
+}
+
+// "*" and "?" don't match "/", "**" matches anything.
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => (0..=text.len()).any(|index| wildcard_match(&pattern[2..], &text[index..])),
+        Some(b'*') => (0..=text.len())
+            .take_while(|&index| index == 0 || text[index - 1] != b'/')
+            .any(|index| wildcard_match(&pattern[1..], &text[index..])),
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && wildcard_match(&pattern[1..], &text[1..]),
+        Some(&c) => text.first() == Some(&c) && wildcard_match(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| String::from(*pattern)).collect()
+    }
+
+    #[test]
+    fn debouncer_waits_for_quiet_period() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+        debouncer.on_change(start);
+        assert!(!debouncer.should_run(start + Duration::from_millis(499)));
+        assert!(debouncer.should_run(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn debouncer_restarts_on_new_change() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+        debouncer.on_change(start);
+        debouncer.on_change(start + Duration::from_millis(400));
+        assert!(!debouncer.should_run(start + Duration::from_millis(600)));
+        assert!(debouncer.should_run(start + Duration::from_millis(900)));
+    }
+
+    #[test]
+    fn debouncer_runs_once_per_change() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+        debouncer.on_change(start);
+        assert!(debouncer.should_run(start + Duration::from_secs(1)));
+        assert!(!debouncer.should_run(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn debouncer_no_changes() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+        assert!(!debouncer.should_run(Instant::now() + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn is_ignored_name_pattern() {
+        assert!(is_ignored("src/Main.java.swp", false, &patterns(&["*.swp"])));
+        assert!(!is_ignored("src/Main.java", false, &patterns(&["*.swp"])));
+    }
+
+    #[test]
+    fn is_ignored_dir_pattern() {
+        assert!(is_ignored("app/build", true, &patterns(&["build/"])));
+        assert!(!is_ignored("app/build", false, &patterns(&["build/"])));
+    }
+
+    #[test]
+    fn is_ignored_anchored_pattern() {
+        assert!(is_ignored("build", true, &patterns(&["/build"])));
+        assert!(!is_ignored("app/build", true, &patterns(&["/build"])));
+    }
+
+    #[test]
+    fn is_ignored_pattern_with_slash() {
+        assert!(is_ignored("app/build/tmp", true, &patterns(&["build/tmp"])));
+        assert!(!is_ignored("app/mybuild/tmp", true, &patterns(&["build/tmp"])));
+    }
+
+    #[test]
+    fn is_ignored_double_star() {
+        assert!(is_ignored("app/src/gen/R.java", false, &patterns(&["/app/**/R.java"])));
+        assert!(!is_ignored("app/src/gen/R.java", false, &patterns(&["/app/*/R.java"])));
+    }
+
+    #[test]
+    fn is_ignored_skips_include_rules() {
+        assert!(!is_ignored("keep.swp", false, &patterns(&["+ *.swp", "!keep.swp"])));
+        assert!(is_ignored("drop.swp", false, &patterns(&["- *.swp"])));
+    }
+
+    #[test]
+    fn snapshot_skips_ignored_files() {
+        let dir = env::temp_dir().join(format!("mainframer_watch_snapshot_{}", process::id()));
+        fs::create_dir_all(dir.join("build")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("build/output"), "output").unwrap();
+        fs::write(dir.join("src/Main.java"), "class Main {}").unwrap();
+        fs::write(dir.join("src/.Main.java.swp"), "").unwrap();
+
+        let snapshot = snapshot(&dir, &patterns(&["build/", "*.swp"]));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), vec![&PathBuf::from("src/Main.java")]);
+    }
+}
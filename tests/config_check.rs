@@ -0,0 +1,41 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::process::Command;
+use std::process::Output;
+
+// Global config of the user running tests is left out with empty "XDG_CONFIG_HOME".
+fn config_check(project_dir: &Path, config_content: &str) -> Output {
+    fs::create_dir_all(project_dir.join(".mainframer")).unwrap();
+    fs::write(project_dir.join(".mainframer/config"), config_content).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_mainframer"))
+        .arg("--config-check")
+        .current_dir(project_dir)
+        .env("XDG_CONFIG_HOME", project_dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn config_check_valid() {
+    let dir = env::temp_dir().join(format!("mainframer_config_check_valid_{}", process::id()));
+    let output = config_check(&dir, "remote_machine=machine\nremote_machine_port=2222");
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Config is valid."));
+}
+
+#[test]
+fn config_check_invalid() {
+    let dir = env::temp_dir().join(format!("mainframer_config_check_invalid_{}", process::id()));
+    let output = config_check(&dir, "remote_machine=machine\nremote_machine_port=0\nsync_checksum=on");
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(78));
+    assert!(stderr.contains("'remote_machine_port' must be a number from 1 to 65535, found '0'."), "{}", stderr);
+    assert!(stderr.contains("'sync_checksum' must be either 'true' or 'false', found 'on'."), "{}", stderr);
+}
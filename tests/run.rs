@@ -0,0 +1,64 @@
+extern crate mainframer;
+
+use mainframer::config::Config;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process;
+
+// Remote machine is a dir on local one: rsync copies between dirs and ssh runs the command with local shell.
+const FAKE_RSYNC: &str = "#!/bin/sh
+for arg; do source=$destination; destination=$arg; done
+source=${source#machine:}
+destination=${destination#machine:}
+mkdir -p \"$destination\" && cp -R \"$source.\" \"$destination\"
+";
+
+const FAKE_SSH: &str = "#!/bin/sh
+for arg; do command=$arg; done
+exec sh -c \"$command\"
+";
+
+fn write_executable(path: &Path, content: &str) {
+    fs::write(path, content).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn run_syncs_executes_and_syncs_back() {
+    let dir = env::temp_dir().join(format!("mainframer_run_{}", process::id()));
+    let local_dir = dir.join("local");
+    let remote_dir = dir.join("remote");
+    let bin_dir = dir.join("bin");
+
+    fs::create_dir_all(&local_dir).unwrap();
+    fs::create_dir_all(&bin_dir).unwrap();
+    write_executable(&bin_dir.join("rsync"), FAKE_RSYNC);
+    write_executable(&bin_dir.join("ssh"), FAKE_SSH);
+    fs::write(local_dir.join("input.txt"), "mainframer").unwrap();
+
+    let config_content = format!(
+        "remote_machine=machine\nremote_folder_prefix={}\nremote_machine_multiplex=false\nsync_rsync_path={}\nremote_machine_ssh_path={}",
+        remote_dir.to_string_lossy(),
+        bin_dir.join("rsync").to_string_lossy(),
+        bin_dir.join("ssh").to_string_lossy()
+    );
+    let config = || Config::from_reader(config_content.as_bytes(), Path::new("<test>"), None, None, None).unwrap();
+
+    env::set_current_dir(&local_dir).unwrap();
+    let local_dir = env::current_dir().unwrap();
+
+    let succeeded = mainframer::run(config(), "tr a-z A-Z < input.txt > output.txt");
+    let output = fs::read_to_string(local_dir.join("output.txt"));
+    let uploaded = remote_dir.join(local_dir.strip_prefix("/").unwrap()).join("input.txt").exists();
+    let failed = mainframer::run(config(), "exit 3");
+
+    env::set_current_dir(env::temp_dir()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(succeeded.map(|result| result.exit_code), Ok(0));
+    assert_eq!(output.unwrap(), "MAINFRAMER");
+    assert!(uploaded);
+    assert_eq!(failed.map(|result| result.exit_code), Ok(3));
+}